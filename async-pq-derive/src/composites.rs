@@ -0,0 +1,130 @@
+use super::pg_name;
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Error, FieldsNamed, Ident, Type};
+
+pub(crate) struct Field {
+    ident: Ident,
+    name: String,
+    ty: Type,
+}
+
+pub(crate) fn fields(fields: &FieldsNamed) -> Result<Vec<Field>, Error> {
+    fields
+        .named
+        .iter()
+        .map(|f| {
+            let ident = f.ident.clone().unwrap();
+            Ok(Field {
+                name: pg_name(&f.attrs, &ident.to_string())?,
+                ident,
+                ty: f.ty.clone(),
+            })
+        })
+        .collect()
+}
+
+// attributes are written in the order of the type, binary format only
+pub(crate) fn to_sql(ident: &Ident, name: &str, fields: &[Field]) -> TokenStream {
+    let idents: Vec<_> = fields.iter().map(|f| &f.ident).collect();
+    let names: Vec<_> = fields.iter().map(|f| &f.name).collect();
+    let count = fields.len();
+    quote! {
+        impl ::async_pq::types::ToSql for #ident {
+            fn accepts(&self, ty: &::async_pq::types::Type) -> bool {
+                match ::async_pq::types::__private::composite_fields(ty, #name) {
+                    ::std::option::Option::Some(fields) => {
+                        fields.len() == #count
+                            && fields.iter().all(|f| match f.name() {
+                                #(#names => ::async_pq::types::ToSql::accepts(&self.#idents, f.type_()),)*
+                                _ => false,
+                            })
+                    }
+                    ::std::option::Option::None => false,
+                }
+            }
+
+            fn to_sql(
+                &self,
+                ty: &::async_pq::types::Type,
+                out: &mut ::std::vec::Vec<u8>,
+            ) -> ::std::result::Result<::async_pq::types::IsNull, ::async_pq::types::TypeError> {
+                let fields = match ::async_pq::types::__private::composite_fields(ty, #name) {
+                    ::std::option::Option::Some(fields) => fields,
+                    ::std::option::Option::None => {
+                        return ::std::result::Result::Err(::async_pq::types::TypeError::WrongType {
+                            rust: ::std::any::type_name::<Self>(),
+                            postgres: ty.clone(),
+                        })
+                    }
+                };
+                ::async_pq::types::__private::write_count(fields.len(), out);
+                for f in fields {
+                    let value: &dyn ::async_pq::types::ToSql = match f.name() {
+                        #(#names => &self.#idents,)*
+                        _ => return ::std::result::Result::Err(::async_pq::types::__private::missing_field(f.name())),
+                    };
+                    ::async_pq::types::__private::write_field(f.type_(), value, out)?;
+                }
+                ::std::result::Result::Ok(::async_pq::types::IsNull::No)
+            }
+        }
+    }
+}
+
+pub(crate) fn from_sql(ident: &Ident, name: &str, fields: &[Field]) -> TokenStream {
+    let idents: Vec<_> = fields.iter().map(|f| &f.ident).collect();
+    let names: Vec<_> = fields.iter().map(|f| &f.name).collect();
+    let types: Vec<_> = fields.iter().map(|f| &f.ty).collect();
+    let count = fields.len();
+    quote! {
+        impl<'a> ::async_pq::types::FromSql<'a> for #ident {
+            fn accepts(ty: &::async_pq::types::Type) -> bool {
+                match ::async_pq::types::__private::composite_fields(ty, #name) {
+                    ::std::option::Option::Some(fields) => {
+                        fields.len() == #count
+                            && fields.iter().all(|f| match f.name() {
+                                #(#names => <#types as ::async_pq::types::FromSql>::accepts(f.type_()),)*
+                                _ => false,
+                            })
+                    }
+                    ::std::option::Option::None => false,
+                }
+            }
+
+            fn from_sql(
+                ty: &::async_pq::types::Type,
+                raw: &'a [u8],
+            ) -> ::std::result::Result<Self, ::async_pq::types::TypeError> {
+                let fields = match ::async_pq::types::__private::composite_fields(ty, #name) {
+                    ::std::option::Option::Some(fields) => fields,
+                    ::std::option::Option::None => {
+                        return ::std::result::Result::Err(::async_pq::types::TypeError::WrongType {
+                            rust: ::std::any::type_name::<Self>(),
+                            postgres: ty.clone(),
+                        })
+                    }
+                };
+                let mut reader = ::async_pq::types::__private::CompositeReader::new(raw, fields)?;
+                #(let mut #idents: ::std::option::Option<#types> = ::std::option::Option::None;)*
+                for f in fields {
+                    let value = reader.next(f)?;
+                    match f.name() {
+                        #(#names => #idents = ::std::option::Option::Some(
+                            ::async_pq::types::__private::decode(f.type_(), true, value)?,
+                        ),)*
+                        _ => {}
+                    }
+                }
+                ::std::result::Result::Ok(#ident {
+                    #(#idents: #idents.ok_or_else(|| ::async_pq::types::__private::missing_field(#names))?,)*
+                })
+            }
+
+            // text format of composites is not parsed
+            fn accepts_text(_ty: &::async_pq::types::Type) -> bool {
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use super::pg_name;
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{DataEnum, Error, Fields, Ident};
+
+pub(crate) struct Variant {
+    ident: Ident,
+    label: String,
+}
+
+pub(crate) fn variants(data: &DataEnum) -> Result<Vec<Variant>, Error> {
+    data.variants
+        .iter()
+        .map(|v| match v.fields {
+            Fields::Unit => Ok(Variant {
+                ident: v.ident.clone(),
+                label: pg_name(&v.attrs, &v.ident.to_string())?,
+            }),
+            _ => Err(Error::new_spanned(v, "only unit variants are supported")),
+        })
+        .collect()
+}
+
+// label is sent as is in both binary and text format
+pub(crate) fn to_sql(ident: &Ident, name: &str, variants: &[Variant]) -> TokenStream {
+    let idents: Vec<_> = variants.iter().map(|v| &v.ident).collect();
+    let labels: Vec<_> = variants.iter().map(|v| &v.label).collect();
+    quote! {
+        impl ::async_pq::types::ToSql for #ident {
+            fn accepts(&self, ty: &::async_pq::types::Type) -> bool {
+                ::async_pq::types::__private::enum_accepts(ty, #name, &[#(#labels),*])
+            }
+
+            fn to_sql(
+                &self,
+                _ty: &::async_pq::types::Type,
+                out: &mut ::std::vec::Vec<u8>,
+            ) -> ::std::result::Result<::async_pq::types::IsNull, ::async_pq::types::TypeError> {
+                let label: &str = match self {
+                    #(#ident::#idents => #labels,)*
+                };
+                out.extend(label.as_bytes());
+                ::std::result::Result::Ok(::async_pq::types::IsNull::No)
+            }
+
+            fn accepts_text(&self, ty: &::async_pq::types::Type) -> bool {
+                ::async_pq::types::ToSql::accepts(self, ty)
+            }
+
+            fn to_sql_text(
+                &self,
+                ty: &::async_pq::types::Type,
+                out: &mut ::std::vec::Vec<u8>,
+            ) -> ::std::result::Result<::async_pq::types::IsNull, ::async_pq::types::TypeError> {
+                ::async_pq::types::ToSql::to_sql(self, ty, out)
+            }
+        }
+    }
+}
+
+pub(crate) fn from_sql(ident: &Ident, name: &str, variants: &[Variant]) -> TokenStream {
+    let idents: Vec<_> = variants.iter().map(|v| &v.ident).collect();
+    let labels: Vec<_> = variants.iter().map(|v| &v.label).collect();
+    let bytes: Vec<_> = variants
+        .iter()
+        .map(|v| syn::LitByteStr::new(v.label.as_bytes(), v.ident.span()))
+        .collect();
+    quote! {
+        impl<'a> ::async_pq::types::FromSql<'a> for #ident {
+            fn accepts(ty: &::async_pq::types::Type) -> bool {
+                ::async_pq::types::__private::enum_accepts(ty, #name, &[#(#labels),*])
+            }
+
+            fn from_sql(
+                _ty: &::async_pq::types::Type,
+                raw: &'a [u8],
+            ) -> ::std::result::Result<Self, ::async_pq::types::TypeError> {
+                match raw {
+                    #(#bytes => ::std::result::Result::Ok(#ident::#idents),)*
+                    _ => ::std::result::Result::Err(::async_pq::types::TypeError::InvalidValue(
+                        ::std::format!("unknown label {:?}", ::std::string::String::from_utf8_lossy(raw)),
+                    )),
+                }
+            }
+
+            fn from_sql_text(
+                ty: &::async_pq::types::Type,
+                raw: &'a [u8],
+            ) -> ::std::result::Result<Self, ::async_pq::types::TypeError> {
+                <Self as ::async_pq::types::FromSql>::from_sql(ty, raw)
+            }
+        }
+    }
+}
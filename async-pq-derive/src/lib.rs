@@ -0,0 +1,103 @@
+// derive(ToSql, FromSql) for user-defined postgres types:
+//
+// - enum of unit variants maps to enum type, by label
+// - struct of named fields maps to composite type, by attribute name
+//
+// type, label and attribute names default to snake case of the rust
+// name, #[postgres(name = "...")] overrides any of them. the type is
+// matched by name against the description the connection looks up in
+// pg_type, so the oid is never needed at compile time
+extern crate proc_macro;
+
+mod composites;
+mod enums;
+
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Error, Fields, LitStr};
+
+#[proc_macro_derive(ToSql, attributes(postgres))]
+pub fn derive_to_sql(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input, true).unwrap_or_else(|e| e.to_compile_error()).into()
+}
+
+#[proc_macro_derive(FromSql, attributes(postgres))]
+pub fn derive_from_sql(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input, false).unwrap_or_else(|e| e.to_compile_error()).into()
+}
+
+fn expand(input: &DeriveInput, to_sql: bool) -> Result<proc_macro2::TokenStream, Error> {
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(&input.generics, "generic types are not supported"));
+    }
+    let name = pg_name(&input.attrs, &input.ident.to_string())?;
+    match &input.data {
+        Data::Enum(data) => {
+            let variants = enums::variants(data)?;
+            Ok(if to_sql {
+                enums::to_sql(&input.ident, &name, &variants)
+            } else {
+                enums::from_sql(&input.ident, &name, &variants)
+            })
+        }
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => {
+                let fields = composites::fields(fields)?;
+                Ok(if to_sql {
+                    composites::to_sql(&input.ident, &name, &fields)
+                } else {
+                    composites::from_sql(&input.ident, &name, &fields)
+                })
+            }
+            _ => Err(Error::new_spanned(&input.ident, "only structs with named fields are supported")),
+        },
+        Data::Union(_) => Err(Error::new_spanned(&input.ident, "unions are not supported")),
+    }
+}
+
+// value of #[postgres(name = "...")], or snake case of the rust name
+fn pg_name(attrs: &[Attribute], rust_name: &str) -> Result<String, Error> {
+    let mut name = None;
+    for attr in attrs.iter().filter(|a| a.path().is_ident("postgres")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                name = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else {
+                Err(meta.error("unknown postgres attribute"))
+            }
+        })?;
+    }
+    Ok(name.unwrap_or_else(|| snake_case(rust_name.trim_start_matches("r#"))))
+}
+
+fn snake_case(s: &str) -> String {
+    let chars: Vec<char> = s.chars().collect();
+    let mut out = String::new();
+    for (i, c) in chars.iter().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|n| n.is_lowercase());
+            // word boundary: fooBar, Foo2Bar, HTTPServer
+            if prev.is_lowercase() || prev.is_ascii_digit() || (prev.is_uppercase() && next_lower) {
+                out.push('_');
+            }
+        }
+        out.extend(c.to_lowercase());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snake_case() {
+        assert_eq!("order_status", snake_case("OrderStatus"));
+        assert_eq!("http_server", snake_case("HTTPServer"));
+        assert_eq!("point2_d", snake_case("Point2D"));
+        assert_eq!("shipped", snake_case("shipped"));
+    }
+}
@@ -27,6 +27,12 @@ fn main() {
 
 async fn query_something(p: async_pq::Pool, i: usize) -> Result<(), Box<dyn std::error::Error>> {
     debug!("Futures: {} getting connection", i);
-    let mut conn = p.get_conn().await?;
+    let conn = p.get_conn().await?;
+    info!(
+        "Futures: {} got connection, backend pid: {:?}, server version: {:?}",
+        i,
+        conn.backend_pid(),
+        conn.server_version()
+    );
     Ok(())
 }
@@ -0,0 +1,408 @@
+use super::connection::{Connection, ConnectionError};
+use super::copy::{self, CopyIn};
+use super::protocols::backend::{self, BackendMessage, FieldDescription};
+use super::protocols::deserializer::MessageDeserializerError;
+use super::row::Row;
+use super::to_params::to_params;
+use super::types::{IsNull, ToSql, Type, TypeError};
+use futures::stream::{self, Stream, StreamExt};
+use serde::Serialize;
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+// binary COPY format: signature, i32 flags, i32 length of header extension
+// and the extension itself, then every tuple as i16 number of fields and
+// each field prefixed by its i32 length (-1 for NULL), ended by -1
+const SIGNATURE: &[u8] = b"PGCOPY\n\xff\r\n\0";
+const HEADER_LEN: usize = 19;
+// tuples start with their oid, not supported
+const WITH_OIDS: i32 = 1 << 16;
+
+// tuples of COPY ... FROM STDIN (FORMAT binary) encoded from values of
+// the column types. unlike query parameters every value has to accept
+// binary format
+pub struct BinaryCopyEncoder {
+    types: Vec<Type>,
+    buf: Vec<u8>,
+}
+
+impl BinaryCopyEncoder {
+    pub fn new(types: &[Type]) -> BinaryCopyEncoder {
+        let mut buf = Vec::with_capacity(HEADER_LEN);
+        buf.extend(SIGNATURE);
+        buf.extend(&0i32.to_be_bytes());
+        buf.extend(&0i32.to_be_bytes());
+        BinaryCopyEncoder {
+            types: types.to_vec(),
+            buf,
+        }
+    }
+
+    // value of every column in order
+    pub fn write(&mut self, values: &[&(dyn ToSql + Sync)]) -> Result<(), ConnectionError> {
+        if values.len() != self.types.len() {
+            return Err(ConnectionError::ParameterCount {
+                expected: self.types.len(),
+                actual: values.len(),
+            });
+        }
+        let start = self.buf.len();
+        let r = self.encode(values);
+        // nothing of failed tuple is kept
+        if r.is_err() {
+            self.buf.truncate(start);
+        }
+        r.map_err(ConnectionError::Type)
+    }
+
+    // tuple holding value of every column in order
+    pub fn write_row<R: CopyRow>(&mut self, row: &R) -> Result<(), ConnectionError> {
+        self.write(&row.values())
+    }
+
+    // fields of struct (or map) in order, see to_params
+    pub fn write_struct<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ConnectionError> {
+        let fields = to_params(value).map_err(ConnectionError::ToParams)?;
+        let values: Vec<&(dyn ToSql + Sync)> = fields.iter().map(|(_, p)| p as &(dyn ToSql + Sync)).collect();
+        self.write(&values)
+    }
+
+    fn encode(&mut self, values: &[&(dyn ToSql + Sync)]) -> Result<(), TypeError> {
+        self.buf.extend(&(values.len() as i16).to_be_bytes());
+        for (v, ty) in values.iter().zip(&self.types) {
+            if !v.accepts(ty) {
+                return Err(TypeError::WrongType {
+                    rust: v.type_name(),
+                    postgres: ty.clone(),
+                });
+            }
+            let start = self.buf.len();
+            self.buf.extend(&0i32.to_be_bytes());
+            let len = match v.to_sql(ty, &mut self.buf)? {
+                IsNull::Yes => -1,
+                IsNull::No => i32::try_from(self.buf.len() - start - 4)
+                    .map_err(|_| TypeError::InvalidValue(String::from("value too large")))?,
+            };
+            self.buf[start..start + 4].copy_from_slice(&len.to_be_bytes());
+        }
+        Ok(())
+    }
+
+    // data encoded since last taken, header included the first time
+    pub fn take(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.buf)
+    }
+
+    // the rest of the data, with the trailer
+    pub fn finish(mut self) -> Vec<u8> {
+        self.buf.extend(&(-1i16).to_be_bytes());
+        self.buf
+    }
+}
+
+// values of single row, implemented for tuples
+pub trait CopyRow {
+    fn values(&self) -> Vec<&(dyn ToSql + Sync)>;
+}
+
+macro_rules! copy_row {
+    ($($t:ident $idx:tt),*) => {
+        impl<$($t: ToSql + Sync),*> CopyRow for ($($t,)*) {
+            fn values(&self) -> Vec<&(dyn ToSql + Sync)> {
+                vec![$(&self.$idx),*]
+            }
+        }
+    };
+}
+
+copy_row!(A 0);
+copy_row!(A 0, B 1);
+copy_row!(A 0, B 1, C 2);
+copy_row!(A 0, B 1, C 2, D 3);
+copy_row!(A 0, B 1, C 2, D 3, E 4);
+copy_row!(A 0, B 1, C 2, D 3, E 4, F 5);
+copy_row!(A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+copy_row!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+copy_row!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8);
+copy_row!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9);
+copy_row!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10);
+copy_row!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10, L 11);
+
+// CopyIn of binary format, rows encoded as they are written
+pub struct BinaryCopyWriter<'a> {
+    copy: CopyIn<'a>,
+    encoder: BinaryCopyEncoder,
+}
+
+impl<'a> BinaryCopyWriter<'a> {
+    pub fn new(copy: CopyIn<'a>, types: &[Type]) -> BinaryCopyWriter<'a> {
+        BinaryCopyWriter {
+            copy,
+            encoder: BinaryCopyEncoder::new(types),
+        }
+    }
+
+    pub async fn write(&mut self, values: &[&(dyn ToSql + Sync)]) -> Result<(), ConnectionError> {
+        self.encoder.write(values)?;
+        self.send().await
+    }
+
+    pub async fn write_row<R: CopyRow>(&mut self, row: &R) -> Result<(), ConnectionError> {
+        self.encoder.write_row(row)?;
+        self.send().await
+    }
+
+    pub async fn write_struct<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ConnectionError> {
+        self.encoder.write_struct(value)?;
+        self.send().await
+    }
+
+    async fn send(&mut self) -> Result<(), ConnectionError> {
+        let data = self.encoder.take();
+        self.copy.send(&data).await
+    }
+
+    // number of rows copied, see CopyIn::finish
+    pub async fn finish(mut self) -> Result<u64, ConnectionError> {
+        self.copy.send(&self.encoder.finish()).await?;
+        self.copy.finish().await
+    }
+}
+
+// tuples of COPY ... TO STDOUT (FORMAT binary) read as rows of the column
+// types, fed with the data as it arrives. columns have no name unless
+// given by with_names
+pub struct BinaryCopyDecoder {
+    columns: Arc<Vec<FieldDescription>>,
+    types: Arc<Vec<Type>>,
+    buf: Vec<u8>,
+    // start of unread data in buf
+    pos: usize,
+    header: bool,
+    done: bool,
+}
+
+impl BinaryCopyDecoder {
+    pub fn new(types: &[Type]) -> BinaryCopyDecoder {
+        let columns = types.iter().map(|ty| column("", ty, 1)).collect();
+        BinaryCopyDecoder {
+            columns: Arc::new(columns),
+            types: Arc::new(types.to_vec()),
+            buf: vec![],
+            pos: 0,
+            header: false,
+            done: false,
+        }
+    }
+
+    // names of the columns in order, e.g. to deserialize rows into structs
+    pub fn with_names(mut self, names: &[&str]) -> BinaryCopyDecoder {
+        let columns = Arc::make_mut(&mut self.columns);
+        for (c, name) in columns.iter_mut().zip(names) {
+            c.name = name.to_string();
+        }
+        self
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        if self.pos > 0 && self.pos >= self.buf.len() / 2 {
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+        }
+        self.buf.extend_from_slice(data);
+    }
+
+    // whether the trailer was read
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    // next row, none until all of it is pushed or once the trailer is read
+    pub fn read_row(&mut self) -> Result<Option<Row>, ConnectionError> {
+        if !self.header && !self.read_header()? {
+            return Ok(None);
+        }
+        if self.done {
+            return Ok(None);
+        }
+        let data = &self.buf[self.pos..];
+        let count = match data.get(..2) {
+            Some(b) => i16::from_be_bytes([b[0], b[1]]),
+            None => return Ok(None),
+        };
+        if count == -1 {
+            self.done = true;
+            self.pos += 2;
+            return Ok(None);
+        }
+        if count as usize != self.types.len() {
+            return Err(invalid(format!(
+                "tuple of {} fields, {} columns expected",
+                count,
+                self.types.len()
+            )));
+        }
+        let mut end = 2;
+        for _ in 0..count {
+            let len = match data.get(end..end + 4) {
+                Some(b) => i32::from_be_bytes([b[0], b[1], b[2], b[3]]),
+                None => return Ok(None),
+            };
+            end += 4 + len.max(0) as usize;
+        }
+        if end > data.len() {
+            return Ok(None);
+        }
+        let body = data[..end].to_vec();
+        self.pos += end;
+        match backend::parse(b'D', body).map_err(ConnectionError::Deserialize)? {
+            BackendMessage::DataRow(data) => Ok(Some(Row::new(self.columns.clone(), self.types.clone(), data))),
+            _ => Err(ConnectionError::Unknown),
+        }
+    }
+
+    fn read_header(&mut self) -> Result<bool, ConnectionError> {
+        let data = &self.buf[self.pos..];
+        if data.len() < HEADER_LEN {
+            return Ok(false);
+        }
+        if &data[..SIGNATURE.len()] != SIGNATURE {
+            return Err(invalid(String::from("missing binary copy signature")));
+        }
+        let flags = i32::from_be_bytes([data[11], data[12], data[13], data[14]]);
+        if flags & WITH_OIDS != 0 {
+            return Err(invalid(String::from("binary copy with oids is not supported")));
+        }
+        let extension = u32::from_be_bytes([data[15], data[16], data[17], data[18]]) as usize;
+        if data.len() < HEADER_LEN + extension {
+            return Ok(false);
+        }
+        self.pos += HEADER_LEN + extension;
+        self.header = true;
+        Ok(true)
+    }
+}
+
+// description of copied column, which has no table
+pub(crate) fn column(name: &str, ty: &Type, format: i16) -> FieldDescription {
+    FieldDescription {
+        name: name.to_string(),
+        table_oid: 0,
+        column_id: 0,
+        type_oid: ty.oid(),
+        type_size: -1,
+        type_modifier: -1,
+        format,
+    }
+}
+
+pub(crate) fn invalid(msg: String) -> ConnectionError {
+    ConnectionError::Deserialize(MessageDeserializerError::Custom(msg))
+}
+
+struct CopyOut<S> {
+    data: S,
+    decoder: BinaryCopyDecoder,
+    failed: bool,
+}
+
+// rows of COPY ... TO STDOUT (FORMAT binary) decoded as they arrive
+pub(crate) fn copy_out<'a>(
+    conn: &'a mut Connection,
+    sql: &'a str,
+    decoder: BinaryCopyDecoder,
+) -> impl Stream<Item = Result<Row, ConnectionError>> + 'a {
+    let state = CopyOut {
+        data: Box::pin(copy::copy_out(conn, sql)),
+        decoder,
+        failed: false,
+    };
+    stream::unfold(state, |mut state| async move {
+        if state.failed {
+            return None;
+        }
+        loop {
+            let r = match state.decoder.read_row() {
+                Ok(Some(row)) => return Some((Ok(row), state)),
+                Ok(None) => match state.data.next().await {
+                    Some(Ok(data)) => {
+                        state.decoder.push(&data);
+                        continue;
+                    }
+                    Some(Err(e)) => Err(e),
+                    // read up to the end of the copy even after the trailer,
+                    // leaving the connection ready
+                    None if state.decoder.is_done() => return None,
+                    None => Err(invalid(String::from("binary copy ended without trailer"))),
+                },
+                Err(e) => Err(e),
+            };
+            state.failed = true;
+            return Some((r, state));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        let types = [Type::INT8, Type::TEXT, Type::BOOL];
+        let mut encoder = BinaryCopyEncoder::new(&types);
+        encoder.write_row(&(7i64, "ab", None::<bool>)).unwrap();
+        let mut data = encoder.take();
+        assert_eq!(SIGNATURE, &data[..11]);
+        assert_eq!(HEADER_LEN + 2 + 12 + 6 + 4, data.len());
+
+        // failed tuple leaves nothing behind
+        assert!(encoder.write_row(&(1i32, "x", true)).is_err());
+        assert!(encoder.write(&[&1i64, &"x"]).is_err());
+
+        #[derive(Serialize)]
+        struct Flag {
+            id: i64,
+            name: String,
+            on: bool,
+        }
+        let flag = Flag {
+            id: 8,
+            name: String::from("cd"),
+            on: true,
+        };
+        encoder.write_struct(&flag).unwrap();
+        data.extend(encoder.finish());
+
+        let mut decoder = BinaryCopyDecoder::new(&types).with_names(&["id", "name", "on"]);
+        let mut rows = vec![];
+        // fed in pieces, rows complete only once all of them arrive
+        for chunk in data.chunks(5) {
+            decoder.push(chunk);
+            while let Some(row) = decoder.read_row().unwrap() {
+                rows.push(row);
+            }
+        }
+        assert!(decoder.is_done());
+        assert_eq!(2, rows.len());
+        assert_eq!(7, rows[0].get::<_, i64>(0));
+        assert_eq!("ab", rows[0].get::<_, &str>(1));
+        assert_eq!(None, rows[0].get::<_, Option<bool>>(2));
+        assert!(rows[1].get::<_, bool>("on"));
+        let (id, name, _): (i64, String, bool) = rows[1].deserialize().unwrap();
+        assert_eq!((8, String::from("cd")), (id, name));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mut decoder = BinaryCopyDecoder::new(&[Type::INT4]);
+        decoder.push(b"PGCOPY\n\xff\r\n\0\0\x01\0\0\0\0\0\0");
+        assert!(decoder.read_row().is_err());
+        let mut decoder = BinaryCopyDecoder::new(&[Type::INT4]);
+        let mut data = BinaryCopyEncoder::new(&[]).finish();
+        data.truncate(HEADER_LEN);
+        data.extend(&[0, 2]);
+        decoder.push(&data);
+        assert!(decoder.read_row().is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use super::connection::ConnectionError;
+use super::protocols::{self, frontend::CancelRequest};
+use async_std::net::{SocketAddr, TcpStream};
+use async_std::prelude::*;
+use async_std::task;
+
+// handle able to cancel the query currently running on a connection,
+// from any task. connections in this crate are plain TCP, so is the
+// connection used to deliver the cancel request.
+#[derive(Debug, Clone)]
+pub struct CancelToken {
+    address: SocketAddr,
+    process_id: i32,
+    secret_key: i32,
+}
+
+impl CancelToken {
+    pub(crate) fn new(address: SocketAddr, process_id: i32, secret_key: i32) -> CancelToken {
+        CancelToken {
+            address,
+            process_id,
+            secret_key,
+        }
+    }
+
+    // open separate connection to the server and send CancelRequest.
+    // the server closes the connection without replying, so success only
+    // means the request was delivered, not that a query was cancelled
+    pub async fn cancel_query(&self) -> Result<(), ConnectionError> {
+        let mut stream = TcpStream::connect(self.address)
+            .await
+            .map_err(ConnectionError::TcpConnect)?;
+        let m = CancelRequest::new(self.process_id, self.secret_key);
+        let m = protocols::to_untagged_message(&m)?;
+        stream
+            .write_all(&m)
+            .await
+            .map_err(ConnectionError::WriteError)?;
+
+        // wait for the server to close the connection
+        let mut buf = [0u8; 1];
+        let _ = stream.read(&mut buf).await;
+        Ok(())
+    }
+
+    // guard which sends the cancel request when dropped, unless disarmed
+    pub fn guard(&self) -> CancelGuard {
+        CancelGuard {
+            token: Some(self.clone()),
+        }
+    }
+}
+
+pub struct CancelGuard {
+    token: Option<CancelToken>,
+}
+
+impl CancelGuard {
+    pub fn disarm(mut self) {
+        self.token = None;
+    }
+}
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        if let Some(token) = self.token.take() {
+            debug!("Cancelling query on backend pid: {}", token.process_id);
+            task::spawn(async move {
+                if let Err(e) = token.cancel_query().await {
+                    warn!("Failed to cancel query: {}", e);
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::net::TcpListener;
+
+    #[test]
+    fn test_guard_sends_cancel_request() {
+        task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let token = CancelToken::new(listener.local_addr().unwrap(), 258, -2);
+
+            token.guard().disarm();
+            drop(token.guard());
+
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 16];
+            stream.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&[0, 0, 0, 16, 0x4, 0xd2, 0x16, 0x2e], &buf[..8]);
+            assert_eq!(&[0, 0, 0x1, 0x2, 0xff, 0xff, 0xff, 0xfe], &buf[8..]);
+        });
+    }
+}
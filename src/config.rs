@@ -9,6 +9,8 @@ pub trait ToPqConfig {
 #[derive(Debug)]
 pub struct PqConfig {
     pub address: SocketAddr,
+    // none when the url has no user, the connection then logs in as
+    // $USER (or "postgres"), see Connection::startup
     pub cred: Option<Credential>,
     pub dbname: Option<String>,
 }
@@ -65,7 +67,7 @@ impl ToPqConfig for &str {
             let mut h = format!("{}:{}", host, port)
                 .to_socket_addrs()
                 .await
-                .map_err(|e| ConfParseError::ResolveError(e))?;
+                .map_err(ConfParseError::ResolveError)?;
             h.next().ok_or(ConfParseError::NoHost)
         })?;
 
@@ -1,46 +1,578 @@
+use super::binary_copy::{self, BinaryCopyDecoder};
+use super::cancel::{CancelGuard, CancelToken};
 use super::config::Credential;
-use super::protocols::{self, auth::StartupMessage, stream};
+use super::copy::{self, CopyIn};
+use super::error::DbError;
+use super::from_row::{from_row, FromRowError};
+use super::named;
+use super::protocols::auth::{AuthResponse, PasswordMessage, StartupMessage, PASSWORD_MESSAGE_TAG};
+use super::protocols::backend::{self, BackendKeyData, BackendMessage, Notification, TransactionStatus};
+use super::protocols::frontend::{
+    Close, CopyFail, SyncMessage, CLOSE_STATEMENT, CLOSE_TAG, COPY_FAIL_TAG, SYNC_TAG,
+};
+use super::protocols::deserializer::MessageDeserializerError;
+use super::protocols::serializer::MessageSerializerError;
+use super::protocols::{self, stream::MessageReader};
+use super::pipeline::Pipeline;
+use super::query;
+use super::row::Row;
+use super::row_stream;
+use super::simple_query::{self, SimpleQueryResult};
+use super::statement::{Statement, StatementCache, ToStatement};
+use super::text_copy::{self, TextCopyDecoder};
+use super::to_params::{to_params, ToParamsError};
+use super::types::{ToSql, Type, TypeError};
 use async_std::io::Error as AsyncError;
-use async_std::net::{TcpStream, ToSocketAddrs};
+use async_std::net::{SocketAddr, TcpStream, ToSocketAddrs};
 use async_std::prelude::*;
+use futures::Stream;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+
+const DEF_FETCH_SIZE: usize = 1000;
 
 pub struct Connection {
-    stream: TcpStream,
+    reader: MessageReader<TcpStream>,
+    writer: TcpStream,
+    address: SocketAddr,
+    // run-time parameters reported by the backend through ParameterStatus,
+    // sent during startup and again whenever one of them changes
+    parameters: HashMap<String, String>,
+    backend_key: Option<BackendKeyData>,
+    transaction_status: TransactionStatus,
+    statements: StatementCache,
+    // user-defined types looked up in the catalog, by oid
+    types: HashMap<u32, Type>,
+    // send cancel request when query future is dropped before completion
+    cancel_on_drop: bool,
+    // number of ReadyForQuery the backend has yet to send, non zero
+    // when a previous query future was dropped before completion
+    pending_ready: usize,
+    // extended query messages were sent without closing Sync yet
+    unsynced: bool,
+    fetch_size: usize,
+    // copy from stdin was left unfinished, the backend waits for its data
+    copy_in: bool,
+    // received while reading for something else, until taken
+    notifications: VecDeque<Notification>,
+    // set when reading fails, or while a message is partially written
+    // and stays set if the write is interrupted, leaving the stream unusable
+    broken: bool,
 }
 
 impl Connection {
     pub async fn new<A: ToSocketAddrs>(address: A) -> Result<Connection, ConnectionError> {
+        let stream = TcpStream::connect(address)
+            .await
+            .map_err(ConnectionError::TcpConnect)?;
+        let address = stream.peer_addr().map_err(ConnectionError::TcpConnect)?;
         Ok(Connection {
-            stream: TcpStream::connect(address)
-                .await
-                .map_err(|e| ConnectionError::TcpConnect(e))?,
+            reader: MessageReader::new(stream.clone()),
+            writer: stream,
+            address,
+            parameters: HashMap::new(),
+            backend_key: None,
+            transaction_status: TransactionStatus::Idle,
+            statements: StatementCache::new(),
+            types: HashMap::new(),
+            cancel_on_drop: false,
+            pending_ready: 0,
+            unsynced: false,
+            fetch_size: DEF_FETCH_SIZE,
+            copy_in: false,
+            notifications: VecDeque::new(),
+            broken: false,
         })
     }
 
+    // authenticate and wait for the backend to be ready. without
+    // credentials the user is $USER, falling back to "postgres", the
+    // same default as libpq
     pub async fn startup(
         &mut self,
         cred: Option<&Credential>,
         db: Option<&str>,
     ) -> Result<(), ConnectionError> {
-        match cred {
-            None => Ok(()),
-            Some(Credential::UserPass(user, pass)) => {
-                let m = StartupMessage::new(user, db);
-                let m = protocols::to_message_with_len(&m, 0).unwrap();
-                self.stream
-                    .write_all(&m)
-                    .await
-                    .map_err(|e| ConnectionError::WriteError(e))?;
+        let (user, pass) = match cred {
+            Some(Credential::UserPass(user, pass)) => (user.clone(), pass.as_deref()),
+            None => (
+                std::env::var("USER").unwrap_or_else(|_| String::from("postgres")),
+                None,
+            ),
+        };
+
+        let m = StartupMessage::new(&user, db);
+        let m = protocols::to_message_with_len(&m, 0)?;
+        self.write_all(&m).await?;
+        self.authenticate(&user, pass).await?;
+
+        // backend sends parameter status and key data, then ready for query
+        loop {
+            match self.read_message().await? {
+                BackendMessage::BackendKeyData(k) => self.backend_key = Some(k),
+                BackendMessage::ReadyForQuery(_) => {
+                    debug!(
+                        "Connection ready, backend pid: {:?}, server version: {:?}",
+                        self.backend_pid(),
+                        self.server_version()
+                    );
+                    return Ok(());
+                }
+                BackendMessage::ErrorResponse(e) => return Err(ConnectionError::Db(Box::new(e))),
+                m => return Err(self.unexpected(m)),
+            }
+        }
+    }
+
+    async fn authenticate(&mut self, user: &str, pass: Option<&str>) -> Result<(), ConnectionError> {
+        loop {
+            let m = match self.read_message().await? {
+                BackendMessage::Authentication(AuthResponse::AuthenticationOk) => return Ok(()),
+                BackendMessage::Authentication(AuthResponse::AuthenticationCleartextPassword) => {
+                    PasswordMessage::cleartext(pass.ok_or(ConnectionError::MissingPassword)?)
+                }
+                BackendMessage::Authentication(AuthResponse::AuthenticationMD5Password(salt)) => {
+                    PasswordMessage::md5(user, pass.ok_or(ConnectionError::MissingPassword)?, &salt)
+                }
+                BackendMessage::Authentication(AuthResponse::NotImplemented(code)) => {
+                    return Err(ConnectionError::AuthNotSupported(code))
+                }
+                BackendMessage::ErrorResponse(e) => return Err(ConnectionError::Db(Box::new(e))),
+                m => return Err(self.unexpected(m)),
+            };
+            let m = protocols::to_tagged_message(PASSWORD_MESSAGE_TAG, &m)?;
+            self.write_all(&m).await?;
+        }
+    }
+
+    // server_version parameter reported at startup, e.g. "15.4"
+    pub fn server_version(&self) -> Option<&str> {
+        self.parameter("server_version")
+    }
+
+    // current value of run-time parameter reported by the backend
+    // (client_encoding, TimeZone, integer_datetimes, ...)
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        self.parameters.get(name).map(String::as_str)
+    }
+
+    // process id of the backend serving this connection
+    pub fn backend_pid(&self) -> Option<i32> {
+        self.backend_key.map(|k| k.process_id)
+    }
+
+    // token to cancel query running on this connection from another task,
+    // none if the backend did not send BackendKeyData
+    pub fn cancel_token(&self) -> Option<CancelToken> {
+        self.backend_key
+            .map(|k| CancelToken::new(self.address, k.process_id, k.secret_key))
+    }
+
+    pub fn cancel_on_drop(&self) -> bool {
+        self.cancel_on_drop
+    }
+
+    // when enabled, dropping a query future before it completes
+    // sends cancel request for the running query
+    pub fn set_cancel_on_drop(&mut self, enabled: bool) {
+        self.cancel_on_drop = enabled;
+    }
+
+    // run statements using simple query protocol,
+    // yielding one result per statement
+    pub fn simple_query<'a>(
+        &'a mut self,
+        sql: &'a str,
+    ) -> impl Stream<Item = Result<SimpleQueryResult, ConnectionError>> + 'a {
+        simple_query::query(self, sql)
+    }
+
+    // run single statement using extended query protocol, parameters are
+    // sent separately from the statement text, referenced as $1, $2, ...
+    // statement given as sql text is prepared once and kept in the
+    // statement cache of the connection
+    pub async fn query<S: ToStatement + ?Sized>(
+        &mut self,
+        statement: &S,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, ConnectionError> {
+        self.ready().await?;
+        let guard = self.cancel_guard();
+        let r = query::run(self, statement.to_statement(), params).await;
+        self.release_guard(guard);
+        let (rows, _) = r?;
+        Ok(rows)
+    }
+
+    // same as query, every row deserialized into T, see from_row
+    pub async fn query_as<T: DeserializeOwned>(
+        &mut self,
+        statement: &(impl ToStatement + ?Sized),
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<T>, ConnectionError> {
+        let rows = self.query(statement, params).await?;
+        rows.iter()
+            .map(|r| from_row(r).map_err(ConnectionError::FromRow))
+            .collect()
+    }
+
+    // same as query, returning number of rows affected instead
+    pub async fn execute<S: ToStatement + ?Sized>(
+        &mut self,
+        statement: &S,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<u64, ConnectionError> {
+        self.ready().await?;
+        let guard = self.cancel_guard();
+        let r = query::run(self, statement.to_statement(), params).await;
+        self.release_guard(guard);
+        let (_, tag) = r?;
+        Ok(tag.and_then(|t| t.rows()).unwrap_or(0))
+    }
+
+    // same as query, with :name or @name placeholders instead of
+    // positional ones, see named::rewrite
+    pub async fn query_named(
+        &mut self,
+        sql: &str,
+        params: &[(&str, &(dyn ToSql + Sync))],
+    ) -> Result<Vec<Row>, ConnectionError> {
+        let (sql, params) = named::bind(sql, |name| params.iter().find(|(n, _)| *n == name).map(|(_, p)| *p))?;
+        self.query(sql.as_str(), &params).await
+    }
+
+    // same as execute, with :name or @name placeholders instead of
+    // positional ones, see named::rewrite
+    pub async fn execute_named(
+        &mut self,
+        sql: &str,
+        params: &[(&str, &(dyn ToSql + Sync))],
+    ) -> Result<u64, ConnectionError> {
+        let (sql, params) = named::bind(sql, |name| params.iter().find(|(n, _)| *n == name).map(|(_, p)| *p))?;
+        self.execute(sql.as_str(), &params).await
+    }
+
+    // execute statement with :name or @name placeholders, each bound to
+    // the field of value with the same name, see to_params
+    pub async fn execute_struct<T: Serialize + ?Sized>(
+        &mut self,
+        sql: &str,
+        value: &T,
+    ) -> Result<u64, ConnectionError> {
+        let fields = to_params(value).map_err(ConnectionError::ToParams)?;
+        let (sql, params) = named::bind(sql, |name| {
+            fields.iter().find(|(f, _)| f == name).map(|(_, p)| p as &(dyn ToSql + Sync))
+        })?;
+        self.execute(sql.as_str(), &params).await
+    }
+
+    // same as query, yielding rows as they are fetched instead of
+    // collecting them. rows are requested fetch_size at a time, the next
+    // batch only once the previous one is consumed
+    pub fn query_stream<'a, S: ToStatement + ?Sized>(
+        &'a mut self,
+        statement: &'a S,
+        params: &'a [&'a (dyn ToSql + Sync)],
+    ) -> impl Stream<Item = Result<Row, ConnectionError>> + 'a {
+        let fetch_size = self.fetch_size;
+        row_stream::query(self, statement.to_statement(), params, fetch_size)
+    }
+
+    // start COPY ... FROM STDIN, its data is sent through CopyIn
+    pub async fn copy_in(&mut self, sql: &str) -> Result<CopyIn<'_>, ConnectionError> {
+        CopyIn::start(self, sql).await
+    }
+
+    // run COPY ... TO STDOUT, yielding its data as it arrives
+    pub fn copy_out<'a>(&'a mut self, sql: &'a str) -> impl Stream<Item = Result<Vec<u8>, ConnectionError>> + 'a {
+        copy::copy_out(self, sql)
+    }
+
+    // run COPY ... TO STDOUT (FORMAT binary), yielding rows decoded as
+    // they arrive, see BinaryCopyDecoder
+    pub fn binary_copy_out<'a>(
+        &'a mut self,
+        sql: &'a str,
+        decoder: BinaryCopyDecoder,
+    ) -> impl Stream<Item = Result<Row, ConnectionError>> + 'a {
+        binary_copy::copy_out(self, sql, decoder)
+    }
+
+    // run COPY ... TO STDOUT in text or csv format, yielding rows
+    // decoded as they arrive, see TextCopyDecoder
+    pub fn text_copy_out<'a>(
+        &'a mut self,
+        sql: &'a str,
+        decoder: TextCopyDecoder,
+    ) -> impl Stream<Item = Result<Row, ConnectionError>> + 'a {
+        text_copy::copy_out(self, sql, decoder)
+    }
+
+    // start receiving notifications sent to channel, see notifications
+    pub async fn listen(&mut self, channel: &str) -> Result<(), ConnectionError> {
+        self.simple_command(&format!("LISTEN {}", quote_ident(channel))).await
+    }
+
+    pub async fn unlisten(&mut self, channel: &str) -> Result<(), ConnectionError> {
+        self.simple_command(&format!("UNLISTEN {}", quote_ident(channel))).await
+    }
+
+    // notifications of listened channels, those received during earlier
+    // queries first, then waiting for more. the connection can be used
+    // again once the stream is dropped
+    pub fn notifications(&mut self) -> impl Stream<Item = Result<Notification, ConnectionError>> + '_ {
+        futures::stream::unfold((self, false), |(conn, failed)| async move {
+            if failed {
+                return None;
+            }
+            let r = conn.next_notification().await;
+            let failed = r.is_err();
+            Some((r, (conn, failed)))
+        })
+    }
+
+    // notification received so far without waiting
+    pub fn take_notification(&mut self) -> Option<Notification> {
+        self.notifications.pop_front()
+    }
+
+    pub(crate) async fn next_notification(&mut self) -> Result<Notification, ConnectionError> {
+        loop {
+            if let Some(n) = self.notifications.pop_front() {
+                return Ok(n);
+            }
+            self.ready().await?;
+            // nothing else is expected while idle, but FATAL error
+            // before the backend closes the connection (e.g. terminated)
+            match self.receive().await? {
+                Some(BackendMessage::ErrorResponse(e)) => {
+                    self.broken = true;
+                    return Err(ConnectionError::Db(Box::new(e)));
+                }
+                Some(m) => return Err(self.unexpected(m)),
+                None => {}
+            }
+        }
+    }
+
+    pub(crate) async fn simple_command(&mut self, sql: &str) -> Result<(), ConnectionError> {
+        let mut results = Box::pin(self.simple_query(sql));
+        while let Some(r) = results.next().await {
+            r?;
+        }
+        Ok(())
+    }
+
+    // queries sent together before their results are read, see Pipeline
+    pub fn pipeline(&mut self) -> Pipeline<'_> {
+        Pipeline::new(self)
+    }
+
+    pub fn fetch_size(&self) -> usize {
+        self.fetch_size
+    }
+
+    // number of rows fetched per round trip by query_stream, 0 fetches
+    // every row at once
+    pub fn set_fetch_size(&mut self, fetch_size: usize) {
+        self.fetch_size = fetch_size;
+    }
+
+    // prepare named statement, or get it from the statement cache
+    pub async fn prepare(&mut self, sql: &str) -> Result<Statement, ConnectionError> {
+        self.ready().await?;
+        query::cached(self, sql).await
+    }
+
+    pub fn statement_cache_capacity(&self) -> usize {
+        self.statements.capacity()
+    }
+
+    // maximum number of statements kept prepared, least recently used
+    // statement is closed when exceeded. 0 disables the cache, every
+    // statement given as sql text is then parsed on each execution
+    pub fn set_statement_cache_capacity(&mut self, capacity: usize) {
+        self.statements.set_capacity(capacity);
+    }
+
+    // transaction status as of the last ReadyForQuery
+    pub fn transaction_status(&self) -> TransactionStatus {
+        self.transaction_status
+    }
+
+    // connection can not be used anymore and should be discarded
+    pub fn is_broken(&self) -> bool {
+        self.broken
+    }
+
+    pub(crate) fn cancel_guard(&self) -> Option<CancelGuard> {
+        if self.cancel_on_drop {
+            self.cancel_token().map(|t| t.guard())
+        } else {
+            None
+        }
+    }
+
+    // statement is done with, its response read up to ReadyForQuery even
+    // on error, unless the connection broke midway and it may still run
+    pub(crate) fn release_guard(&self, guard: Option<CancelGuard>) {
+        if let Some(guard) = guard {
+            if !self.broken {
+                guard.disarm();
+            }
+        }
+    }
+
+    // make sure the connection is ready to accept new query,
+    // discarding leftover of previously interrupted one
+    pub(crate) async fn ready(&mut self) -> Result<(), ConnectionError> {
+        if self.broken {
+            return Err(ConnectionError::Broken);
+        }
+        if self.copy_in {
+            debug!("Failing unfinished copy");
+            let m = protocols::to_tagged_message(COPY_FAIL_TAG, &CopyFail::new("copy abandoned"))?;
+            self.write_all(&m).await?;
+            self.copy_in = false;
+        }
+        self.sync().await?;
+        while self.pending_ready > 0 {
+            debug!("Discarding leftover messages, pending: {}", self.pending_ready);
+            self.read_message().await?;
+        }
+        Ok(())
+    }
+
+    // after ErrorResponse the backend discards messages until Sync,
+    // read up to its ReadyForQuery so the connection can be reused
+    pub(crate) async fn sync_error(&mut self, e: DbError) -> ConnectionError {
+        match self.ready().await {
+            Ok(()) => ConnectionError::Db(Box::new(e)),
+            Err(err) => err,
+        }
+    }
+
+    pub(crate) fn set_copy_in(&mut self, copy_in: bool) {
+        self.copy_in = copy_in;
+    }
+
+    pub(crate) fn statement_cache(&mut self) -> &mut StatementCache {
+        &mut self.statements
+    }
+
+    // type of oid, with its description if user-defined one was looked up
+    pub(crate) fn known_type(&self, oid: u32) -> Type {
+        self.types.get(&oid).cloned().unwrap_or_else(|| Type::from_oid(oid))
+    }
+
+    pub(crate) fn type_cache(&mut self) -> &mut HashMap<u32, Type> {
+        &mut self.types
+    }
+
+    // send Sync if extended query messages were sent without it
+    pub(crate) async fn sync(&mut self) -> Result<(), ConnectionError> {
+        if self.unsynced {
+            let m = protocols::to_tagged_message(SYNC_TAG, &SyncMessage {})?;
+            self.write_sync(&m).await?;
+        }
+        Ok(())
+    }
+
+    // write extended query messages to be followed by Sync later
+    pub(crate) async fn write_unsynced(&mut self, buf: &[u8]) -> Result<(), ConnectionError> {
+        self.write_all(buf).await?;
+        self.unsynced = true;
+        Ok(())
+    }
+
+    // write message which ends with ReadyForQuery (Query, Sync),
+    // preceded by Close of every statement dropped since last time
+    pub(crate) async fn write_sync(&mut self, buf: &[u8]) -> Result<(), ConnectionError> {
+        let closed = self.statements.take_closed();
+        if closed.is_empty() {
+            self.write_all(buf).await?;
+        } else {
+            let mut m = vec![];
+            for name in closed {
+                debug!("Closing statement: {}", name);
+                m.extend(protocols::to_tagged_message(
+                    CLOSE_TAG,
+                    &Close::new(CLOSE_STATEMENT, &name),
+                )?);
+            }
+            m.extend(buf);
+            self.write_all(&m).await?;
+        }
+        self.unsynced = false;
+        self.pending_ready += 1;
+        Ok(())
+    }
 
-                let resp = stream::read_from_backend(&mut self.stream)
-                    .await
-                    .map_err(|e| ConnectionError::ReadError(e))?;
+    pub(crate) async fn write_all(&mut self, buf: &[u8]) -> Result<(), ConnectionError> {
+        self.broken = true;
+        self.writer
+            .write_all(buf)
+            .await
+            .map_err(ConnectionError::WriteError)?;
+        self.broken = false;
+        Ok(())
+    }
 
-                println!("Response: len: {}, {:?}", resp.len(), resp);
-                Ok(())
+    // read next message from backend, consuming asynchronous messages
+    // which may arrive at any time
+    pub(crate) async fn read_message(&mut self) -> Result<BackendMessage, ConnectionError> {
+        loop {
+            if let Some(m) = self.receive().await? {
+                return Ok(m);
             }
         }
     }
+
+    // next message from backend, none if it was an asynchronous one
+    async fn receive(&mut self) -> Result<Option<BackendMessage>, ConnectionError> {
+        let m = match self.reader.read_message().await {
+            Ok((tag, body)) => backend::parse(tag, body).map_err(ConnectionError::Deserialize),
+            Err(e) => Err(ConnectionError::ReadError(e)),
+        };
+        // either the socket is gone or the stream is out of sync
+        if m.is_err() {
+            self.broken = true;
+        }
+        match m? {
+            BackendMessage::ParameterStatus(p) => {
+                debug!("Parameter status: {} = {}", p.name, p.value);
+                self.parameters.insert(p.name, p.value);
+            }
+            BackendMessage::NoticeResponse(n) => info!("Notice from backend: {}", n),
+            BackendMessage::NotificationResponse(n) => {
+                debug!("Notification on {} from {}", n.channel, n.process_id);
+                self.notifications.push_back(n);
+            }
+            // answer to Close sent along with other messages
+            BackendMessage::CloseComplete => {}
+            BackendMessage::ReadyForQuery(s) => {
+                self.pending_ready = self.pending_ready.saturating_sub(1);
+                self.transaction_status = s;
+                return Ok(Some(BackendMessage::ReadyForQuery(s)));
+            }
+            m => return Ok(Some(m)),
+        }
+        Ok(None)
+    }
+
+    // message which is not valid at this point of the protocol,
+    // the connection state is unknown afterward
+    pub(crate) fn unexpected(&mut self, m: BackendMessage) -> ConnectionError {
+        error!("Unexpected message from backend: {:?}", m);
+        self.broken = true;
+        ConnectionError::UnexpectedMessage
+    }
+}
+
+// identifier in double quotes, e.g. channel name which is not lower case
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
 }
 
 #[derive(Debug)]
@@ -48,5 +580,204 @@ pub enum ConnectionError {
     TcpConnect(AsyncError),
     WriteError(std::io::Error),
     ReadError(std::io::Error),
+    Serialize(MessageSerializerError),
+    Deserialize(MessageDeserializerError),
+    // error reported by the backend
+    Db(Box<DbError>),
+    Type(TypeError),
+    // row does not fit the type it is deserialized into
+    FromRow(FromRowError),
+    // value can not be bound as statement parameters
+    ToParams(ToParamsError),
+    // number of parameters given differs from the statement
+    ParameterCount { expected: usize, actual: usize },
+    // server requested password but none configured
+    MissingPassword,
+    // authentication method code which is not supported yet (e.g. SASL)
+    AuthNotSupported(i32),
+    UnexpectedMessage,
+    // copy was already ended by an error of the backend
+    CopyFailed,
+    // previous operation was interrupted in the middle of a message
+    Broken,
     Unknown,
 }
+
+impl From<MessageSerializerError> for ConnectionError {
+    fn from(e: MessageSerializerError) -> Self {
+        ConnectionError::Serialize(e)
+    }
+}
+
+impl From<MessageDeserializerError> for ConnectionError {
+    fn from(e: MessageDeserializerError) -> Self {
+        ConnectionError::Deserialize(e)
+    }
+}
+
+use std::fmt;
+
+impl fmt::Display for ConnectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionError::Db(e) => write!(f, "Database error: {}", e),
+            ConnectionError::Type(e) => write!(f, "{}", e),
+            ConnectionError::FromRow(e) => write!(f, "{}", e),
+            ConnectionError::ToParams(e) => write!(f, "{}", e),
+            ConnectionError::ParameterCount { expected, actual } => write!(
+                f,
+                "Statement expects {} parameters, {} given",
+                expected, actual
+            ),
+            ConnectionError::AuthNotSupported(code) => {
+                write!(f, "Authentication method {} is not supported", code)
+            }
+            ConnectionError::CopyFailed => write!(f, "Copy was already ended by an error"),
+            _ => write!(f, "Connection error: {:?}", self),
+        }
+    }
+}
+
+impl std::error::Error for ConnectionError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_backend::FakeBackend;
+    use async_std::task;
+    use std::time::Duration;
+
+    #[test]
+    fn test_failed_query_does_not_cancel() {
+        task::block_on(async {
+            let backend = FakeBackend::bind().await;
+            let mut conn = Connection::new(backend.address()).await.unwrap();
+            let server = async {
+                let mut session = backend.start(42).await;
+                assert_eq!(vec![b'P', b'D', b'S'], session.read_request().await.0);
+                session.describe_int4().await;
+                session.ready().await;
+                assert_eq!(vec![b'B', b'E', b'S'], session.read_request().await.0);
+                session.error("ERROR", "22012", "division by zero").await;
+                session.ready().await;
+                assert_eq!(vec![b'B', b'E', b'S'], session.read_request().await.0);
+                session.send(b'2', &[]).await;
+                session.data_row_int4(1).await;
+                session.complete("SELECT 1").await;
+                session.ready().await;
+                session
+            };
+            let client = async {
+                conn.startup(None, None).await.unwrap();
+                conn.set_cancel_on_drop(true);
+                let sql = "select 1 / n from t";
+                match conn.query(sql, &[]).await {
+                    Err(ConnectionError::Db(e)) => assert_eq!("22012", e.code),
+                    r => panic!("Unexpected result: {:?}", r),
+                }
+                match conn.execute(sql, &[&1i32]).await {
+                    Err(ConnectionError::ParameterCount { expected: 0, actual: 1 }) => {}
+                    r => panic!("Unexpected result: {:?}", r),
+                }
+                let rows = conn.query(sql, &[]).await.unwrap();
+                assert_eq!(1, rows[0].get::<_, i32>(0));
+            };
+            let (_session, ()) = futures::join!(server, client);
+            assert!(backend.accept_within(Duration::from_millis(200)).await.is_none());
+        });
+    }
+
+    #[test]
+    fn test_failed_simple_query_does_not_cancel() {
+        task::block_on(async {
+            let backend = FakeBackend::bind().await;
+            let mut conn = Connection::new(backend.address()).await.unwrap();
+            let server = async {
+                let mut session = backend.start(42).await;
+                assert_eq!("LISTEN \"bad\"", session.read_request().await.1);
+                session.error("ERROR", "42602", "invalid name").await;
+                session.ready().await;
+                assert_eq!("select 1", session.read_request().await.1);
+                session.complete("SELECT 1").await;
+                session.ready().await;
+                session
+            };
+            let client = async {
+                conn.startup(None, None).await.unwrap();
+                conn.set_cancel_on_drop(true);
+                match conn.listen("bad").await {
+                    Err(ConnectionError::Db(e)) => assert_eq!("42602", e.code),
+                    r => panic!("Unexpected result: {:?}", r),
+                }
+                conn.simple_command("select 1").await.unwrap();
+            };
+            let (_session, ()) = futures::join!(server, client);
+            assert!(backend.accept_within(Duration::from_millis(200)).await.is_none());
+            assert!(!conn.is_broken());
+        });
+    }
+
+    #[test]
+    fn test_failed_stream_does_not_cancel() {
+        task::block_on(async {
+            let backend = FakeBackend::bind().await;
+            let mut conn = Connection::new(backend.address()).await.unwrap();
+            let server = async {
+                let mut session = backend.start(42).await;
+                assert_eq!(vec![b'P', b'D', b'S'], session.read_request().await.0);
+                session.describe_int4().await;
+                session.ready().await;
+                assert_eq!(vec![b'B', b'E', b'H'], session.read_request().await.0);
+                session.send(b'2', &[]).await;
+                session.data_row_int4(1).await;
+                session.error("ERROR", "22012", "division by zero").await;
+                assert_eq!(vec![b'S'], session.read_request().await.0);
+                session.ready().await;
+                session
+            };
+            let client = async {
+                conn.startup(None, None).await.unwrap();
+                conn.set_cancel_on_drop(true);
+                let mut rows = Box::pin(conn.query_stream("select 1 / n from t", &[]));
+                assert!(rows.next().await.unwrap().is_ok());
+                assert!(rows.next().await.unwrap().is_err());
+            };
+            let (_session, ()) = futures::join!(server, client);
+            assert!(backend.accept_within(Duration::from_millis(200)).await.is_none());
+            assert!(!conn.is_broken());
+        });
+    }
+
+    #[test]
+    fn test_failed_copy_out_does_not_cancel() {
+        task::block_on(async {
+            let backend = FakeBackend::bind().await;
+            let mut conn = Connection::new(backend.address()).await.unwrap();
+            let server = async {
+                let mut session = backend.start(42).await;
+                assert_eq!(vec![b'Q'], session.read_request().await.0);
+                session.error("ERROR", "42P01", "relation \"t\" does not exist").await;
+                session.ready().await;
+                assert_eq!(vec![b'Q'], session.read_request().await.0);
+                session.send(b'H', &[0, 0, 1, 0, 0]).await;
+                session.send(b'd', b"1\n").await;
+                session.error("ERROR", "22012", "division by zero").await;
+                session.ready().await;
+                session
+            };
+            let client = async {
+                conn.startup(None, None).await.unwrap();
+                conn.set_cancel_on_drop(true);
+                let mut data = Box::pin(conn.copy_out("copy t to stdout"));
+                assert!(data.next().await.unwrap().is_err());
+                drop(data);
+                let mut data = Box::pin(conn.copy_out("copy (select 1 / n from t) to stdout"));
+                assert_eq!(b"1\n".to_vec(), data.next().await.unwrap().unwrap());
+                assert!(data.next().await.unwrap().is_err());
+            };
+            let (_session, ()) = futures::join!(server, client);
+            assert!(backend.accept_within(Duration::from_millis(200)).await.is_none());
+            assert!(!conn.is_broken());
+        });
+    }
+}
@@ -0,0 +1,220 @@
+use super::cancel::CancelGuard;
+use super::connection::{Connection, ConnectionError};
+use super::protocols::backend::BackendMessage;
+use super::protocols::frontend::{CopyDone, CopyFail, Query, COPY_DATA_TAG, COPY_DONE_TAG, COPY_FAIL_TAG, QUERY_TAG};
+use super::protocols;
+use futures::sink::{self, Sink};
+use futures::stream::{self, Stream};
+use futures::FutureExt;
+use std::pin::Pin;
+
+// data is sent once this much is buffered
+const COPY_BUF_LEN: usize = 64 * 1024;
+
+// data of COPY ... FROM STDIN in the format given by the statement, any
+// split of it into chunks is fine. chunks are buffered and sent in
+// CopyData messages of about COPY_BUF_LEN. dropping it before finish
+// or abort fails the copy on the next use of the connection
+pub struct CopyIn<'a> {
+    conn: &'a mut Connection,
+    binary: bool,
+    // CopyData message being filled, its length set when sent
+    buf: Vec<u8>,
+    // ended by an error of the backend
+    failed: bool,
+}
+
+impl<'a> CopyIn<'a> {
+    pub(crate) async fn start(conn: &'a mut Connection, sql: &str) -> Result<CopyIn<'a>, ConnectionError> {
+        conn.ready().await?;
+        let m = protocols::to_tagged_message(QUERY_TAG, &Query::new(sql))?;
+        conn.write_sync(&m).await?;
+        match conn.read_message().await? {
+            BackendMessage::CopyInResponse(r) => {
+                conn.set_copy_in(true);
+                Ok(CopyIn {
+                    conn,
+                    binary: r.format == 1,
+                    buf: data_header(),
+                    failed: false,
+                })
+            }
+            BackendMessage::ErrorResponse(e) => Err(conn.sync_error(e).await),
+            m => Err(conn.unexpected(m)),
+        }
+    }
+
+    // whether the statement asked for binary format
+    pub fn is_binary(&self) -> bool {
+        self.binary
+    }
+
+    pub async fn send(&mut self, data: &[u8]) -> Result<(), ConnectionError> {
+        if self.failed {
+            return Err(ConnectionError::CopyFailed);
+        }
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= COPY_BUF_LEN {
+            self.check_error().await?;
+            let m = self.take_data();
+            self.conn.write_all(&m).await?;
+        }
+        Ok(())
+    }
+
+    // chunks sent one by one, e.g. to forward a stream into the copy.
+    // finish is still needed once the sink is closed
+    pub fn sink<'b, T: AsRef<[u8]> + 'b>(&'b mut self) -> Pin<Box<dyn Sink<T, Error = ConnectionError> + 'b>> {
+        Box::pin(sink::unfold(self, |copy, data: T| async move {
+            copy.send(data.as_ref()).await?;
+            Ok(copy)
+        }))
+    }
+
+    // send the rest of the data and end the copy, giving number of rows
+    // copied. error of the backend (e.g. malformed row) is reported here
+    // if it was not by send already
+    pub async fn finish(mut self) -> Result<u64, ConnectionError> {
+        if self.failed {
+            return Err(ConnectionError::CopyFailed);
+        }
+        self.check_error().await?;
+        let mut m = self.take_data();
+        m.extend(protocols::to_tagged_message(COPY_DONE_TAG, &CopyDone {})?);
+        self.conn.write_all(&m).await?;
+        self.conn.set_copy_in(false);
+
+        let mut rows = 0;
+        loop {
+            match self.conn.read_message().await? {
+                BackendMessage::CommandComplete(tag) => rows = tag.rows().unwrap_or(0),
+                BackendMessage::ErrorResponse(e) => return Err(self.conn.sync_error(e).await),
+                BackendMessage::ReadyForQuery(_) => return Ok(rows),
+                m => return Err(self.conn.unexpected(m)),
+            }
+        }
+    }
+
+    // end the copy without copying anything, the backend fails it with
+    // the message
+    pub async fn abort(self, message: &str) -> Result<(), ConnectionError> {
+        if self.failed {
+            return Ok(());
+        }
+        let m = protocols::to_tagged_message(COPY_FAIL_TAG, &CopyFail::new(message))?;
+        self.conn.write_all(&m).await?;
+        self.conn.set_copy_in(false);
+        loop {
+            match self.conn.read_message().await? {
+                BackendMessage::ErrorResponse(e) => debug!("Copy aborted: {}", e),
+                BackendMessage::ReadyForQuery(_) => return Ok(()),
+                m => return Err(self.conn.unexpected(m)),
+            }
+        }
+    }
+
+    // the backend reports error as soon as the copy fails, then ignores
+    // the rest of the data. messages already received are read without
+    // waiting, so the error stops the copy early instead of after all
+    // the data is sent
+    async fn check_error(&mut self) -> Result<(), ConnectionError> {
+        match self.conn.read_message().now_or_never() {
+            None => Ok(()),
+            Some(m) => match m? {
+                BackendMessage::ErrorResponse(e) => {
+                    self.failed = true;
+                    self.conn.set_copy_in(false);
+                    Err(self.conn.sync_error(e).await)
+                }
+                m => Err(self.conn.unexpected(m)),
+            },
+        }
+    }
+
+    // buffered data as CopyData message, empty if there is none
+    fn take_data(&mut self) -> Vec<u8> {
+        if self.buf.len() == 5 {
+            return vec![];
+        }
+        let mut m = std::mem::replace(&mut self.buf, data_header());
+        let len = (m.len() as u32 - 1).to_be_bytes();
+        m[1..5].copy_from_slice(&len);
+        m
+    }
+}
+
+struct CopyOut<'a> {
+    conn: &'a mut Connection,
+    // query not sent yet
+    sql: Option<&'a str>,
+    guard: Option<CancelGuard>,
+    done: bool,
+}
+
+// data of COPY ... TO STDOUT as it arrives, chunk per CopyData message
+// (a row each in text and csv format). dropping the stream midway
+// leaves the rest to be discarded on the next use of the connection
+pub(crate) fn copy_out<'a>(
+    conn: &'a mut Connection,
+    sql: &'a str,
+) -> impl Stream<Item = Result<Vec<u8>, ConnectionError>> + 'a {
+    let state = CopyOut {
+        conn,
+        sql: Some(sql),
+        guard: None,
+        done: false,
+    };
+    stream::unfold(state, |mut state| async move {
+        if state.done {
+            return None;
+        }
+        match state.next().await {
+            Ok(Some(data)) => Some((Ok(data), state)),
+            Ok(None) => None,
+            Err(e) => {
+                state.done = true;
+                // the connection is ready again after an error, unless broken
+                state.conn.release_guard(state.guard.take());
+                Some((Err(e), state))
+            }
+        }
+    })
+}
+
+impl<'a> CopyOut<'a> {
+    async fn next(&mut self) -> Result<Option<Vec<u8>>, ConnectionError> {
+        if let Some(sql) = self.sql.take() {
+            self.conn.ready().await?;
+            let m = protocols::to_tagged_message(QUERY_TAG, &Query::new(sql))?;
+            self.guard = self.conn.cancel_guard();
+            self.conn.write_sync(&m).await?;
+            match self.conn.read_message().await? {
+                BackendMessage::CopyOutResponse(_) => {}
+                BackendMessage::ErrorResponse(e) => return Err(self.conn.sync_error(e).await),
+                m => return Err(self.conn.unexpected(m)),
+            }
+        }
+
+        loop {
+            match self.conn.read_message().await? {
+                BackendMessage::CopyData(data) => return Ok(Some(data)),
+                BackendMessage::CopyDone | BackendMessage::CommandComplete(_) => {}
+                BackendMessage::ErrorResponse(e) => return Err(self.conn.sync_error(e).await),
+                BackendMessage::ReadyForQuery(_) => {
+                    self.done = true;
+                    if let Some(guard) = self.guard.take() {
+                        guard.disarm();
+                    }
+                    return Ok(None);
+                }
+                m => return Err(self.conn.unexpected(m)),
+            }
+        }
+    }
+}
+
+fn data_header() -> Vec<u8> {
+    let mut buf = Vec::with_capacity(COPY_BUF_LEN + 5);
+    buf.extend(&[COPY_DATA_TAG, 0, 0, 0, 0]);
+    buf
+}
@@ -0,0 +1,116 @@
+use super::protocols::deserializer::MessageDeserializerError;
+use std::fmt;
+
+// error (or notice) reported by the backend through
+// ErrorResponse / NoticeResponse message
+#[derive(Debug, Clone, Default)]
+pub struct DbError {
+    pub severity: String,
+    pub code: String,
+    pub message: String,
+    pub detail: Option<String>,
+    pub hint: Option<String>,
+    pub position: Option<u32>,
+    pub context: Option<String>,
+    pub schema: Option<String>,
+    pub table: Option<String>,
+    pub column: Option<String>,
+    pub datatype: Option<String>,
+    pub constraint: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub routine: Option<String>,
+}
+
+impl DbError {
+    // body is a list of (field type byte, nul terminated string)
+    // closed by a single nul byte
+    pub fn parse(body: &[u8]) -> Result<DbError, MessageDeserializerError> {
+        let mut err = DbError::default();
+        let mut rest = body;
+        loop {
+            match rest.split_first() {
+                None => return Err(MessageDeserializerError::NoNullTerminator),
+                Some((0, _)) => return Ok(err),
+                Some((&field, tail)) => {
+                    let end = tail
+                        .iter()
+                        .position(|&b| b == 0)
+                        .ok_or(MessageDeserializerError::NoNullTerminator)?;
+                    let value = String::from_utf8_lossy(&tail[..end]).into_owned();
+                    rest = &tail[end + 1..];
+                    match field {
+                        // localized severity (S) is only used when V is missing
+                        b'S' if err.severity.is_empty() => err.severity = value,
+                        b'V' => err.severity = value,
+                        b'C' => err.code = value,
+                        b'M' => err.message = value,
+                        b'D' => err.detail = Some(value),
+                        b'H' => err.hint = Some(value),
+                        b'P' => err.position = value.parse().ok(),
+                        b'W' => err.context = Some(value),
+                        b's' => err.schema = Some(value),
+                        b't' => err.table = Some(value),
+                        b'c' => err.column = Some(value),
+                        b'd' => err.datatype = Some(value),
+                        b'n' => err.constraint = Some(value),
+                        b'F' => err.file = Some(value),
+                        b'L' => err.line = value.parse().ok(),
+                        b'R' => err.routine = Some(value),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}): {}", self.severity, self.code, self.message)?;
+        if let Some(detail) = &self.detail {
+            write!(f, ", detail: {}", detail)?;
+        }
+        if let Some(hint) = &self.hint {
+            write!(f, ", hint: {}", hint)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for DbError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_error_response() {
+        let mut source = vec![];
+        for (t, v) in &[
+            (b'S', "FEHLER"),
+            (b'V', "ERROR"),
+            (b'C', "42P01"),
+            (b'M', "relation \"t\" does not exist"),
+            (b'P', "15"),
+        ] {
+            source.push(*t);
+            source.extend(v.as_bytes());
+            source.push(0);
+        }
+        source.push(0);
+
+        let err = DbError::parse(&source).unwrap();
+        assert_eq!("ERROR", err.severity);
+        assert_eq!("42P01", err.code);
+        assert_eq!("relation \"t\" does not exist", err.message);
+        assert_eq!(Some(15), err.position);
+        assert!(err.detail.is_none());
+    }
+
+    #[test]
+    fn test_parse_error_response_unterminated() {
+        let source = b"SERROR\0C42P01";
+        assert!(DbError::parse(source).is_err());
+    }
+}
@@ -0,0 +1,142 @@
+// minimal backend speaking the protocol over a local socket, scripted
+// by the test message by message
+use super::protocols::stream::MessageReader;
+use async_std::future;
+use async_std::net::{SocketAddr, TcpListener, TcpStream};
+use async_std::prelude::*;
+use std::time::Duration;
+
+const CANCEL_REQUEST_CODE: u32 = 80877102;
+
+pub(crate) struct FakeBackend {
+    listener: TcpListener,
+}
+
+// connection accepted by FakeBackend
+pub(crate) enum Accepted {
+    Session(Session),
+    // process id of CancelRequest
+    Cancel(i32),
+}
+
+impl FakeBackend {
+    pub(crate) async fn bind() -> FakeBackend {
+        FakeBackend {
+            listener: TcpListener::bind("127.0.0.1:0").await.unwrap(),
+        }
+    }
+
+    pub(crate) fn address(&self) -> SocketAddr {
+        self.listener.local_addr().unwrap()
+    }
+
+    // configuration of a pool connecting to it
+    pub(crate) fn url(&self) -> String {
+        format!("postgresql://tester@{}/db", self.address())
+    }
+
+    // next connection, with its startup message read
+    pub(crate) async fn accept(&self) -> Accepted {
+        let (mut stream, _) = self.listener.accept().await.unwrap();
+        let mut len = [0u8; 4];
+        stream.read_exact(&mut len).await.unwrap();
+        let mut body = vec![0u8; u32::from_be_bytes(len) as usize - 4];
+        stream.read_exact(&mut body).await.unwrap();
+        if body[..4] == CANCEL_REQUEST_CODE.to_be_bytes() {
+            let mut pid = [0u8; 4];
+            pid.copy_from_slice(&body[4..8]);
+            return Accepted::Cancel(i32::from_be_bytes(pid));
+        }
+        Accepted::Session(Session {
+            reader: MessageReader::new(stream.clone()),
+            stream,
+        })
+    }
+
+    // session which completes startup as process pid, without password
+    pub(crate) async fn start(&self, pid: i32) -> Session {
+        let mut session = match self.accept().await {
+            Accepted::Session(s) => s,
+            Accepted::Cancel(pid) => panic!("Unexpected cancel request of {}", pid),
+        };
+        session.send(b'R', &0i32.to_be_bytes()).await;
+        let mut key = pid.to_be_bytes().to_vec();
+        key.extend(&7i32.to_be_bytes());
+        session.send(b'K', &key).await;
+        session.ready().await;
+        session
+    }
+
+    // connection accepted within timeout, none otherwise
+    pub(crate) async fn accept_within(&self, timeout: Duration) -> Option<Accepted> {
+        future::timeout(timeout, self.accept()).await.ok()
+    }
+}
+
+pub(crate) struct Session {
+    reader: MessageReader<TcpStream>,
+    stream: TcpStream,
+}
+
+impl Session {
+    // tag and body of next frontend message
+    pub(crate) async fn read(&mut self) -> (u8, Vec<u8>) {
+        self.reader.read_message().await.unwrap()
+    }
+
+    // tags of messages up to Sync, Flush or Query, the sql of Query last
+    pub(crate) async fn read_request(&mut self) -> (Vec<u8>, String) {
+        let mut tags = vec![];
+        loop {
+            let (tag, body) = self.read().await;
+            tags.push(tag);
+            match tag {
+                b'S' | b'H' => return (tags, String::new()),
+                b'Q' => return (tags, String::from_utf8_lossy(&body[..body.len() - 1]).into_owned()),
+                _ => {}
+            }
+        }
+    }
+
+    pub(crate) async fn send(&mut self, tag: u8, body: &[u8]) {
+        let mut m = vec![tag];
+        m.extend(&(body.len() as u32 + 4).to_be_bytes());
+        m.extend(body);
+        self.stream.write_all(&m).await.unwrap();
+    }
+
+    pub(crate) async fn ready(&mut self) {
+        self.send(b'Z', b"I").await;
+    }
+
+    pub(crate) async fn error(&mut self, severity: &str, code: &str, message: &str) {
+        let body = format!("S{}\0V{}\0C{}\0M{}\0\0", severity, severity, code, message);
+        self.send(b'E', body.as_bytes()).await;
+    }
+
+    pub(crate) async fn complete(&mut self, tag: &str) {
+        self.send(b'C', format!("{}\0", tag).as_bytes()).await;
+    }
+
+    // ParseComplete, no parameters, single int4 column named n
+    pub(crate) async fn describe_int4(&mut self) {
+        self.send(b'1', &[]).await;
+        self.send(b't', &[0, 0]).await;
+        let mut columns = vec![0, 1];
+        columns.extend(b"n\0");
+        columns.extend(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0x17, 0, 4, 0xff, 0xff, 0xff, 0xff, 0, 0]);
+        self.send(b'T', &columns).await;
+    }
+
+    pub(crate) async fn notify(&mut self, pid: i32, channel: &str, payload: &str) {
+        let mut body = pid.to_be_bytes().to_vec();
+        body.extend(format!("{}\0{}\0", channel, payload).as_bytes());
+        self.send(b'A', &body).await;
+    }
+
+    pub(crate) async fn data_row_int4(&mut self, v: i32) {
+        let mut row = vec![0, 1, 0, 0, 0, 4];
+        row.extend(&v.to_be_bytes());
+        self.send(b'D', &row).await;
+    }
+}
@@ -0,0 +1,879 @@
+use super::row::Row;
+use super::types::{self, FromSql, Kind, Numeric, Type, TypeError};
+use serde::de::value::{BorrowedStrDeserializer, MapDeserializer, SeqDeserializer, StrDeserializer};
+use serde::de::{self, DeserializeSeed, Visitor};
+use serde::{forward_to_deserialize_any, Deserialize};
+use std::fmt;
+
+type DResult<T> = Result<T, FromRowError>;
+
+// deserialize row into T. struct fields (or map keys) are matched with
+// columns by name, tuple and sequence elements by position, any other
+// type is read from the only column of the row. NULL is accepted by
+// Option fields only.
+// every column which is missing or does not fit its field is reported:
+// the failing one is replaced by a placeholder value and deserialization
+// starts over to find the next one
+pub fn from_row<'de, T: Deserialize<'de>>(row: &'de Row) -> Result<T, FromRowError> {
+    let mut replaced: Vec<String> = vec![];
+    let mut errors = vec![];
+    loop {
+        let mut de = RowDeserializer {
+            row,
+            replaced: &replaced,
+            failed: None,
+        };
+        let result = T::deserialize(&mut de);
+        let e = match result {
+            Ok(v) if errors.is_empty() => return Ok(v),
+            Ok(_) => break,
+            Err(e) => e,
+        };
+        let name = match (&e, de.failed) {
+            (FromRowError::Column { column, .. }, Some(Failed::Column)) => column.clone(),
+            // reported by the struct once every column is read
+            (FromRowError::MissingColumn(column), None) => column.clone(),
+            _ if errors.is_empty() => return Err(e),
+            _ => break,
+        };
+        if replaced.contains(&name) {
+            break;
+        }
+        errors.push(e);
+        replaced.push(name);
+    }
+    Err(match errors.len() {
+        1 => errors.pop().unwrap(),
+        _ => FromRowError::Columns(errors),
+    })
+}
+
+#[derive(Clone, Copy)]
+enum Failed {
+    Column,
+    Placeholder,
+}
+
+struct RowDeserializer<'a, 'de> {
+    row: &'de Row,
+    // names of columns (or missing fields) given as placeholder
+    replaced: &'a [String],
+    // set when a map value fails
+    failed: Option<Failed>,
+}
+
+impl<'a, 'de> RowDeserializer<'a, 'de> {
+    fn column(&self, idx: usize) -> ColumnDeserializer<'de> {
+        ColumnDeserializer {
+            ty: self.row.column_type(idx).clone(),
+            binary: self.row.is_binary(idx),
+            raw: self.row.get_raw(idx),
+        }
+    }
+
+    fn name(&self, idx: usize) -> &'de str {
+        &self.row.columns()[idx].name
+    }
+
+    // value of the only column
+    fn single<V, F>(&self, f: F) -> DResult<V>
+    where
+        F: FnOnce(ColumnDeserializer<'de>) -> DResult<V>,
+    {
+        if self.row.len() != 1 {
+            return Err(FromRowError::Custom(format!(
+                "row of {} columns into single value",
+                self.row.len()
+            )));
+        }
+        f(self.column(0)).map_err(|e| column_error(self.name(0), e))
+    }
+}
+
+fn column_error(column: &str, e: FromRowError) -> FromRowError {
+    FromRowError::Column {
+        column: column.to_string(),
+        error: e.to_string(),
+    }
+}
+
+macro_rules! single_column {
+    ($($fn:ident)*) => {
+        $(
+            fn $fn<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+                self.single(|c| c.$fn(visitor))
+            }
+        )*
+    };
+}
+
+impl<'a, 'de> de::Deserializer<'de> for &mut RowDeserializer<'a, 'de> {
+    type Error = FromRowError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        if self.row.len() == 1 {
+            self.single(|c| c.deserialize_any(visitor))
+        } else {
+            self.deserialize_map(visitor)
+        }
+    }
+
+    single_column!(
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_f32
+        deserialize_f64 deserialize_char deserialize_str deserialize_string deserialize_bytes
+        deserialize_byte_buf deserialize_unit deserialize_identifier
+    );
+
+    // row itself is never NULL
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, name: &'static str, visitor: V) -> DResult<V::Value> {
+        self.single(|c| c.deserialize_unit_struct(name, visitor))
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> DResult<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        visitor.visit_seq(ColumnSeq { de: self, idx: 0 })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> DResult<V::Value> {
+        if len != self.row.len() {
+            return Err(FromRowError::Custom(format!(
+                "row of {} columns into tuple of {}",
+                self.row.len(),
+                len
+            )));
+        }
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> DResult<V::Value> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        // missing fields replaced by placeholder follow the columns
+        let names: Vec<&'de str> = self.row.columns().iter().map(|c| c.name.as_str()).collect();
+        let mut keys: Vec<Key<'a>> = (0..names.len()).map(Key::Column).collect();
+        let replaced = self.replaced;
+        keys.extend(
+            replaced
+                .iter()
+                .filter(|r| !names.contains(&r.as_str()))
+                .map(|r| Key::Placeholder(r)),
+        );
+        visitor.visit_map(ColumnMap {
+            de: self,
+            keys: keys.into_iter(),
+            value: None,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> DResult<V::Value> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> DResult<V::Value> {
+        self.single(|c| c.deserialize_enum(name, variants, visitor))
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        visitor.visit_unit()
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Key<'a> {
+    Column(usize),
+    Placeholder(&'a str),
+}
+
+struct ColumnMap<'a, 'b, 'de> {
+    de: &'b mut RowDeserializer<'a, 'de>,
+    keys: std::vec::IntoIter<Key<'a>>,
+    value: Option<Key<'a>>,
+}
+
+impl<'a, 'b, 'de> de::MapAccess<'de> for ColumnMap<'a, 'b, 'de> {
+    type Error = FromRowError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> DResult<Option<K::Value>> {
+        let key = match self.keys.next() {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+        self.value = Some(key);
+        match key {
+            Key::Column(idx) => seed.deserialize(BorrowedStrDeserializer::new(self.de.name(idx))).map(Some),
+            Key::Placeholder(name) => seed.deserialize(StrDeserializer::new(name)).map(Some),
+        }
+    }
+
+    fn next_value_seed<S: DeserializeSeed<'de>>(&mut self, seed: S) -> DResult<S::Value> {
+        let key = self.value.take().expect("value without key");
+        let column = match key {
+            Key::Column(idx) if !self.de.replaced.iter().any(|r| r == self.de.name(idx)) => idx,
+            _ => {
+                let r = seed.deserialize(Placeholder);
+                if r.is_err() {
+                    self.de.failed = Some(Failed::Placeholder);
+                }
+                return r;
+            }
+        };
+        seed.deserialize(self.de.column(column)).map_err(|e| {
+            self.de.failed = Some(Failed::Column);
+            column_error(self.de.name(column), e)
+        })
+    }
+}
+
+struct ColumnSeq<'a, 'b, 'de> {
+    de: &'b mut RowDeserializer<'a, 'de>,
+    idx: usize,
+}
+
+impl<'a, 'b, 'de> de::SeqAccess<'de> for ColumnSeq<'a, 'b, 'de> {
+    type Error = FromRowError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> DResult<Option<T::Value>> {
+        if self.idx >= self.de.row.len() {
+            return Ok(None);
+        }
+        let idx = self.idx;
+        self.idx += 1;
+        seed.deserialize(self.de.column(idx))
+            .map(Some)
+            .map_err(|e| column_error(self.de.name(idx), e))
+    }
+}
+
+// value of single column (or array element), deserialized according
+// to its postgres type unless the type asks for something else
+struct ColumnDeserializer<'de> {
+    ty: Type,
+    binary: bool,
+    raw: Option<&'de [u8]>,
+}
+
+impl<'de> ColumnDeserializer<'de> {
+    fn decode<T: FromSql<'de>>(&self) -> DResult<T> {
+        types::decode(&self.ty, self.binary, self.raw).map_err(type_error)
+    }
+
+    // text format value, or binary one which is text anyway
+    fn str(&self, raw: &'de [u8]) -> DResult<&'de str> {
+        let is_enum = matches!(self.ty.custom().map(|c| c.kind()), Some(Kind::Enum(_)));
+        if self.binary && !is_enum {
+            return self.decode();
+        }
+        std::str::from_utf8(raw).map_err(|e| FromRowError::Custom(e.to_string()))
+    }
+
+    // non NULL value in text format
+    fn text(&self) -> Option<DResult<&'de str>> {
+        match self.raw {
+            Some(raw) if !self.binary => Some(self.str(raw)),
+            _ => None,
+        }
+    }
+}
+
+fn type_error(e: TypeError) -> FromRowError {
+    FromRowError::Custom(e.to_string())
+}
+
+// text format value asked for as number (or bool, char) is parsed,
+// anything else is given as string
+macro_rules! parse_text {
+    ($($fn:ident $visit:ident $t:ty),*) => {
+        $(
+            fn $fn<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+                match self.text() {
+                    Some(s) => visitor.$visit(s?.parse::<$t>().map_err(|e| FromRowError::Custom(e.to_string()))?),
+                    None => self.deserialize_any(visitor),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for ColumnDeserializer<'de> {
+    type Error = FromRowError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        let raw = match self.raw {
+            Some(raw) => raw,
+            None => return visitor.visit_none(),
+        };
+        if !self.binary {
+            return visitor.visit_borrowed_str(self.str(raw)?);
+        }
+        match self.ty {
+            Type::BOOL => visitor.visit_bool(self.decode()?),
+            Type::CHAR => visitor.visit_i8(self.decode()?),
+            Type::INT2 => visitor.visit_i16(self.decode()?),
+            Type::INT4 => visitor.visit_i32(self.decode()?),
+            Type::INT8 => visitor.visit_i64(self.decode()?),
+            Type::OID => visitor.visit_u32(self.decode()?),
+            Type::FLOAT4 => visitor.visit_f32(self.decode()?),
+            Type::FLOAT8 => visitor.visit_f64(self.decode()?),
+            Type::TEXT | Type::VARCHAR | Type::BPCHAR | Type::NAME | Type::UNKNOWN => {
+                visitor.visit_borrowed_str(self.decode()?)
+            }
+            Type::BYTEA => visitor.visit_borrowed_bytes(raw),
+            Type::JSON => json(raw, visitor),
+            // version byte first
+            Type::JSONB => json(raw.get(1..).unwrap_or_default(), visitor),
+            Type::NUMERIC => visitor.visit_string(self.decode::<Numeric>()?.to_string()),
+            Type::UUID => visitor.visit_string(uuid(raw)?),
+            Type::VOID => visitor.visit_unit(),
+            Type::TIMESTAMP | Type::TIMESTAMPTZ | Type::DATE | Type::TIME | Type::INTERVAL => {
+                visitor.visit_string(text(&self.ty, raw).map_err(type_error)?)
+            }
+            Type::INET | Type::CIDR => visitor.visit_string(text(&self.ty, raw).map_err(type_error)?),
+            _ => {
+                if let Some(elem) = self.ty.element() {
+                    let elements: Vec<RawValue<'de>> = self.decode()?;
+                    return visitor.visit_seq(ElementSeq {
+                        ty: elem,
+                        elements: elements.into_iter(),
+                    });
+                }
+                if let Some(Kind::Enum(_)) = self.ty.custom().map(|c| c.kind()) {
+                    return visitor.visit_borrowed_str(self.str(raw)?);
+                }
+                if self.ty.range_subtype().is_some() || self.ty.multirange_range().is_some() {
+                    return visitor.visit_string(text(&self.ty, raw).map_err(type_error)?);
+                }
+                Err(FromRowError::Custom(format!("values of {} can not be deserialized", self.ty)))
+            }
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        match self.raw {
+            Some(_) => visitor.visit_some(self),
+            None => visitor.visit_none(),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> DResult<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    // unit variant by its name, as postgres enum label or text
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> DResult<V::Value> {
+        match self.raw {
+            Some(raw) => visitor.visit_enum(BorrowedStrDeserializer::new(self.str(raw)?)),
+            None => Err(type_error(TypeError::UnexpectedNull)),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        match self.text() {
+            Some(s) => visitor.visit_bool(parse_bool(s?)?),
+            None => self.deserialize_any(visitor),
+        }
+    }
+
+    parse_text!(
+        deserialize_i8 visit_i8 i8, deserialize_i16 visit_i16 i16, deserialize_i32 visit_i32 i32,
+        deserialize_i64 visit_i64 i64, deserialize_i128 visit_i128 i128, deserialize_u8 visit_u8 u8,
+        deserialize_u16 visit_u16 u16, deserialize_u32 visit_u32 u32, deserialize_u64 visit_u64 u64,
+        deserialize_u128 visit_u128 u128, deserialize_f32 visit_f32 f32, deserialize_f64 visit_f64 f64,
+        deserialize_char visit_char char
+    );
+
+    forward_to_deserialize_any! {
+        str string bytes byte_buf unit unit_struct seq tuple tuple_struct map struct identifier
+    }
+}
+
+// same spellings as the backend accepts
+fn parse_bool(s: &str) -> DResult<bool> {
+    match s.trim().to_ascii_lowercase().as_str() {
+        "t" | "true" | "y" | "yes" | "on" | "1" => Ok(true),
+        "f" | "false" | "n" | "no" | "off" | "0" => Ok(false),
+        _ => Err(FromRowError::Custom(format!("invalid bool: {}", s))),
+    }
+}
+
+fn json<'de, V: Visitor<'de>>(raw: &'de [u8], visitor: V) -> DResult<V::Value> {
+    let mut de = serde_json::Deserializer::from_slice(raw);
+    de::Deserializer::deserialize_any(&mut de, visitor).map_err(|e| FromRowError::Custom(e.to_string()))
+}
+
+fn uuid(raw: &[u8]) -> DResult<String> {
+    if raw.len() != 16 {
+        return Err(type_error(TypeError::InvalidValue(format!("uuid of {} bytes", raw.len()))));
+    }
+    let hex: Vec<String> = raw.iter().map(|b| format!("{:02x}", b)).collect();
+    Ok(format!(
+        "{}-{}-{}-{}-{}",
+        hex[..4].concat(),
+        hex[4..6].concat(),
+        hex[6..8].concat(),
+        hex[8..10].concat(),
+        hex[10..].concat()
+    ))
+}
+
+// binary value given as string: date and time types in ISO 8601,
+// others (and range bounds) as postgres prints them
+fn text(ty: &Type, raw: &[u8]) -> Result<String, TypeError> {
+    match *ty {
+        Type::INT4 => i32::from_sql(ty, raw).map(|v| v.to_string()),
+        Type::INT8 => i64::from_sql(ty, raw).map(|v| v.to_string()),
+        Type::NUMERIC => Numeric::from_sql(ty, raw).map(|v| v.to_string()),
+        Type::INET | Type::CIDR => types::inet_text(ty, raw),
+        _ if ty.range_subtype().is_some() || ty.multirange_range().is_some() => {
+            types::range_text(ty, raw, text)
+        }
+        _ => types::iso_text(ty, raw),
+    }
+}
+
+// array element of any type, decoded later
+struct RawValue<'a>(Option<&'a [u8]>);
+
+impl<'a> FromSql<'a> for RawValue<'a> {
+    fn accepts(_ty: &Type) -> bool {
+        true
+    }
+
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        Ok(RawValue(Some(raw)))
+    }
+
+    fn from_sql_null(_ty: &Type) -> Result<Self, TypeError> {
+        Ok(RawValue(None))
+    }
+}
+
+struct ElementSeq<'de> {
+    ty: Type,
+    elements: std::vec::IntoIter<RawValue<'de>>,
+}
+
+impl<'de> de::SeqAccess<'de> for ElementSeq<'de> {
+    type Error = FromRowError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> DResult<Option<T::Value>> {
+        match self.elements.next() {
+            Some(RawValue(raw)) => seed
+                .deserialize(ColumnDeserializer {
+                    ty: self.ty.clone(),
+                    binary: true,
+                    raw,
+                })
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+// stands for missing or failing column so deserialization can go on,
+// empty or zero value of whatever type is asked
+struct Placeholder;
+
+impl<'de> de::Deserializer<'de> for Placeholder {
+    type Error = FromRowError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        visitor.visit_bool(false)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        visitor.visit_u8(0)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        visitor.visit_f64(0.0)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        visitor.visit_char('\0')
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        visitor.visit_str("")
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        visitor.visit_bytes(b"")
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        visitor.visit_none()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> DResult<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        visitor.visit_seq(SeqDeserializer::new(std::iter::empty::<()>()))
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        visitor.visit_map(MapDeserializer::new(std::iter::empty::<((), ())>()))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        _visitor: V,
+    ) -> DResult<V::Value> {
+        Err(FromRowError::Custom(String::from("no value for enum")))
+    }
+
+    forward_to_deserialize_any! { unit unit_struct ignored_any }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        self.deserialize_f64(visitor)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> DResult<V::Value> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> DResult<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> DResult<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> DResult<V::Value> {
+        self.deserialize_map(visitor)
+    }
+}
+
+#[derive(Debug)]
+pub enum FromRowError {
+    // field without column of the same name
+    MissingColumn(String),
+    // value of the column does not fit its field
+    Column { column: String, error: String },
+    // more than one column missing or not fitting
+    Columns(Vec<FromRowError>),
+    Custom(String),
+}
+
+impl fmt::Display for FromRowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FromRowError::MissingColumn(name) => write!(f, "Missing column {}", name),
+            FromRowError::Column { column, error } => write!(f, "Column {}: {}", column, error),
+            FromRowError::Columns(errors) => {
+                for (i, e) in errors.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{}", e)?;
+                }
+                Ok(())
+            }
+            FromRowError::Custom(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for FromRowError {}
+
+impl de::Error for FromRowError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        FromRowError::Custom(msg.to_string())
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        FromRowError::MissingColumn(field.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols::backend::{self, BackendMessage, FieldDescription};
+    use serde::Deserialize;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::sync::Arc;
+
+    fn column(name: &str, ty: &Type, format: i16) -> FieldDescription {
+        FieldDescription {
+            name: name.to_string(),
+            table_oid: 0,
+            column_id: 0,
+            type_oid: ty.oid(),
+            type_size: -1,
+            type_modifier: -1,
+            format,
+        }
+    }
+
+    // columns given as (name, type, format, value)
+    fn row(values: &[(&str, Type, i16, Option<&[u8]>)]) -> Row {
+        let columns: Vec<_> = values.iter().map(|(n, ty, f, _)| column(n, ty, *f)).collect();
+        let types = values.iter().map(|(_, ty, _, _)| ty.clone()).collect();
+        let mut body = (values.len() as i16).to_be_bytes().to_vec();
+        for (_, _, _, v) in values {
+            match v {
+                Some(v) => {
+                    body.extend_from_slice(&(v.len() as i32).to_be_bytes());
+                    body.extend_from_slice(v);
+                }
+                None => body.extend_from_slice(&(-1i32).to_be_bytes()),
+            }
+        }
+        match backend::parse(b'D', body).unwrap() {
+            BackendMessage::DataRow(data) => Row::new(Arc::new(columns), Arc::new(types), data),
+            m => panic!("Unexpected message: {:?}", m),
+        }
+    }
+
+    fn order() -> Row {
+        row(&[
+            ("id", Type::INT4, 1, Some(&7i32.to_be_bytes())),
+            ("customer", Type::TEXT, 1, Some(b"ann")),
+            ("total", Type::INT8, 0, Some(b"42")),
+            ("note", Type::TEXT, 1, None),
+            ("paid", Type::BOOL, 1, Some(&[1])),
+        ])
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Order {
+        id: i64,
+        #[serde(rename = "customer")]
+        name: String,
+        total: String,
+        note: Option<String>,
+    }
+
+    #[test]
+    fn test_struct() {
+        let order: Order = from_row(&order()).unwrap();
+        let expected = Order {
+            id: 7,
+            name: "ann".to_string(),
+            total: "42".to_string(),
+            note: None,
+        };
+        assert_eq!(expected, order);
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Flattened<'a> {
+        id: i32,
+        #[serde(flatten, borrow)]
+        customer: Customer<'a>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Customer<'a> {
+        customer: &'a str,
+        paid: bool,
+    }
+
+    #[test]
+    fn test_flatten() {
+        let row = order();
+        let order: Flattened = row.deserialize().unwrap();
+        assert_eq!(7, order.id);
+        assert_eq!(Customer { customer: "ann", paid: true }, order.customer);
+    }
+
+    #[test]
+    fn test_tuple() {
+        let order = order();
+        let t: (i32, &str, String, Option<&str>, bool) = from_row(&order).unwrap();
+        assert_eq!((7, "ann", "42".to_string(), None, true), t);
+        match from_row::<(i32, &str)>(&order) {
+            Err(FromRowError::Custom(_)) => {}
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        let single = row(&[("count", Type::INT8, 1, Some(&3i64.to_be_bytes()))]);
+        assert_eq!(3, from_row::<u32>(&single).unwrap());
+    }
+
+    #[test]
+    fn test_text() {
+        let row = row(&[
+            ("total", Type::INT8, 0, Some(b"42")),
+            ("paid", Type::BOOL, 0, Some(b"t")),
+            ("rate", Type::TEXT, 0, Some(b"1.5")),
+        ]);
+        assert_eq!((42u16, true, 1.5), from_row::<(u16, bool, f64)>(&row).unwrap());
+        assert_eq!(("42", "t", "1.5"), from_row::<(&str, &str, &str)>(&row).unwrap());
+        assert!(from_row::<(u16, u8, f64)>(&row).is_err());
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Event {
+        at: String,
+        day: Option<String>,
+        during: String,
+        every: String,
+    }
+
+    #[test]
+    fn test_date_time() {
+        // 2024-02-29 12:34:56.5, and from it to unbounded
+        let at = (8_825 * 86_400_000_000i64 + 45_296_500_000).to_be_bytes();
+        let mut during = vec![0x02 | 0x10, 0, 0, 0, 8];
+        during.extend(&at);
+        let mut every = 14_706_000_000i64.to_be_bytes().to_vec();
+        every.extend(&[0, 0, 0, 1, 0, 0, 0, 0]);
+        let row = row(&[
+            ("at", Type::TIMESTAMPTZ, 1, Some(&at)),
+            ("day", Type::DATE, 1, Some(&8_825i32.to_be_bytes())),
+            ("during", Type::TSRANGE, 1, Some(&during)),
+            ("every", Type::INTERVAL, 1, Some(&every)),
+        ]);
+        let expected = Event {
+            at: "2024-02-29T12:34:56.5+00:00".to_string(),
+            day: Some("2024-02-29".to_string()),
+            during: "[2024-02-29T12:34:56.5,)".to_string(),
+            every: "P1DT4H5M6S".to_string(),
+        };
+        assert_eq!(expected, from_row(&row).unwrap());
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Host {
+        addr: IpAddr,
+        net: String,
+    }
+
+    #[test]
+    fn test_inet() {
+        let host = row(&[
+            ("addr", Type::INET, 1, Some(&[2, 32, 0, 4, 192, 168, 0, 1])),
+            ("net", Type::CIDR, 1, Some(&[2, 24, 1, 4, 192, 168, 0, 0])),
+        ]);
+        let expected = Host {
+            addr: IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1)),
+            net: "192.168.0.0/24".to_string(),
+        };
+        assert_eq!(expected, from_row(&host).unwrap());
+        let masked = row(&[("addr", Type::INET, 1, Some(&[2, 24, 0, 4, 192, 168, 0, 1]))]);
+        assert_eq!("192.168.0.1/24", from_row::<String>(&masked).unwrap());
+        assert!(from_row::<IpAddr>(&masked).is_err());
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Wrong {
+        id: String,
+        missing: i32,
+        note: String,
+        paid: bool,
+        other: Option<i32>,
+    }
+
+    #[test]
+    fn test_errors() {
+        let errors = match from_row::<Wrong>(&order()) {
+            Err(FromRowError::Columns(errors)) => errors,
+            r => panic!("Unexpected result: {:?}", r),
+        };
+        let mut described: Vec<_> = errors
+            .iter()
+            .map(|e| match e {
+                FromRowError::Column { column, .. } => format!("column {}", column),
+                FromRowError::MissingColumn(name) => format!("missing {}", name),
+                e => panic!("Unexpected error: {:?}", e),
+            })
+            .collect();
+        described.sort();
+        assert_eq!(vec!["column id", "column note", "missing missing"], described);
+
+        match from_row::<i32>(&order()) {
+            Err(FromRowError::Custom(_)) => {}
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+}
@@ -1,13 +1,46 @@
 #[macro_use] extern crate log;
 #[macro_use] extern crate lazy_static;
 
+// lets derived code in tests refer to this crate by name
+#[cfg(all(test, feature = "derive"))]
+extern crate self as async_pq;
+
+pub mod binary_copy;
+pub mod cancel;
 pub mod client;
 pub mod connection;
 pub mod pool;
 pub mod config;
+pub mod copy;
+pub mod error;
+#[cfg(test)]
+mod fake_backend;
+pub mod from_row;
+pub mod listener;
+pub mod pipeline;
+pub mod named;
 pub mod protocols;
+mod query;
+pub mod row;
+mod row_stream;
+pub mod simple_query;
+pub mod statement;
+pub mod text_copy;
+pub mod to_params;
+mod type_lookup;
+pub mod types;
 
+pub use cancel::CancelToken;
 pub use client::Client;
 pub use pool::Pool;
 pub use connection::Connection;
 pub use config::PqConfig;
+pub use copy::CopyIn;
+pub use error::DbError;
+pub use listener::{Listener, ListenerEvent};
+pub use pipeline::Pipeline;
+pub use protocols::backend::{CommandTag, Notification};
+pub use row::Row;
+pub use simple_query::SimpleQueryResult;
+pub use statement::Statement;
+pub use types::{FromSql, ToSql, Type};
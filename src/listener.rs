@@ -0,0 +1,315 @@
+use super::connection::{Connection, ConnectionError};
+use super::pool::Pool;
+use super::protocols::backend::Notification;
+use async_std::future;
+use futures::stream::{self, Stream};
+use std::io;
+use std::time::Duration;
+
+// wait between attempts when reconnecting fails
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+// quiet time after which the connection is checked
+const IDLE_CHECK: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ListenerEvent {
+    Notification(Notification),
+    // connection was lost and established again, channels are listened
+    // again but notifications sent meanwhile are lost, subscribers
+    // should resync whatever state the notifications keep up to date
+    Reconnected,
+}
+
+// notifications of a set of channels, surviving loss of its connection:
+// it reconnects on the next receive and issues LISTEN of every channel
+pub struct Listener {
+    pool: Pool,
+    // none while disconnected
+    conn: Option<Connection>,
+    channels: Vec<String>,
+    // last attempt to reconnect failed
+    retry: bool,
+    reconnect_delay: Duration,
+    idle_check: Option<Duration>,
+}
+
+impl Listener {
+    pub(crate) fn new(pool: Pool, conn: Connection) -> Listener {
+        Listener {
+            pool,
+            conn: Some(conn),
+            channels: vec![],
+            retry: false,
+            reconnect_delay: RECONNECT_DELAY,
+            idle_check: Some(IDLE_CHECK),
+        }
+    }
+
+    // wait between attempts when reconnecting fails, 1s by default
+    pub fn set_reconnect_delay(&mut self, delay: Duration) {
+        self.reconnect_delay = delay;
+    }
+
+    // once nothing is received for this long recv sends an empty query,
+    // which must be answered within the same time. a backend gone
+    // without closing the connection (host down, network cut) is only
+    // noticed this way. 60s by default, none waits forever
+    pub fn set_idle_check(&mut self, idle_check: Option<Duration>) {
+        self.idle_check = idle_check;
+    }
+
+    // channel is listened again after each reconnect. while disconnected
+    // it is only recorded, LISTEN is issued when reconnecting
+    pub async fn listen(&mut self, channel: &str) -> Result<(), ConnectionError> {
+        if self.channels.iter().any(|c| c == channel) {
+            return Ok(());
+        }
+        self.channels.push(channel.to_string());
+        if let Some(conn) = &mut self.conn {
+            if let Err(e) = conn.listen(channel).await {
+                if !self.disconnect_if_broken() {
+                    self.channels.retain(|c| c != channel);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn unlisten(&mut self, channel: &str) -> Result<(), ConnectionError> {
+        self.channels.retain(|c| c != channel);
+        if let Some(conn) = &mut self.conn {
+            if let Err(e) = conn.unlisten(channel).await {
+                if !self.disconnect_if_broken() {
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn channels(&self) -> &[String] {
+        &self.channels
+    }
+
+    // next notification, or Reconnected once the connection is back.
+    // errors of the connection are returned, the next call reconnects
+    pub async fn recv(&mut self) -> Result<ListenerEvent, ConnectionError> {
+        loop {
+            let conn = match &mut self.conn {
+                Some(conn) => conn,
+                None => {
+                    let conn = self.reconnect().await?;
+                    self.conn = Some(conn);
+                    return Ok(ListenerEvent::Reconnected);
+                }
+            };
+            let r = match self.idle_check {
+                Some(idle) => match future::timeout(idle, conn.next_notification()).await {
+                    Ok(r) => r,
+                    Err(_) => match self.check_alive(idle).await {
+                        Ok(()) => continue,
+                        Err(e) => Err(e),
+                    },
+                },
+                None => conn.next_notification().await,
+            };
+            return match r {
+                Ok(n) => Ok(ListenerEvent::Notification(n)),
+                Err(e) => {
+                    self.disconnect_if_broken();
+                    Err(e)
+                }
+            };
+        }
+    }
+
+    // events as received by recv, errors included, never ending
+    pub fn events(&mut self) -> impl Stream<Item = Result<ListenerEvent, ConnectionError>> + '_ {
+        stream::unfold(self, |listener| async move {
+            let r = listener.recv().await;
+            Some((r, listener))
+        })
+    }
+
+    // connection with every channel listened
+    async fn reconnect(&mut self) -> Result<Connection, ConnectionError> {
+        if self.retry {
+            async_std::task::sleep(self.reconnect_delay).await;
+        }
+        self.retry = true;
+        debug!("Reconnecting listener of {} channels", self.channels.len());
+        let mut conn = self.pool.connect().await?;
+        for channel in &self.channels {
+            conn.listen(channel).await?;
+        }
+        self.retry = false;
+        Ok(conn)
+    }
+
+    // empty query, the connection is dropped unless it is answered
+    // within timeout
+    async fn check_alive(&mut self, timeout: Duration) -> Result<(), ConnectionError> {
+        let conn = match &mut self.conn {
+            Some(conn) => conn,
+            None => return Ok(()),
+        };
+        match future::timeout(timeout, conn.simple_command("")).await {
+            Ok(r) => r,
+            Err(_) => {
+                warn!("Listener connection not answering");
+                self.conn = None;
+                Err(ConnectionError::ReadError(io::ErrorKind::TimedOut.into()))
+            }
+        }
+    }
+
+    fn disconnect_if_broken(&mut self) -> bool {
+        match &self.conn {
+            Some(conn) if conn.is_broken() => {
+                warn!("Listener connection lost");
+                self.conn = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_backend::{Accepted, FakeBackend, Session};
+    use async_std::task;
+
+    fn notification(pid: i32, payload: &str) -> ListenerEvent {
+        ListenerEvent::Notification(Notification {
+            process_id: pid,
+            channel: String::from("jobs"),
+            payload: payload.to_string(),
+        })
+    }
+
+    async fn answer_listen(session: &mut Session, channel: &str) {
+        assert_eq!(format!("LISTEN \"{}\"", channel), session.read_request().await.1);
+        session.complete("LISTEN").await;
+        session.ready().await;
+    }
+
+    #[test]
+    fn test_listen_again_after_reconnect() {
+        task::block_on(async {
+            let backend = FakeBackend::bind().await;
+            let pool = Pool::new(backend.url().as_str(), 1).unwrap();
+            let server = async {
+                let mut session = backend.start(1).await;
+                answer_listen(&mut session, "jobs").await;
+                session.notify(1, "jobs", "a").await;
+                // connection lost
+                drop(session);
+                let mut session = backend.start(2).await;
+                answer_listen(&mut session, "jobs").await;
+                session.notify(2, "jobs", "b").await;
+                session
+            };
+            let client = async {
+                let mut listener = pool.listener().await.unwrap();
+                listener.listen("jobs").await.unwrap();
+                assert_eq!(notification(1, "a"), listener.recv().await.unwrap());
+                assert!(listener.recv().await.is_err());
+                assert_eq!(ListenerEvent::Reconnected, listener.recv().await.unwrap());
+                assert_eq!(notification(2, "b"), listener.recv().await.unwrap());
+            };
+            futures::join!(server, client);
+        });
+    }
+
+    #[test]
+    fn test_reconnected_after_failed_attempt() {
+        task::block_on(async {
+            let backend = FakeBackend::bind().await;
+            let pool = Pool::new(backend.url().as_str(), 1).unwrap();
+            let server = async {
+                let mut session = backend.start(1).await;
+                answer_listen(&mut session, "jobs").await;
+                drop(session);
+                match backend.accept().await {
+                    Accepted::Session(mut s) => s.error("FATAL", "57P03", "starting up").await,
+                    Accepted::Cancel(pid) => panic!("Unexpected cancel request of {}", pid),
+                }
+                let mut session = backend.start(2).await;
+                answer_listen(&mut session, "jobs").await;
+                session.notify(2, "jobs", "b").await;
+                session
+            };
+            let client = async {
+                let mut listener = pool.listener().await.unwrap();
+                listener.set_reconnect_delay(Duration::from_millis(10));
+                listener.listen("jobs").await.unwrap();
+                assert!(listener.recv().await.is_err());
+                match listener.recv().await {
+                    Err(ConnectionError::Db(e)) => assert_eq!("57P03", e.code),
+                    r => panic!("Unexpected result: {:?}", r),
+                }
+                assert_eq!(ListenerEvent::Reconnected, listener.recv().await.unwrap());
+                assert_eq!(notification(2, "b"), listener.recv().await.unwrap());
+            };
+            futures::join!(server, client);
+        });
+    }
+
+    #[test]
+    fn test_failed_listen_drops_channel() {
+        task::block_on(async {
+            let backend = FakeBackend::bind().await;
+            let pool = Pool::new(backend.url().as_str(), 1).unwrap();
+            let server = async {
+                let mut session = backend.start(1).await;
+                assert_eq!("LISTEN \"bad\"", session.read_request().await.1);
+                session.error("ERROR", "42602", "invalid name").await;
+                session.ready().await;
+                answer_listen(&mut session, "jobs").await;
+                session.notify(1, "jobs", "a").await;
+                session
+            };
+            let client = async {
+                let mut listener = pool.listener().await.unwrap();
+                assert!(listener.listen("bad").await.is_err());
+                assert!(listener.channels().is_empty());
+                listener.listen("jobs").await.unwrap();
+                assert_eq!(&[String::from("jobs")], listener.channels());
+                // still on the same connection
+                assert_eq!(notification(1, "a"), listener.recv().await.unwrap());
+            };
+            futures::join!(server, client);
+        });
+    }
+
+    #[test]
+    fn test_idle_check() {
+        task::block_on(async {
+            let backend = FakeBackend::bind().await;
+            let pool = Pool::new(backend.url().as_str(), 1).unwrap();
+            let server = async {
+                let mut session = backend.start(1).await;
+                assert_eq!((vec![b'Q'], String::new()), session.read_request().await);
+                session.send(b'I', &[]).await;
+                session.ready().await;
+                session.notify(1, "jobs", "a").await;
+                // gone without closing the connection
+                assert_eq!((vec![b'Q'], String::new()), session.read_request().await);
+                session
+            };
+            let client = async {
+                let mut listener = pool.listener().await.unwrap();
+                listener.set_idle_check(Some(Duration::from_millis(100)));
+                assert_eq!(notification(1, "a"), listener.recv().await.unwrap());
+                match listener.recv().await {
+                    Err(ConnectionError::ReadError(e)) => assert_eq!(io::ErrorKind::TimedOut, e.kind()),
+                    r => panic!("Unexpected result: {:?}", r),
+                }
+            };
+            let (_session, ()) = futures::join!(server, client);
+        });
+    }
+}
@@ -0,0 +1,226 @@
+use super::connection::ConnectionError;
+use super::to_params::ToParamsError;
+use super::types::ToSql;
+
+// rewrite :name and @name placeholders into positional $n ones, giving
+// the sql and the name of each parameter in order. repeated name shares
+// its number. placeholders are not looked for inside quoted strings and
+// identifiers, dollar-quoted strings and comments, nor in :: casts,
+// operators such as @@ and <@ or array slices.
+// positional placeholders already in the sql are left alone, named ones
+// are numbered after the highest of them
+pub fn rewrite(sql: &str) -> (String, Vec<String>) {
+    let (placeholders, positional) = scan(sql.as_bytes());
+    let mut out = String::with_capacity(sql.len());
+    let mut names: Vec<String> = vec![];
+    let mut last = 0;
+    for (start, end) in placeholders {
+        let name = &sql[start + 1..end];
+        let idx = match names.iter().position(|n| n == name) {
+            Some(idx) => idx,
+            None => {
+                names.push(name.to_string());
+                names.len() - 1
+            }
+        };
+        out.push_str(&sql[last..start]);
+        out.push_str(&format!("${}", positional + idx + 1));
+        last = end;
+    }
+    out.push_str(&sql[last..]);
+    (out, names)
+}
+
+// sql rewritten into positional placeholders with the value of each,
+// values looked up by name
+pub(crate) fn bind<'a, F>(sql: &str, value: F) -> Result<(String, Vec<&'a (dyn ToSql + Sync)>), ConnectionError>
+where
+    F: Fn(&str) -> Option<&'a (dyn ToSql + Sync)>,
+{
+    let (sql, names) = rewrite(sql);
+    let mut params = Vec::with_capacity(names.len());
+    for name in names {
+        match value(&name) {
+            Some(p) => params.push(p),
+            None => return Err(ConnectionError::ToParams(ToParamsError::MissingField(name))),
+        }
+    }
+    Ok((sql, params))
+}
+
+// byte ranges of named placeholders (including the leading : or @)
+// and the highest positional placeholder number
+fn scan(sql: &[u8]) -> (Vec<(usize, usize)>, usize) {
+    let mut placeholders = vec![];
+    let mut positional = 0;
+    let mut i = 0;
+    while i < sql.len() {
+        let c = sql[i];
+        let next = sql.get(i + 1).copied();
+        i = match c {
+            b'\'' => quoted(sql, i, is_escape_string(sql, i)),
+            b'"' => quoted(sql, i, false),
+            b'-' if next == Some(b'-') => line_comment(sql, i),
+            b'/' if next == Some(b'*') => block_comment(sql, i),
+            b':' if next == Some(b':') => i + 2,
+            b':' | b'@' if next.is_some_and(is_name_start) && starts_placeholder(sql, i) => {
+                let end = name_end(sql, i + 1);
+                placeholders.push((i, end));
+                end
+            }
+            b'$' if next.is_some_and(|n| n.is_ascii_digit()) => {
+                let end = digits_end(sql, i + 1);
+                let n = std::str::from_utf8(&sql[i + 1..end]).ok().and_then(|n| n.parse().ok());
+                positional = positional.max(n.unwrap_or(0));
+                end
+            }
+            b'$' => dollar_quoted(sql, i).unwrap_or(i + 1),
+            // keywords and identifiers, $ is allowed inside
+            c if is_name_start(c) => identifier_end(sql, i),
+            _ => i + 1,
+        };
+    }
+    (placeholders, positional)
+}
+
+// : or @ right after a name, number or [ is part of the expression
+// (arr[1:n], arr[:n]), @ right after an operator char part of the
+// operator (@@, <@). : may follow one, as in v=:n
+fn starts_placeholder(sql: &[u8], i: usize) -> bool {
+    match sql[..i].last().copied() {
+        Some(p) if is_name_char(p) || p == b'[' || p == b':' => false,
+        Some(p) if sql[i] == b'@' && is_operator_char(p) => false,
+        _ => true,
+    }
+}
+
+fn is_operator_char(c: u8) -> bool {
+    b"+-*/<>=~!@#%^&|`?".contains(&c)
+}
+
+fn is_name_start(c: u8) -> bool {
+    c.is_ascii_alphabetic() || c == b'_' || c >= 0x80
+}
+
+fn is_name_char(c: u8) -> bool {
+    is_name_start(c) || c.is_ascii_digit() || c == b'$'
+}
+
+// placeholder name, unlike in identifiers $ is not part of it
+fn name_end(sql: &[u8], start: usize) -> usize {
+    start + sql[start..].iter().take_while(|c| is_name_char(**c) && **c != b'$').count()
+}
+
+fn identifier_end(sql: &[u8], start: usize) -> usize {
+    start + sql[start..].iter().take_while(|c| is_name_char(**c)).count()
+}
+
+fn digits_end(sql: &[u8], start: usize) -> usize {
+    start + sql[start..].iter().take_while(|c| c.is_ascii_digit()).count()
+}
+
+// E'...' string, backslash escapes the next character
+fn is_escape_string(sql: &[u8], quote: usize) -> bool {
+    match quote.checked_sub(1).map(|i| (i, sql[i])) {
+        Some((i, b'e' | b'E')) => i == 0 || !is_name_char(sql[i - 1]),
+        _ => false,
+    }
+}
+
+// end of string or quoted identifier starting at start, doubled quote
+// stands for the quote itself
+fn quoted(sql: &[u8], start: usize, escapes: bool) -> usize {
+    let quote = sql[start];
+    let mut i = start + 1;
+    while i < sql.len() {
+        match sql[i] {
+            b'\\' if escapes => i += 2,
+            c if c == quote && sql.get(i + 1) == Some(&quote) => i += 2,
+            c if c == quote => return i + 1,
+            _ => i += 1,
+        }
+    }
+    sql.len()
+}
+
+fn line_comment(sql: &[u8], start: usize) -> usize {
+    match sql[start..].iter().position(|c| *c == b'\n') {
+        Some(end) => start + end + 1,
+        None => sql.len(),
+    }
+}
+
+// block comments nest
+fn block_comment(sql: &[u8], start: usize) -> usize {
+    let mut depth = 0;
+    let mut i = start;
+    while i < sql.len() {
+        match (sql[i], sql.get(i + 1)) {
+            (b'/', Some(b'*')) => {
+                depth += 1;
+                i += 2;
+            }
+            (b'*', Some(b'/')) => {
+                depth -= 1;
+                i += 2;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    sql.len()
+}
+
+// end of $tag$...$tag$ string starting at start, none if there is
+// no valid opening tag
+fn dollar_quoted(sql: &[u8], start: usize) -> Option<usize> {
+    let tag_end = name_end(sql, start + 1);
+    if sql.get(tag_end) != Some(&b'$') || sql.get(start + 1).is_some_and(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let tag = &sql[start..=tag_end];
+    let body = tag_end + 1;
+    match sql[body..].windows(tag.len()).position(|w| w == tag) {
+        Some(end) => Some(body + end + tag.len()),
+        None => Some(sql.len()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rewrite() {
+        let (sql, names) = rewrite("insert into t (a, b, c) values (:id, @total::numeric, ':x') returning :id");
+        assert_eq!("insert into t (a, b, c) values ($1, $2::numeric, ':x') returning $1", sql);
+        assert_eq!(vec!["id", "total"], names);
+    }
+
+    #[test]
+    fn test_ignored() {
+        let sql = "select ':a', 'it''s :b', E'\\' :c', \"col:d\", $$ :e $$, $fn$ '$$' :f $fn$, \
+                   x::int, y@>z, a @ -1 -- :g\n /* :h /* :i */ :j */ from t$1 where v = :k";
+        let (out, names) = rewrite(sql);
+        assert_eq!(vec!["k"], names);
+        assert_eq!(sql.replace(":k", "$1"), out);
+    }
+
+    #[test]
+    fn test_operators() {
+        let sql = "select * from t where tsv @@to_tsquery('x') and arr[1:n] = arr[:n] and arr[i:j] = x \
+                   and a @> b and a<@b and tags @> @tags and v=:v and w = @w";
+        let (out, names) = rewrite(sql);
+        assert_eq!(vec!["tags", "v", "w"], names);
+        assert_eq!(sql.replace("@tags", "$1").replace(":v", "$2").replace("@w", "$3"), out);
+    }
+
+    #[test]
+    fn test_positional() {
+        let (sql, names) = rewrite("select $1, $2::text, :a, @b, :a");
+        assert_eq!("select $1, $2::text, $3, $4, $3", sql);
+        assert_eq!(vec!["a", "b"], names);
+    }
+}
@@ -0,0 +1,255 @@
+use super::connection::{Connection, ConnectionError};
+use super::protocols::backend::CommandTag;
+use super::query::{self, ExecuteResponse, PrepareResponse};
+use super::row::Row;
+use super::statement::{Statement, StatementRef, ToStatement};
+use super::types::ToSql;
+use async_std::sync::{Mutex, MutexGuard};
+use async_std::task;
+use std::collections::{HashMap, VecDeque};
+
+// queries sharing single connection, each query future writes its
+// messages as soon as it is polled and only then waits for the results,
+// so queries awaited together (e.g. with join) are sent before any of
+// their results is read. every query ends with its own Sync, an error
+// fails only the query which caused it. statement not cached yet is
+// prepared within the pipeline too, except one with user-defined types
+// not looked up yet, which waits for every result in flight first
+pub struct Pipeline<'a> {
+    inner: Mutex<Inner<'a>>,
+}
+
+struct Inner<'a> {
+    conn: &'a mut Connection,
+    next_id: u64,
+    // requests written, in the order their responses arrive,
+    // front one possibly partially read
+    sent: VecDeque<(u64, Pending)>,
+    done: HashMap<u64, Done>,
+}
+
+enum Pending {
+    Prepare(String, PrepareResponse),
+    Execute(ExecuteResponse),
+}
+
+enum Done {
+    Prepared(Result<Statement, ConnectionError>),
+    Executed(Result<(Vec<Row>, Option<CommandTag>), ConnectionError>),
+}
+
+impl<'a> Pipeline<'a> {
+    pub(crate) fn new(conn: &'a mut Connection) -> Pipeline<'a> {
+        Pipeline {
+            inner: Mutex::new(Inner {
+                conn,
+                next_id: 0,
+                sent: VecDeque::new(),
+                done: HashMap::new(),
+            }),
+        }
+    }
+
+    // same as Connection::query, except a stale cached statement is
+    // not prepared again, it only gets removed from the cache
+    pub async fn query<S: ToStatement + ?Sized>(
+        &self,
+        statement: &S,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, ConnectionError> {
+        let (rows, _) = self.run(statement.to_statement(), params).await?;
+        Ok(rows)
+    }
+
+    // same as query, returning number of rows affected instead
+    pub async fn execute<S: ToStatement + ?Sized>(
+        &self,
+        statement: &S,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<u64, ConnectionError> {
+        let (_, tag) = self.run(statement.to_statement(), params).await?;
+        Ok(tag.and_then(|t| t.rows()).unwrap_or(0))
+    }
+
+    async fn run(
+        &self,
+        statement: StatementRef<'_>,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<(Vec<Row>, Option<CommandTag>), ConnectionError> {
+        let (stmt, sql) = match statement {
+            StatementRef::Prepared(stmt) => (stmt.clone(), None),
+            StatementRef::Sql(sql) => (self.cached(sql).await?, Some(sql)),
+        };
+
+        let buf = query::encode_execute(&stmt, params)?;
+        let id = self.send(&buf, Pending::Execute(ExecuteResponse::new(&stmt))).await?;
+        let r = match self.wait(id).await? {
+            Done::Executed(r) => r,
+            Done::Prepared(_) => return Err(ConnectionError::UnexpectedMessage),
+        };
+        if let (Some(sql), Err(ConnectionError::Db(e))) = (sql, &r) {
+            if query::is_stale_plan(e) {
+                self.lock().await?.conn.statement_cache().remove(sql);
+            }
+        }
+        r
+    }
+
+    // statement from the cache of the connection, prepared if missing.
+    // always named, unnamed one could be replaced by another query of
+    // the pipeline before being bound
+    async fn cached(&self, sql: &str) -> Result<Statement, ConnectionError> {
+        let name = {
+            let mut inner = self.lock().await?;
+            if let Some(stmt) = inner.conn.statement_cache().get(sql) {
+                return Ok(stmt);
+            }
+            inner.conn.statement_cache().unique_name()
+        };
+
+        let buf = query::encode_prepare(&name, sql)?;
+        let id = self.send(&buf, Pending::Prepare(name, PrepareResponse::default())).await?;
+        let mut stmt = match self.wait(id).await? {
+            Done::Prepared(r) => r?,
+            Done::Executed(_) => return Err(ConnectionError::UnexpectedMessage),
+        };
+        let mut inner = self.lock().await?;
+        // user-defined types are looked up outside of the pipeline,
+        // once responses to everything sent so far are read
+        if query::has_unknown_types(inner.conn, &stmt) {
+            while !inner.sent.is_empty() {
+                inner.read_message().await?;
+            }
+            query::resolve_types(inner.conn, &mut stmt).await?;
+        }
+        inner.conn.statement_cache().insert(sql, stmt.clone());
+        Ok(stmt)
+    }
+
+    async fn send(&self, buf: &[u8], pending: Pending) -> Result<u64, ConnectionError> {
+        let id = {
+            let mut inner = self.lock().await?;
+            inner.conn.write_sync(buf).await?;
+            let id = inner.next_id;
+            inner.next_id += 1;
+            inner.sent.push_back((id, pending));
+            id
+        };
+        // let other queries of the pipeline write theirs before
+        // waiting for the response
+        task::yield_now().await;
+        Ok(id)
+    }
+
+    // read responses in order until the one of request id is complete
+    async fn wait(&self, id: u64) -> Result<Done, ConnectionError> {
+        let mut inner = self.lock().await?;
+        loop {
+            if let Some(done) = inner.done.remove(&id) {
+                return Ok(done);
+            }
+            inner.read_message().await?;
+        }
+    }
+
+    async fn lock(&self) -> Result<MutexGuard<'_, Inner<'a>>, ConnectionError> {
+        let mut inner = self.inner.lock().await;
+        // nothing in flight, discard leftover of previous queries or
+        // of interrupted type lookup
+        if inner.sent.is_empty() {
+            inner.conn.ready().await?;
+        } else if inner.conn.is_broken() {
+            return Err(ConnectionError::Broken);
+        }
+        Ok(inner)
+    }
+}
+
+impl<'a> Inner<'a> {
+    // read single message into the response it belongs to, so nothing
+    // is lost when the waiting future is dropped midway
+    async fn read_message(&mut self) -> Result<(), ConnectionError> {
+        let m = self.conn.read_message().await?;
+        let complete = match self.sent.front_mut() {
+            Some((_, Pending::Prepare(_, resp))) => resp.push(m),
+            Some((_, Pending::Execute(resp))) => resp.push(m),
+            None => Err(Box::new(m)),
+        };
+        if !complete.map_err(|m| self.conn.unexpected(*m))? {
+            return Ok(());
+        }
+
+        let done = match self.sent.pop_front() {
+            Some((id, Pending::Prepare(name, resp))) => (id, Done::Prepared(resp.finish(self.conn, name))),
+            Some((id, Pending::Execute(resp))) => (id, Done::Executed(resp.finish())),
+            None => return Ok(()),
+        };
+        self.done.insert(done.0, done.1);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_backend::{FakeBackend, Session};
+    use async_std::future;
+    use std::time::Duration;
+
+    async fn execute_response(session: &mut Session, v: i32) {
+        session.send(b'2', &[]).await;
+        session.data_row_int4(v).await;
+        session.complete("SELECT 1").await;
+        session.ready().await;
+    }
+
+    #[test]
+    fn test_prepare_within_pipeline() {
+        task::block_on(async {
+            let backend = FakeBackend::bind().await;
+            let mut conn = Connection::new(backend.address()).await.unwrap();
+            let server = async {
+                let mut session = backend.start(42).await;
+                // statement cached before the pipeline
+                assert_eq!(vec![b'P', b'D', b'S'], session.read_request().await.0);
+                session.describe_int4().await;
+                session.ready().await;
+                assert_eq!(vec![b'B', b'E', b'S'], session.read_request().await.0);
+                execute_response(&mut session, 0).await;
+
+                for _ in 0..2 {
+                    assert_eq!(vec![b'P', b'D', b'S'], session.read_request().await.0);
+                }
+                assert_eq!(vec![b'B', b'E', b'S'], session.read_request().await.0);
+                for _ in 0..2 {
+                    session.describe_int4().await;
+                    session.ready().await;
+                }
+                // both statements are bound before the result of the
+                // cached one is sent
+                for _ in 0..2 {
+                    assert_eq!(vec![b'B', b'E', b'S'], session.read_request().await.0);
+                }
+                for v in &[3, 1, 2] {
+                    execute_response(&mut session, *v).await;
+                }
+                session
+            };
+            let client = async {
+                conn.startup(None, None).await.unwrap();
+                conn.query("select 3", &[]).await.unwrap();
+                let pipeline = conn.pipeline();
+                let (a, b, c) = futures::join!(
+                    pipeline.query("select 1", &[]),
+                    pipeline.query("select 2", &[]),
+                    pipeline.query("select 3", &[])
+                );
+                let values: Vec<i32> = vec![a, b, c].into_iter().map(|r| r.unwrap()[0].get(0)).collect();
+                assert_eq!(vec![1, 2, 3], values);
+            };
+            future::timeout(Duration::from_secs(5), async { futures::join!(server, client) })
+                .await
+                .expect("pipeline waited for results before binding");
+        });
+    }
+}
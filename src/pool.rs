@@ -1,5 +1,7 @@
 use super::connection::{Connection, ConnectionError};
 use super::config::*;
+use super::listener::Listener;
+use std::ops::{Deref, DerefMut};
 use std::sync::{Arc, Mutex};
 
 #[derive(Clone)]
@@ -41,29 +43,24 @@ impl Pool {
                 conn: Some(c),
             }),
             None => {
-                let mut allocated = self.inner.conn_allocated.lock().unwrap();
-
-                // max conn check
-                if allocated.0 + allocated.1 >= self.inner.max_conn {
-                    return Err(ConnectionPoolError::Exhausted);
+                {
+                    let mut allocated = self.inner.conn_allocated.lock().unwrap();
+
+                    // max conn check
+                    if allocated.0 + allocated.1 >= self.inner.max_conn {
+                        return Err(ConnectionPoolError::Exhausted);
+                    }
+
+                    // allocate new connection
+                    // add new pending, drop the mutex
+                    allocated.1 += 1;
+                    debug!(
+                        "Creating new connection, total allocated: {}, pending: {}",
+                        allocated.0, allocated.1
+                    );
                 }
 
-                // allocate new connection
-                // add new pending, drop the mutex
-                allocated.1 += 1;
-                debug!(
-                    "Creating new connection, total allocated: {}, pending: {}",
-                    allocated.0, allocated.1
-                );
-                drop(allocated);
-
-                let mut conn = Connection::new(self.inner.conf.address)
-                    .await
-                    .map_err(|e| ConnectionPoolError::Connection(e))?;
-                conn.startup(self.inner.conf.cred.as_ref(), self.inner.conf.dbname.as_deref())
-                    .await
-                    .map_err(|e| ConnectionPoolError::Connection(e))?;
-;
+                let conn = self.connect().await.map_err(ConnectionPoolError::Connection)?;
 
                 let mut allocated = self.inner.conn_allocated.lock().unwrap();
                 allocated.0 += 1;
@@ -81,7 +78,29 @@ impl Pool {
         }
     }
 
+    // listener on a dedicated connection, which is not taken from the pool
+    // nor counted in its connections, see Listener
+    pub async fn listener(&self) -> Result<Listener, ConnectionPoolError> {
+        let conn = self.connect().await.map_err(ConnectionPoolError::Connection)?;
+        Ok(Listener::new(self.clone(), conn))
+    }
+
+    // new connection to the configured database
+    pub(crate) async fn connect(&self) -> Result<Connection, ConnectionError> {
+        let mut conn = Connection::new(self.inner.conf.address).await?;
+        conn.startup(self.inner.conf.cred.as_ref(), self.inner.conf.dbname.as_deref())
+            .await?;
+        Ok(conn)
+    }
+
     pub fn put_back(&self, conn: Connection) {
+        if conn.is_broken() {
+            let mut allocated = self.inner.conn_allocated.lock().unwrap();
+            allocated.0 -= 1;
+            debug!("Discarding broken connection, total allocated: {}", allocated.0);
+            return;
+        }
+
         let mut vec = self.inner.conns.lock().unwrap();
         debug!("#REMOVE total in pools: {}", vec.len());
         vec.push(conn);
@@ -93,6 +112,20 @@ pub struct PooledConnection {
     conn: Option<Connection>,
 }
 
+impl Deref for PooledConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledConnection {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.conn.as_mut().unwrap()
+    }
+}
+
 impl Drop for PooledConnection {
     fn drop(&mut self) {
         self.pool.put_back(self.conn.take().unwrap());
@@ -1,27 +1,34 @@
+use super::deserializer::{from_slice, MessageDeserializerError};
+use md5::{Digest, Md5};
 use serde::Serialize;
 
+// authentication request sent by the backend ('R' message)
+#[derive(Debug)]
 pub enum AuthResponse {
-    ErrorResponse,
     AuthenticationOk,
     AuthenticationCleartextPassword,
-    AuthenticationMD5Password,
-    NotImplemented,
+    AuthenticationMD5Password([u8; 4]),
+    NotImplemented(i32),
 }
 
-// intermediate start up phase between
-// authentication ok and ready for query
-pub enum IntermediateResponse {
-    BackendKeyData,
-    ParameterStatus,
-    ReadyForQuery,
-    ErrorResponse,
-    NoticeResponse,
-    NotImplemented,
+impl AuthResponse {
+    pub fn parse(body: &[u8]) -> Result<AuthResponse, MessageDeserializerError> {
+        if body.len() < 4 {
+            return Err(MessageDeserializerError::InsufficientBytes(4));
+        }
+        let code: i32 = from_slice(&body[..4])?;
+        Ok(match code {
+            0 => AuthResponse::AuthenticationOk,
+            3 => AuthResponse::AuthenticationCleartextPassword,
+            5 => AuthResponse::AuthenticationMD5Password(from_slice(&body[4..])?),
+            c => AuthResponse::NotImplemented(c),
+        })
+    }
 }
 
 const STARTUP_VERSION: u32 = 196608;
-static STARTUP_USER: &'static [u8] = b"user\0";
-static STARTUP_DBNAME: &'static [u8] = b"database\0";
+static STARTUP_USER: &[u8] = b"user\0";
+static STARTUP_DBNAME: &[u8] = b"database\0";
 
 #[derive(Serialize)]
 pub struct StartupMessage<'a> {
@@ -42,3 +49,62 @@ impl<'a> StartupMessage<'a> {
         }
     }
 }
+
+pub const PASSWORD_MESSAGE_TAG: u8 = b'p';
+
+#[derive(Serialize)]
+pub struct PasswordMessage {
+    password: String,
+}
+
+impl PasswordMessage {
+    pub fn cleartext(pass: &str) -> PasswordMessage {
+        PasswordMessage {
+            password: pass.to_string(),
+        }
+    }
+
+    // concat('md5', md5(concat(md5(concat(password, username)), salt)))
+    pub fn md5(user: &str, pass: &str, salt: &[u8; 4]) -> PasswordMessage {
+        let inner = Md5::new()
+            .chain_update(pass.as_bytes())
+            .chain_update(user.as_bytes())
+            .finalize();
+        let outer = Md5::new()
+            .chain_update(to_hex(&inner).as_bytes())
+            .chain_update(salt)
+            .finalize();
+        PasswordMessage {
+            password: format!("md5{}", to_hex(&outer)),
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols::to_tagged_message;
+
+    #[test]
+    fn test_parse_md5_request() {
+        let source: Vec<u8> = vec![0, 0, 0, 5, 0x1, 0x2, 0x3, 0x4];
+        match AuthResponse::parse(&source).unwrap() {
+            AuthResponse::AuthenticationMD5Password(salt) => assert_eq!([1, 2, 3, 4], salt),
+            r => panic!("Unexpected response: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_md5_password_message() {
+        let m = PasswordMessage::md5("myuser", "secret", &[0x1, 0x2, 0x3, 0x4]);
+        let bytes = to_tagged_message(PASSWORD_MESSAGE_TAG, &m).unwrap();
+        assert_eq!(b'p', bytes[0]);
+        assert_eq!(&[0, 0, 0, 40], &bytes[1..5]);
+        assert_eq!(b"md5", &bytes[5..8]);
+        assert_eq!(0, bytes[bytes.len() - 1]);
+    }
+}
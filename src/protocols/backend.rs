@@ -0,0 +1,324 @@
+use super::auth::AuthResponse;
+use super::deserializer::{from_slice, MessageDeserializerError};
+use crate::error::DbError;
+use serde::Deserialize;
+use std::ops::Range;
+
+#[derive(Debug)]
+pub enum BackendMessage {
+    Authentication(AuthResponse),
+    BackendKeyData(BackendKeyData),
+    ParameterStatus(ParameterStatus),
+    ReadyForQuery(TransactionStatus),
+    ParseComplete,
+    BindComplete,
+    CloseComplete,
+    ParameterDescription(Vec<u32>),
+    RowDescription(Vec<FieldDescription>),
+    NoData,
+    DataRow(DataRow),
+    CommandComplete(CommandTag),
+    EmptyQueryResponse,
+    PortalSuspended,
+    CopyInResponse(CopyResponse),
+    CopyOutResponse(CopyResponse),
+    CopyData(Vec<u8>),
+    CopyDone,
+    NotificationResponse(Notification),
+    ErrorResponse(DbError),
+    NoticeResponse(DbError),
+    NotImplemented(u8),
+}
+
+// identifies the backend process, needed to issue CancelRequest later
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct BackendKeyData {
+    pub process_id: i32,
+    pub secret_key: i32,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ParameterStatus {
+    pub name: String,
+    pub value: String,
+}
+
+// NOTIFY on a channel the session listens to, sent by process_id
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub process_id: i32,
+    pub channel: String,
+    pub payload: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransactionStatus {
+    Idle,
+    InTransaction,
+    Failed,
+}
+
+impl TransactionStatus {
+    fn parse(body: &[u8]) -> Result<TransactionStatus, MessageDeserializerError> {
+        match from_slice::<u8>(body)? {
+            b'I' => Ok(TransactionStatus::Idle),
+            b'T' => Ok(TransactionStatus::InTransaction),
+            b'E' => Ok(TransactionStatus::Failed),
+            s => Err(MessageDeserializerError::Custom(format!(
+                "unknown transaction status: {}",
+                s
+            ))),
+        }
+    }
+}
+
+// single field of RowDescription
+#[derive(Deserialize, Debug, Clone)]
+pub struct FieldDescription {
+    pub name: String,
+    // 0 if the field is not a column of a table
+    pub table_oid: u32,
+    pub column_id: i16,
+    pub type_oid: u32,
+    pub type_size: i16,
+    pub type_modifier: i32,
+    // 0 text, 1 binary
+    pub format: i16,
+}
+
+// column values of a single row, kept as one buffer
+// with the range of every non NULL value
+#[derive(Debug)]
+pub struct DataRow {
+    body: Vec<u8>,
+    ranges: Vec<Option<Range<usize>>>,
+}
+
+impl DataRow {
+    fn parse(body: Vec<u8>) -> Result<DataRow, MessageDeserializerError> {
+        let count: i16 = from_slice(body.get(..2).ok_or(MessageDeserializerError::InsufficientBytes(2))?)?;
+        let mut ranges = Vec::with_capacity(count.max(0) as usize);
+        let mut idx = 2;
+        for _ in 0..count {
+            let len: i32 = from_slice(
+                body.get(idx..idx + 4)
+                    .ok_or(MessageDeserializerError::InsufficientBytes(4))?,
+            )?;
+            idx += 4;
+            if len < 0 {
+                ranges.push(None);
+            } else {
+                let end = idx + len as usize;
+                if end > body.len() {
+                    return Err(MessageDeserializerError::InsufficientBytes(len as usize));
+                }
+                ranges.push(Some(idx..end));
+                idx = end;
+            }
+        }
+        if idx != body.len() {
+            return Err(MessageDeserializerError::TrailingBytes);
+        }
+        Ok(DataRow { body, ranges })
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    // raw value of column at idx, none if the value is NULL.
+    // panics if idx is out of range
+    pub fn get(&self, idx: usize) -> Option<&[u8]> {
+        self.ranges[idx].clone().map(|r| &self.body[r])
+    }
+}
+
+// start of copy, format of the data
+#[derive(Deserialize, Debug)]
+pub struct CopyResponse {
+    // 0 text (or csv), 1 binary
+    pub format: i8,
+    pub column_formats: Vec<i16>,
+}
+
+// tag of CommandComplete, e.g. "INSERT 0 5", "SELECT 3", "CREATE TABLE"
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct CommandTag {
+    tag: String,
+}
+
+impl CommandTag {
+    pub fn as_str(&self) -> &str {
+        &self.tag
+    }
+
+    // command name without row count
+    pub fn command(&self) -> &str {
+        match self.rows() {
+            Some(_) => self.tag.split(' ').next().unwrap_or(""),
+            None => &self.tag,
+        }
+    }
+
+    // number of rows affected / returned, for commands reporting one
+    pub fn rows(&self) -> Option<u64> {
+        let mut split = self.tag.rsplitn(2, ' ');
+        match (split.next(), split.next()) {
+            (Some(count), Some(_)) => count.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+// decode message body according to the message type byte
+pub fn parse(tag: u8, body: Vec<u8>) -> Result<BackendMessage, MessageDeserializerError> {
+    Ok(match tag {
+        b'R' => BackendMessage::Authentication(AuthResponse::parse(&body)?),
+        b'K' => BackendMessage::BackendKeyData(from_slice(&body)?),
+        b'S' => BackendMessage::ParameterStatus(from_slice(&body)?),
+        b'Z' => BackendMessage::ReadyForQuery(TransactionStatus::parse(&body)?),
+        b'1' => BackendMessage::ParseComplete,
+        b'2' => BackendMessage::BindComplete,
+        b'3' => BackendMessage::CloseComplete,
+        b't' => BackendMessage::ParameterDescription(from_slice(&body)?),
+        b'T' => BackendMessage::RowDescription(from_slice(&body)?),
+        b'n' => BackendMessage::NoData,
+        b'D' => BackendMessage::DataRow(DataRow::parse(body)?),
+        b'C' => BackendMessage::CommandComplete(from_slice(&body)?),
+        b'I' => BackendMessage::EmptyQueryResponse,
+        b's' => BackendMessage::PortalSuspended,
+        b'G' => BackendMessage::CopyInResponse(from_slice(&body)?),
+        b'H' => BackendMessage::CopyOutResponse(from_slice(&body)?),
+        b'd' => BackendMessage::CopyData(body),
+        b'c' => BackendMessage::CopyDone,
+        b'A' => BackendMessage::NotificationResponse(from_slice(&body)?),
+        b'E' => BackendMessage::ErrorResponse(DbError::parse(&body)?),
+        b'N' => BackendMessage::NoticeResponse(DbError::parse(&body)?),
+        t => BackendMessage::NotImplemented(t),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_parameter_status() {
+        let source = b"server_version\x0015.4\x00".to_vec();
+        match parse(b'S', source).unwrap() {
+            BackendMessage::ParameterStatus(p) => {
+                assert_eq!("server_version", p.name);
+                assert_eq!("15.4", p.value);
+            }
+            _ => panic!("Should be parameter status"),
+        }
+    }
+
+    #[test]
+    fn test_parse_backend_key_data() {
+        let source: Vec<u8> = vec![0, 0, 0x1, 0x2, 0xff, 0xff, 0xff, 0xfe];
+        match parse(b'K', source).unwrap() {
+            BackendMessage::BackendKeyData(k) => {
+                assert_eq!(258, k.process_id);
+                assert_eq!(-2, k.secret_key);
+            }
+            _ => panic!("Should be backend key data"),
+        }
+    }
+
+    #[test]
+    fn test_parse_ready_for_query() {
+        match parse(b'Z', b"T".to_vec()).unwrap() {
+            BackendMessage::ReadyForQuery(s) => assert_eq!(TransactionStatus::InTransaction, s),
+            _ => panic!("Should be ready for query"),
+        }
+        assert!(parse(b'Z', b"X".to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_parse_parameter_description() {
+        let source: Vec<u8> = vec![0, 2, 0, 0, 0, 0x17, 0, 0, 0, 0x19];
+        match parse(b't', source).unwrap() {
+            BackendMessage::ParameterDescription(types) => assert_eq!(vec![23, 25], types),
+            _ => panic!("Should be parameter description"),
+        }
+    }
+
+    #[test]
+    fn test_parse_row_description() {
+        let mut source: Vec<u8> = vec![0, 1];
+        source.extend(b"id\0");
+        source.extend(&[0, 0, 0x40, 0x1, 0, 0x1, 0, 0, 0, 0x17, 0, 0x4, 0xff, 0xff, 0xff, 0xff, 0, 0]);
+        match parse(b'T', source).unwrap() {
+            BackendMessage::RowDescription(fields) => {
+                assert_eq!(1, fields.len());
+                assert_eq!("id", fields[0].name);
+                assert_eq!(16385, fields[0].table_oid);
+                assert_eq!(1, fields[0].column_id);
+                assert_eq!(23, fields[0].type_oid);
+                assert_eq!(4, fields[0].type_size);
+                assert_eq!(-1, fields[0].type_modifier);
+                assert_eq!(0, fields[0].format);
+            }
+            _ => panic!("Should be row description"),
+        }
+    }
+
+    #[test]
+    fn test_parse_data_row() {
+        let source: Vec<u8> = vec![0, 3, 0, 0, 0, 2, b'4', b'2', 0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0];
+        match parse(b'D', source).unwrap() {
+            BackendMessage::DataRow(row) => {
+                assert_eq!(3, row.len());
+                assert_eq!(Some(&b"42"[..]), row.get(0));
+                assert_eq!(None, row.get(1));
+                assert_eq!(Some(&b""[..]), row.get(2));
+            }
+            _ => panic!("Should be data row"),
+        }
+        assert!(parse(b'D', vec![0, 1, 0, 0, 0, 5, b'a']).is_err());
+    }
+
+    #[test]
+    fn test_parse_copy_in_response() {
+        match parse(b'G', vec![1, 0, 2, 0, 1, 0, 1]).unwrap() {
+            BackendMessage::CopyInResponse(r) => {
+                assert_eq!(1, r.format);
+                assert_eq!(vec![1, 1], r.column_formats);
+            }
+            _ => panic!("Should be copy in response"),
+        }
+        match parse(b'd', b"1\tx\n".to_vec()).unwrap() {
+            BackendMessage::CopyData(data) => assert_eq!(b"1\tx\n".to_vec(), data),
+            _ => panic!("Should be copy data"),
+        }
+    }
+
+    #[test]
+    fn test_parse_notification() {
+        let mut source: Vec<u8> = vec![0, 0, 0x30, 0x39];
+        source.extend(b"cache\0users:42\0");
+        match parse(b'A', source).unwrap() {
+            BackendMessage::NotificationResponse(n) => {
+                assert_eq!(12345, n.process_id);
+                assert_eq!("cache", n.channel);
+                assert_eq!("users:42", n.payload);
+            }
+            _ => panic!("Should be notification"),
+        }
+    }
+
+    #[test]
+    fn test_command_tag() {
+        let tag: CommandTag = from_slice(b"INSERT 0 5\0").unwrap();
+        assert_eq!("INSERT", tag.command());
+        assert_eq!(Some(5), tag.rows());
+        let tag: CommandTag = from_slice(b"CREATE TABLE\0").unwrap();
+        assert_eq!("CREATE TABLE", tag.command());
+        assert_eq!(None, tag.rows());
+    }
+}
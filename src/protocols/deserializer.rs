@@ -96,7 +96,7 @@ impl<'de> MessageDeserializer<'de> {
         match iter.position(|&x| x == b'\x00') {
             Some(i) => {
                 let s = std::str::from_utf8(self.get_and_advance(i).unwrap())
-                    .map_err(|e| MessageDeserializerError::Utf8Err(e))?;
+                    .map_err(MessageDeserializerError::Utf8Err)?;
                 self.advance(1);
                 Ok(s)
             }
@@ -118,7 +118,7 @@ where
     }
 }
 
-impl<'de, 'a> de::Deserializer<'de> for &'a mut MessageDeserializer<'de> {
+impl<'de> de::Deserializer<'de> for &mut MessageDeserializer<'de> {
     type Error = MessageDeserializerError;
 
     fn deserialize_str<V>(self, visitor: V) -> MResult<V::Value>
@@ -153,7 +153,6 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut MessageDeserializer<'de> {
     deserialize_unimplemented!(deserialize_byte_buf);
     deserialize_unimplemented!(deserialize_option);
     deserialize_unimplemented!(deserialize_unit);
-    deserialize_unimplemented!(deserialize_seq);
     deserialize_unimplemented!(deserialize_map);
     deserialize_unimplemented!(deserialize_identifier);
     deserialize_unimplemented!(deserialize_ignored_any);
@@ -177,11 +176,26 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut MessageDeserializer<'de> {
         unimplemented!()
     }
 
-    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> MResult<V::Value>
+    // variable length lists in protocol messages are prefixed by i16 count
+    fn deserialize_seq<V>(self, visitor: V) -> MResult<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let len = self.parse_i16()?;
+        if len < 0 {
+            return Err(MessageDeserializerError::Custom(format!(
+                "negative sequence length: {}",
+                len
+            )));
+        }
+        visitor.visit_seq(SeqAccess::new(self, len as usize))
+    }
+
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> MResult<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_seq(SeqAccess::new(self))
+        visitor.visit_seq(SeqAccess::new(self, len))
     }
 
     fn deserialize_tuple_struct<V>(
@@ -218,11 +232,12 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut MessageDeserializer<'de> {
 
 struct SeqAccess<'a, 'de> {
     de: &'a mut MessageDeserializer<'de>,
+    remaining: usize,
 }
 
 impl<'a, 'de> SeqAccess<'a, 'de> {
-    fn new(de: &'a mut MessageDeserializer<'de>) -> Self {
-        SeqAccess { de }
+    fn new(de: &'a mut MessageDeserializer<'de>, len: usize) -> Self {
+        SeqAccess { de, remaining: len }
     }
 }
 
@@ -233,12 +248,16 @@ impl<'a, 'de> serde::de::SeqAccess<'de> for SeqAccess<'a, 'de> {
     where
         T: de::DeserializeSeed<'de>,
     {
-        Ok(Some(seed.deserialize(&mut *self.de).unwrap()))
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        seed.deserialize(&mut *self.de).map(Some)
     }
-}
 
-trait KnownByteLen {
-    fn get_byte_len(&self) -> usize;
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
 }
 
 #[derive(Debug)]
@@ -308,6 +327,19 @@ mod tests {
         assert_eq!(&[0x2u8, 0x1, 0xff], &t.b);
     }
 
+    #[test]
+    fn test_deserialize_seq() {
+        #[derive(Deserialize, Debug)]
+        struct Test {
+            a: Vec<u32>,
+            b: u8,
+        }
+        let source: Vec<u8> = vec![0, 0x2, 0, 0, 0, 0x17, 0, 0, 0, 0x19, 0x7];
+        let t: Test = from_slice(&source).unwrap();
+        assert_eq!(vec![23, 25], t.a);
+        assert_eq!(7, t.b);
+    }
+
     #[test]
     fn test_deserialize_string() {
         #[derive(Deserialize, Debug)]
@@ -0,0 +1,263 @@
+use serde::ser::{SerializeTuple, Serializer};
+use serde::Serialize;
+
+// 1234 in the most significant 16 bits, 5678 in the least
+const CANCEL_REQUEST_CODE: u32 = 80877102;
+
+// sent on a fresh connection instead of StartupMessage
+#[derive(Serialize)]
+pub struct CancelRequest {
+    code: u32,
+    process_id: i32,
+    secret_key: i32,
+}
+
+impl CancelRequest {
+    pub fn new(process_id: i32, secret_key: i32) -> CancelRequest {
+        CancelRequest {
+            code: CANCEL_REQUEST_CODE,
+            process_id,
+            secret_key,
+        }
+    }
+}
+
+pub const QUERY_TAG: u8 = b'Q';
+
+// simple query, may contain several statements separated by semicolon
+#[derive(Serialize)]
+pub struct Query<'a> {
+    query: &'a str,
+}
+
+impl<'a> Query<'a> {
+    pub fn new(query: &'a str) -> Query<'a> {
+        Query { query }
+    }
+}
+
+// list prefixed by its i16 length, as used by Parse and Bind
+pub struct CountedSeq<'a, T>(pub &'a [T]);
+
+impl<'a, T: Serialize> Serialize for CountedSeq<'a, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_tuple(self.0.len() + 1)?;
+        seq.serialize_element(&(self.0.len() as i16))?;
+        for e in self.0 {
+            seq.serialize_element(e)?;
+        }
+        seq.end()
+    }
+}
+
+// parameter value prefixed by its i32 length, -1 for NULL
+pub struct BindValue<'a>(pub Option<&'a [u8]>);
+
+impl<'a> Serialize for BindValue<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_tuple(2)?;
+        match self.0 {
+            None => seq.serialize_element(&-1i32)?,
+            Some(v) => {
+                seq.serialize_element(&(v.len() as i32))?;
+                seq.serialize_element(&RawBytes(v))?;
+            }
+        }
+        seq.end()
+    }
+}
+
+// raw bytes without length prefix
+pub struct RawBytes<'a>(pub &'a [u8]);
+
+impl<'a> Serialize for RawBytes<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+pub const PARSE_TAG: u8 = b'P';
+
+#[derive(Serialize)]
+pub struct Parse<'a> {
+    // empty name for unnamed statement
+    name: &'a str,
+    query: &'a str,
+    // 0 leaves the type unspecified, inferred by the backend
+    param_types: CountedSeq<'a, u32>,
+}
+
+impl<'a> Parse<'a> {
+    pub fn new(name: &'a str, query: &'a str, param_types: &'a [u32]) -> Parse<'a> {
+        Parse {
+            name,
+            query,
+            param_types: CountedSeq(param_types),
+        }
+    }
+}
+
+pub const DESCRIBE_TAG: u8 = b'D';
+pub const DESCRIBE_STATEMENT: u8 = b'S';
+pub const DESCRIBE_PORTAL: u8 = b'P';
+
+#[derive(Serialize)]
+pub struct Describe<'a> {
+    kind: u8,
+    name: &'a str,
+}
+
+impl<'a> Describe<'a> {
+    pub fn new(kind: u8, name: &'a str) -> Describe<'a> {
+        Describe { kind, name }
+    }
+}
+
+pub const BIND_TAG: u8 = b'B';
+
+#[derive(Serialize)]
+pub struct Bind<'a> {
+    portal: &'a str,
+    statement: &'a str,
+    // 0 text, 1 binary
+    param_formats: CountedSeq<'a, i16>,
+    params: CountedSeq<'a, BindValue<'a>>,
+    result_formats: CountedSeq<'a, i16>,
+}
+
+impl<'a> Bind<'a> {
+    pub fn new(
+        portal: &'a str,
+        statement: &'a str,
+        param_formats: &'a [i16],
+        params: &'a [BindValue<'a>],
+        result_formats: &'a [i16],
+    ) -> Bind<'a> {
+        Bind {
+            portal,
+            statement,
+            param_formats: CountedSeq(param_formats),
+            params: CountedSeq(params),
+            result_formats: CountedSeq(result_formats),
+        }
+    }
+}
+
+pub const EXECUTE_TAG: u8 = b'E';
+
+#[derive(Serialize)]
+pub struct Execute<'a> {
+    portal: &'a str,
+    // 0 for no limit
+    max_rows: i32,
+}
+
+impl<'a> Execute<'a> {
+    pub fn new(portal: &'a str, max_rows: i32) -> Execute<'a> {
+        Execute { portal, max_rows }
+    }
+}
+
+pub const CLOSE_TAG: u8 = b'C';
+pub const CLOSE_STATEMENT: u8 = b'S';
+pub const CLOSE_PORTAL: u8 = b'P';
+
+#[derive(Serialize)]
+pub struct Close<'a> {
+    kind: u8,
+    name: &'a str,
+}
+
+impl<'a> Close<'a> {
+    pub fn new(kind: u8, name: &'a str) -> Close<'a> {
+        Close { kind, name }
+    }
+}
+
+pub const FLUSH_TAG: u8 = b'H';
+
+// ask the backend to send pending output without ending the query
+#[derive(Serialize)]
+pub struct FlushMessage {}
+
+pub const SYNC_TAG: u8 = b'S';
+
+// ends extended query, backend answers with ReadyForQuery
+#[derive(Serialize)]
+pub struct SyncMessage {}
+
+pub const COPY_DATA_TAG: u8 = b'd';
+
+pub const COPY_DONE_TAG: u8 = b'c';
+
+// end of data sent by COPY FROM STDIN
+#[derive(Serialize)]
+pub struct CopyDone {}
+
+pub const COPY_FAIL_TAG: u8 = b'f';
+
+// abort COPY FROM STDIN, the backend fails it with the message
+#[derive(Serialize)]
+pub struct CopyFail<'a> {
+    message: &'a str,
+}
+
+impl<'a> CopyFail<'a> {
+    pub fn new(message: &'a str) -> CopyFail<'a> {
+        CopyFail { message }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols::{to_tagged_message, to_untagged_message};
+
+    #[test]
+    fn test_serialize_cancel_request() {
+        let m = CancelRequest::new(258, -2);
+        let bytes = to_untagged_message(&m).unwrap();
+        let expected = vec![
+            0, 0, 0, 16, 0x4, 0xd2, 0x16, 0x2e, 0, 0, 0x1, 0x2, 0xff, 0xff, 0xff, 0xfe,
+        ];
+        assert_eq!(expected, bytes);
+    }
+
+    #[test]
+    fn test_serialize_query() {
+        let bytes = to_tagged_message(QUERY_TAG, &Query::new("select 1")).unwrap();
+        let expected = vec![b'Q', 0, 0, 0, 13, b's', b'e', b'l', b'e', b'c', b't', b' ', b'1', 0];
+        assert_eq!(expected, bytes);
+    }
+
+    #[test]
+    fn test_serialize_parse() {
+        let bytes = to_tagged_message(PARSE_TAG, &Parse::new("", "q", &[23])).unwrap();
+        let expected = vec![b'P', 0, 0, 0, 13, 0, b'q', 0, 0, 1, 0, 0, 0, 23];
+        assert_eq!(expected, bytes);
+    }
+
+    #[test]
+    fn test_serialize_bind() {
+        let params = [BindValue(Some(&[0, 0, 0, 7])), BindValue(None)];
+        let m = Bind::new("", "s1", &[1], &params, &[1, 0]);
+        let bytes = to_tagged_message(BIND_TAG, &m).unwrap();
+        let expected = vec![
+            b'B', 0, 0, 0, 32, 0, b's', b'1', 0, 0, 1, 0, 1, 0, 2, 0, 0, 0, 4, 0, 0, 0, 7, 0xff,
+            0xff, 0xff, 0xff, 0, 2, 0, 1, 0, 0,
+        ];
+        assert_eq!(expected, bytes);
+    }
+
+    #[test]
+    fn test_serialize_sync() {
+        let bytes = to_tagged_message(SYNC_TAG, &SyncMessage {}).unwrap();
+        assert_eq!(vec![b'S', 0, 0, 0, 4], bytes);
+    }
+
+    #[test]
+    fn test_serialize_copy_fail() {
+        let bytes = to_tagged_message(COPY_FAIL_TAG, &CopyFail::new("no")).unwrap();
+        assert_eq!(vec![b'f', 0, 0, 0, 7, b'n', b'o', 0], bytes);
+    }
+}
@@ -1,6 +1,8 @@
 pub mod auth;
+pub mod backend;
+pub mod frontend;
 pub mod serializer;
 pub mod deserializer;
 pub mod stream;
 
-pub use serializer::{to_message, to_message_with_len};
+pub use serializer::{to_message, to_message_with_len, to_tagged_message, to_untagged_message};
@@ -25,6 +25,27 @@ pub fn to_message_with_len<T: Serialize>(value: &T, len_pos: usize) -> Result<Ve
     Ok(output)
 }
 
+// serialize message prefixed only by its length, without nul terminator
+// (CancelRequest, SSLRequest)
+pub fn to_untagged_message<T: Serialize>(value: &T) -> Result<Vec<u8>, MessageSerializerError> {
+    let mut serializer = MessageSerializer { output: vec![0, 0, 0, 0] };
+    value.serialize(&mut serializer)?;
+    let len = (serializer.output.len() as u32).to_be_bytes();
+    serializer.output[0..4].copy_from_slice(&len);
+    Ok(serializer.output)
+}
+
+// serialize regular (non startup) frontend message:
+// 1 byte message type, u32 length (excluding the type byte), then the body.
+// unlike to_message no nul terminator is appended
+pub fn to_tagged_message<T: Serialize>(tag: u8, value: &T) -> Result<Vec<u8>, MessageSerializerError> {
+    let mut serializer = MessageSerializer { output: vec![tag, 0, 0, 0, 0] };
+    value.serialize(&mut serializer)?;
+    let len = (serializer.output.len() as u32 - 1).to_be_bytes();
+    serializer.output[1..5].copy_from_slice(&len);
+    Ok(serializer.output)
+}
+
 impl MessageSerializer {
     fn generic_bytes<T: AsRef<[u8]>>(&mut self, v: T) -> MResult<()> {
         self.output.extend(v.as_ref());
@@ -32,7 +53,7 @@ impl MessageSerializer {
     }
 }
 
-impl<'a> serde::Serializer for &'a mut MessageSerializer {
+impl serde::Serializer for &mut MessageSerializer {
     type Ok = ();
     type Error = MessageSerializerError;
     type SerializeSeq = Self;
@@ -45,7 +66,8 @@ impl<'a> serde::Serializer for &'a mut MessageSerializer {
 
     fn serialize_bool(self, v: bool) -> MResult<()> {
         let b = if v { 49u8 } else { 48u8 };
-        Ok(self.output.push(b))
+        self.output.push(b);
+        Ok(())
     }
 
     fn serialize_i8(self, v: i8) -> MResult<()> {
@@ -91,12 +113,14 @@ impl<'a> serde::Serializer for &'a mut MessageSerializer {
     fn serialize_char(self, v: char) -> MResult<()> {
         let mut b = [0; 4];
         v.encode_utf8(&mut b);
-        Ok(self.output.extend(&b))
+        self.output.extend(&b);
+        Ok(())
     }
 
     fn serialize_str(self, v: &str) -> MResult<()> {
         self.generic_bytes(v)?;
-        Ok(self.output.push(0))
+        self.output.push(0);
+        Ok(())
     }
 
     fn serialize_bytes(self, v: &[u8]) -> MResult<()> {
@@ -119,7 +143,8 @@ impl<'a> serde::Serializer for &'a mut MessageSerializer {
     }
 
     fn serialize_unit(self) -> MResult<()> {
-        Ok(self.output.push(0))
+        self.output.push(0);
+        Ok(())
     }
 
     fn serialize_map(self, _len: Option<usize>) -> MResult<Self::SerializeMap> {
@@ -160,7 +185,7 @@ impl<'a> serde::Serializer for &'a mut MessageSerializer {
     where
         T: ?Sized + Serialize,
     {
-        Ok(value.serialize(&mut *self)?)
+        value.serialize(&mut *self)
     }
 
     fn serialize_tuple(self, len: usize) -> MResult<Self::SerializeTuple> {
@@ -196,7 +221,7 @@ impl<'a> serde::Serializer for &'a mut MessageSerializer {
     }
 }
 
-impl<'a> serde::ser::SerializeSeq for &'a mut MessageSerializer {
+impl serde::ser::SerializeSeq for &mut MessageSerializer {
     type Ok = ();
     type Error = MessageSerializerError;
 
@@ -212,7 +237,7 @@ impl<'a> serde::ser::SerializeSeq for &'a mut MessageSerializer {
     }
 }
 
-impl<'a> serde::ser::SerializeTuple for &'a mut MessageSerializer {
+impl serde::ser::SerializeTuple for &mut MessageSerializer {
     type Ok = ();
     type Error = MessageSerializerError;
 
@@ -228,7 +253,7 @@ impl<'a> serde::ser::SerializeTuple for &'a mut MessageSerializer {
     }
 }
 
-impl<'a> serde::ser::SerializeMap for &'a mut MessageSerializer {
+impl serde::ser::SerializeMap for &mut MessageSerializer {
     type Ok = ();
     type Error = MessageSerializerError;
 
@@ -251,7 +276,7 @@ impl<'a> serde::ser::SerializeMap for &'a mut MessageSerializer {
     }
 }
 
-impl<'a> serde::ser::SerializeStruct for &'a mut MessageSerializer {
+impl serde::ser::SerializeStruct for &mut MessageSerializer {
     type Ok = ();
     type Error = MessageSerializerError;
 
@@ -268,7 +293,7 @@ impl<'a> serde::ser::SerializeStruct for &'a mut MessageSerializer {
     }
 }
 
-impl<'a> serde::ser::SerializeTupleStruct for &'a mut MessageSerializer {
+impl serde::ser::SerializeTupleStruct for &mut MessageSerializer {
     type Ok = ();
     type Error = MessageSerializerError;
 
@@ -284,7 +309,7 @@ impl<'a> serde::ser::SerializeTupleStruct for &'a mut MessageSerializer {
     }
 }
 
-impl<'a> serde::ser::SerializeTupleVariant for &'a mut MessageSerializer {
+impl serde::ser::SerializeTupleVariant for &mut MessageSerializer {
     type Ok = ();
     type Error = MessageSerializerError;
 
@@ -300,7 +325,7 @@ impl<'a> serde::ser::SerializeTupleVariant for &'a mut MessageSerializer {
     }
 }
 
-impl<'a> serde::ser::SerializeStructVariant for &'a mut MessageSerializer {
+impl serde::ser::SerializeStructVariant for &mut MessageSerializer {
     type Ok = ();
     type Error = MessageSerializerError;
 
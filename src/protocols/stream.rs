@@ -1,35 +1,94 @@
-use async_std::net::TcpStream;
+use async_std::io::Read;
 use async_std::prelude::*;
 
-const DEF_BUF_LEN: usize = 32;
+const DEF_BUF_LEN: usize = 8192;
 
-pub async fn read_from_backend(stream: &mut TcpStream) -> Result<Vec<u8>, std::io::Error> {
-    read_from_backend_buf(stream, DEF_BUF_LEN).await
+// reads backend messages, each framed as
+// 1 byte message type, u32 length (including itself), then the body.
+// bytes are only consumed once a whole message is buffered, so dropping
+// read_message future before it completes does not lose any data
+pub struct MessageReader<R> {
+    inner: R,
+    buf: Vec<u8>,
+    // start of unconsumed bytes in buf
+    pos: usize,
+    // socket is read into, kept off the stack since read_message future
+    // is nested in every query future
+    chunk: Box<[u8]>,
 }
 
-pub async fn read_from_backend_buf(
-    stream: &mut TcpStream,
-    buf_size: usize,
-) -> Result<Vec<u8>, std::io::Error> {
-    // // first byte indicate the response type (either R / E)
-    // // 2nd u32 indicate message length (not including the first byte)
-    let mut v = Vec::with_capacity(buf_size);
-    let mut known_len: Option<usize> = None;
-    loop {
-        let mut buf = vec![0u8; buf_size];
-        let n = stream.read(&mut buf).await?;
-
-        if known_len.is_none() {
-            let mut msg_len = [0u8; 4];
-            msg_len.copy_from_slice(&buf[1..5]);
-            known_len = Some(u32::from_be_bytes(msg_len) as usize)
+impl<R: Read + Unpin> MessageReader<R> {
+    pub fn new(inner: R) -> Self {
+        MessageReader {
+            inner,
+            buf: Vec::with_capacity(DEF_BUF_LEN),
+            pos: 0,
+            chunk: vec![0u8; DEF_BUF_LEN].into_boxed_slice(),
         }
+    }
+
+    pub async fn read_message(&mut self) -> Result<(u8, Vec<u8>), std::io::Error> {
+        loop {
+            if let Some(m) = self.next_buffered()? {
+                return Ok(m);
+            }
+
+            let n = self.inner.read(&mut self.chunk).await?;
+            if n == 0 {
+                return Err(std::io::ErrorKind::UnexpectedEof.into());
+            }
+            if self.pos > 0 {
+                self.buf.drain(..self.pos);
+                self.pos = 0;
+            }
+            self.buf.extend_from_slice(&self.chunk[..n]);
+        }
+    }
 
-        v.extend(&buf[..n]);
+    fn next_buffered(&mut self) -> Result<Option<(u8, Vec<u8>)>, std::io::Error> {
+        let available = &self.buf[self.pos..];
+        if available.len() < 5 {
+            return Ok(None);
+        }
 
-        if n < buf_size || v.len() - 1 == (known_len.unwrap()) {
-            break
+        let mut msg_len = [0u8; 4];
+        msg_len.copy_from_slice(&available[1..5]);
+        let len = u32::from_be_bytes(msg_len) as usize;
+        if len < 4 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("invalid message length: {}", len),
+            ));
         }
+        if available.len() < len + 1 {
+            return Ok(None);
+        }
+
+        let m = (available[0], available[5..len + 1].to_vec());
+        self.pos += len + 1;
+        Ok(Some(m))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::task;
+
+    #[test]
+    fn test_read_framed_messages() {
+        let source: Vec<u8> = vec![
+            b'Z', 0, 0, 0, 5, b'I', b'K', 0, 0, 0, 12, 0, 0, 0, 0x2a, 0, 0, 0, 0x7, b'Z', 0,
+        ];
+        let mut reader = MessageReader::new(&source[..]);
+        task::block_on(async {
+            let (tag, body) = reader.read_message().await.unwrap();
+            assert_eq!(b'Z', tag);
+            assert_eq!(vec![b'I'], body);
+            let (tag, body) = reader.read_message().await.unwrap();
+            assert_eq!(b'K', tag);
+            assert_eq!(vec![0, 0, 0, 0x2a, 0, 0, 0, 0x7], body);
+            assert!(reader.read_message().await.is_err());
+        });
     }
-    Ok(v)
 }
@@ -0,0 +1,367 @@
+use super::connection::{Connection, ConnectionError};
+use super::error::DbError;
+use super::protocols::backend::{BackendMessage, CommandTag, FieldDescription, TransactionStatus};
+use super::protocols::frontend::{
+    Bind, BindValue, Describe, Execute, Parse, SyncMessage, BIND_TAG, DESCRIBE_STATEMENT,
+    DESCRIBE_TAG, EXECUTE_TAG, PARSE_TAG, SYNC_TAG,
+};
+use super::protocols;
+use super::row::Row;
+use super::statement::{Statement, StatementRef};
+use super::type_lookup;
+use super::types::{IsNull, ToSql, Type, TypeError};
+use std::sync::Arc;
+
+// run statement given as sql text through the statement cache, or
+// already prepared one, collecting every row of the result
+pub(crate) async fn run(
+    conn: &mut Connection,
+    statement: StatementRef<'_>,
+    params: &[&(dyn ToSql + Sync)],
+) -> Result<(Vec<Row>, Option<CommandTag>), ConnectionError> {
+    let sql = match statement {
+        StatementRef::Prepared(stmt) => return execute(conn, stmt, params).await,
+        StatementRef::Sql(sql) => sql,
+    };
+
+    let stmt = cached(conn, sql).await?;
+    match execute(conn, &stmt, params).await {
+        // result type of cached statement changed since it was prepared
+        // (e.g. table altered), prepare it again unless inside transaction
+        // which is now aborted anyway
+        Err(ConnectionError::Db(e))
+            if is_stale_plan(&e) && conn.transaction_status() == TransactionStatus::Idle =>
+        {
+            debug!("Cached plan is stale, preparing again: {}", sql);
+            conn.statement_cache().remove(sql);
+            drop(stmt);
+            let stmt = cached(conn, sql).await?;
+            execute(conn, &stmt, params).await
+        }
+        Err(ConnectionError::Db(e)) => {
+            if is_stale_plan(&e) {
+                conn.statement_cache().remove(sql);
+            }
+            Err(ConnectionError::Db(e))
+        }
+        r => r,
+    }
+}
+
+// "cached plan must not change result type", told apart from other
+// feature_not_supported errors by the routine since the message is
+// localized according to lc_messages
+pub(crate) fn is_stale_plan(e: &DbError) -> bool {
+    e.code == "0A000" && e.routine.as_deref() == Some("RevalidateCachedQuery")
+}
+
+// statement from the cache of the connection, prepared if missing
+pub(crate) async fn cached(conn: &mut Connection, sql: &str) -> Result<Statement, ConnectionError> {
+    if let Some(stmt) = conn.statement_cache().get(sql) {
+        return Ok(stmt);
+    }
+    let name = conn.statement_cache().next_name();
+    let stmt = prepare(conn, name, sql).await?;
+    conn.statement_cache().insert(sql, stmt.clone());
+    Ok(stmt)
+}
+
+// Parse + Describe statement + Sync, the backend infers parameter types
+pub(crate) async fn prepare(
+    conn: &mut Connection,
+    name: String,
+    sql: &str,
+) -> Result<Statement, ConnectionError> {
+    conn.write_sync(&encode_prepare(&name, sql)?).await?;
+    let mut resp = PrepareResponse::default();
+    loop {
+        let m = conn.read_message().await?;
+        if resp.push(m).map_err(|m| conn.unexpected(*m))? {
+            let mut stmt = resp.finish(conn, name)?;
+            resolve_types(conn, &mut stmt).await?;
+            return Ok(stmt);
+        }
+    }
+}
+
+// look up user-defined types of parameters and columns of statement
+// which was just prepared. dropping the statement on error closes it
+pub(crate) async fn resolve_types(
+    conn: &mut Connection,
+    stmt: &mut Statement,
+) -> Result<(), ConnectionError> {
+    if !has_unknown_types(conn, stmt) {
+        return Ok(());
+    }
+    let params: Vec<u32> = stmt.params().iter().map(Type::oid).collect();
+    let columns = stmt.columns().to_vec();
+    let oids = params.iter().copied().chain(columns.iter().map(|c| c.type_oid));
+    for oid in oids {
+        if type_lookup::is_unknown(conn, oid) {
+            type_lookup::lookup(conn, oid).await?;
+        }
+    }
+    let (params, columns, column_types) = statement_types(conn, &params, columns);
+    stmt.set_types(params, columns, column_types);
+    Ok(())
+}
+
+// statement refers to user-defined types not looked up yet
+pub(crate) fn has_unknown_types(conn: &Connection, stmt: &Statement) -> bool {
+    let params = stmt.params().iter().map(Type::oid);
+    let mut oids = params.chain(stmt.columns().iter().map(|c| c.type_oid));
+    oids.any(|oid| type_lookup::is_unknown(conn, oid))
+}
+
+// types of parameters and columns as known to the connection, binary
+// format requested for every column type which supports it
+fn statement_types(
+    conn: &Connection,
+    params: &[u32],
+    columns: Vec<FieldDescription>,
+) -> (Vec<Type>, Vec<FieldDescription>, Vec<Type>) {
+    let params = params.iter().map(|oid| conn.known_type(*oid)).collect();
+    let column_types: Vec<Type> = columns.iter().map(|c| conn.known_type(c.type_oid)).collect();
+    let columns = columns
+        .into_iter()
+        .zip(&column_types)
+        .map(|(mut c, ty)| {
+            c.format = if ty.supports_binary() { 1 } else { 0 };
+            c
+        })
+        .collect();
+    (params, columns, column_types)
+}
+
+pub(crate) fn encode_prepare(name: &str, sql: &str) -> Result<Vec<u8>, ConnectionError> {
+    let mut buf = protocols::to_tagged_message(PARSE_TAG, &Parse::new(name, sql, &[]))?;
+    buf.extend(protocols::to_tagged_message(
+        DESCRIBE_TAG,
+        &Describe::new(DESCRIBE_STATEMENT, name),
+    )?);
+    buf.extend(protocols::to_tagged_message(SYNC_TAG, &SyncMessage {})?);
+    Ok(buf)
+}
+
+// response to Parse + Describe + Sync, built message by message
+#[derive(Default)]
+pub(crate) struct PrepareResponse {
+    params: Option<Vec<u32>>,
+    columns: Option<Vec<FieldDescription>>,
+    error: Option<DbError>,
+}
+
+impl PrepareResponse {
+    // true once ReadyForQuery is received, message not belonging
+    // to the response is given back
+    pub(crate) fn push(&mut self, m: BackendMessage) -> Result<bool, Box<BackendMessage>> {
+        match m {
+            BackendMessage::ParseComplete => {}
+            BackendMessage::ParameterDescription(p) => {
+                self.params = Some(p)
+            }
+            BackendMessage::RowDescription(c) => self.columns = Some(c),
+            BackendMessage::NoData => self.columns = Some(vec![]),
+            // backend skips the rest until Sync
+            BackendMessage::ErrorResponse(e) => self.error = Some(e),
+            BackendMessage::ReadyForQuery(_) => return Ok(true),
+            m => return Err(Box::new(m)),
+        }
+        Ok(false)
+    }
+
+    pub(crate) fn finish(
+        self,
+        conn: &mut Connection,
+        name: String,
+    ) -> Result<Statement, ConnectionError> {
+        if let Some(e) = self.error {
+            return Err(ConnectionError::Db(Box::new(e)));
+        }
+        match (self.params, self.columns) {
+            (Some(params), Some(columns)) => {
+                let (params, columns, column_types) = statement_types(conn, &params, columns);
+                Ok(conn.statement_cache().statement(name, params, columns, column_types))
+            }
+            _ => Err(ConnectionError::UnexpectedMessage),
+        }
+    }
+}
+
+// Bind + Execute + Sync, collecting every row of the result
+pub(crate) async fn execute(
+    conn: &mut Connection,
+    stmt: &Statement,
+    params: &[&(dyn ToSql + Sync)],
+) -> Result<(Vec<Row>, Option<CommandTag>), ConnectionError> {
+    conn.write_sync(&encode_execute(stmt, params)?).await?;
+    let mut resp = ExecuteResponse::new(stmt);
+    loop {
+        let m = conn.read_message().await?;
+        if resp.push(m).map_err(|m| conn.unexpected(*m))? {
+            return resp.finish();
+        }
+    }
+}
+
+pub(crate) fn encode_execute(
+    stmt: &Statement,
+    params: &[&(dyn ToSql + Sync)],
+) -> Result<Vec<u8>, ConnectionError> {
+    let mut buf = encode_bind(stmt, "", params)?;
+    buf.extend(protocols::to_tagged_message(EXECUTE_TAG, &Execute::new("", 0))?);
+    buf.extend(protocols::to_tagged_message(SYNC_TAG, &SyncMessage {})?);
+    Ok(buf)
+}
+
+// response to Bind + Execute + Sync, built message by message
+pub(crate) struct ExecuteResponse {
+    columns: Arc<Vec<FieldDescription>>,
+    types: Arc<Vec<Type>>,
+    rows: Vec<Row>,
+    tag: Option<CommandTag>,
+    error: Option<DbError>,
+}
+
+impl ExecuteResponse {
+    pub(crate) fn new(stmt: &Statement) -> ExecuteResponse {
+        ExecuteResponse {
+            columns: stmt.shared_columns(),
+            types: stmt.shared_column_types(),
+            rows: vec![],
+            tag: None,
+            error: None,
+        }
+    }
+
+    // true once ReadyForQuery is received, message not belonging
+    // to the response is given back
+    pub(crate) fn push(&mut self, m: BackendMessage) -> Result<bool, Box<BackendMessage>> {
+        match m {
+            BackendMessage::BindComplete | BackendMessage::EmptyQueryResponse => {}
+            BackendMessage::DataRow(data) => {
+                self.rows.push(Row::new(self.columns.clone(), self.types.clone(), data))
+            }
+            BackendMessage::CommandComplete(t) => self.tag = Some(t),
+            // backend skips the rest until Sync
+            BackendMessage::ErrorResponse(e) => self.error = Some(e),
+            BackendMessage::ReadyForQuery(_) => return Ok(true),
+            m => return Err(Box::new(m)),
+        }
+        Ok(false)
+    }
+
+    pub(crate) fn finish(self) -> Result<(Vec<Row>, Option<CommandTag>), ConnectionError> {
+        match self.error {
+            Some(e) => Err(ConnectionError::Db(Box::new(e))),
+            None => Ok((self.rows, self.tag)),
+        }
+    }
+}
+
+// Bind message, parameters in binary format where possible
+pub(crate) fn encode_bind(
+    stmt: &Statement,
+    portal: &str,
+    params: &[&(dyn ToSql + Sync)],
+) -> Result<Vec<u8>, ConnectionError> {
+    if params.len() != stmt.params().len() {
+        return Err(ConnectionError::ParameterCount {
+            expected: stmt.params().len(),
+            actual: params.len(),
+        });
+    }
+
+    // encode every value into single buffer, keeping range of each.
+    // values are sent in binary unless only text is accepted
+    let mut buf = vec![];
+    let mut ranges = Vec::with_capacity(params.len());
+    let mut param_formats = Vec::with_capacity(params.len());
+    for (p, ty) in params.iter().zip(stmt.params()) {
+        let start = buf.len();
+        let is_null = if p.accepts(ty) {
+            param_formats.push(1);
+            p.to_sql(ty, &mut buf)
+        } else if p.accepts_text(ty) {
+            param_formats.push(0);
+            p.to_sql_text(ty, &mut buf)
+        } else {
+            return Err(ConnectionError::Type(TypeError::WrongType {
+                rust: p.type_name(),
+                postgres: ty.clone(),
+            }));
+        };
+        ranges.push(match is_null.map_err(ConnectionError::Type)? {
+            IsNull::Yes => None,
+            IsNull::No => Some(start..buf.len()),
+        });
+    }
+
+    let values: Vec<BindValue> = ranges
+        .into_iter()
+        .map(|r| BindValue(r.map(|r| &buf[r])))
+        .collect();
+    let result_formats: Vec<i16> = stmt.columns().iter().map(|c| c.format).collect();
+    let m = Bind::new(portal, stmt.name(), &param_formats, &values, &result_formats);
+    Ok(protocols::to_tagged_message(BIND_TAG, &m)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::statement::StatementCache;
+
+    #[test]
+    fn test_encode_bind() {
+        let cache = StatementCache::new();
+        let stmt = cache.statement(String::from("s1"), vec![Type::INT4, Type::TEXT], vec![], vec![]);
+        let bytes = encode_bind(&stmt, "", &[&7i32, &"ab"]).unwrap();
+        let expected = vec![
+            b'B', 0, 0, 0, 32, 0, b's', b'1', 0, 0, 2, 0, 1, 0, 1, 0, 2, 0, 0, 0, 4, 0, 0, 0, 7, 0, 0,
+            0, 2, b'a', b'b', 0, 0,
+        ];
+        assert_eq!(expected, bytes);
+    }
+
+    #[test]
+    fn test_encode_bind_wrong_params() {
+        let cache = StatementCache::new();
+        let stmt = cache.statement(String::from("s1"), vec![Type::INT4], vec![], vec![]);
+        match encode_bind(&stmt, "", &[]) {
+            Err(ConnectionError::ParameterCount { expected: 1, actual: 0 }) => {}
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        match encode_bind(&stmt, "", &[&7i64]) {
+            Err(ConnectionError::Type(TypeError::WrongType { rust: "i64", postgres })) => {
+                assert_eq!(Type::INT4, postgres)
+            }
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_is_stale_plan() {
+        let mut e = DbError {
+            severity: String::from("ERROR"),
+            code: String::from("0A000"),
+            message: String::from("le plan en cache ne doit pas modifier le type en résultat"),
+            routine: Some(String::from("RevalidateCachedQuery")),
+            ..DbError::default()
+        };
+        assert!(is_stale_plan(&e));
+        e.routine = Some(String::from("transformLockingClause"));
+        assert!(!is_stale_plan(&e));
+    }
+
+    #[test]
+    fn test_encode_bind_text_fallback() {
+        let cache = StatementCache::new();
+        let stmt = cache.statement(String::from(""), vec![Type::from_oid(1700), Type::INT4], vec![], vec![]);
+        let bytes = encode_bind(&stmt, "", &[&"1.5", &None::<i32>]).unwrap();
+        let expected = vec![
+            b'B', 0, 0, 0, 27, 0, 0, 0, 2, 0, 0, 0, 1, 0, 2, 0, 0, 0, 3, b'1', b'.', b'5', 255, 255,
+            255, 255, 0, 0,
+        ];
+        assert_eq!(expected, bytes);
+    }
+}
@@ -0,0 +1,196 @@
+use super::from_row::{from_row, FromRowError};
+use super::protocols::backend::{DataRow, FieldDescription};
+use super::types::{self, FromSql, Type, TypeError};
+use serde::Deserialize;
+use std::fmt;
+use std::sync::Arc;
+
+// single row of query result
+#[derive(Debug)]
+pub struct Row {
+    // shared by every row of the same result
+    columns: Arc<Vec<FieldDescription>>,
+    types: Arc<Vec<Type>>,
+    data: DataRow,
+}
+
+impl Row {
+    pub(crate) fn new(columns: Arc<Vec<FieldDescription>>, types: Arc<Vec<Type>>, data: DataRow) -> Row {
+        Row { columns, types, data }
+    }
+
+    // description of every column: name, table oid, column number,
+    // type oid and format of the values
+    pub fn columns(&self) -> &[FieldDescription] {
+        &self.columns
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    // value of column given by index or name, panics if the column
+    // does not exist or can not be converted into T
+    pub fn get<'a, I: RowIndex, T: FromSql<'a>>(&'a self, idx: I) -> T {
+        match self.try_get(idx) {
+            Ok(v) => v,
+            Err(e) => panic!("Error getting column value: {}", e),
+        }
+    }
+
+    // value of column given by index or name, the first one with the
+    // name if there are more of them
+    pub fn try_get<'a, I: RowIndex, T: FromSql<'a>>(&'a self, idx: I) -> Result<T, RowError> {
+        let i = idx.index(&self.columns)?;
+        let column = &self.columns[i];
+        let value = types::decode(&self.types[i], column.format == 1, self.data.get(i));
+        value.map_err(|error| RowError::Column {
+            column: column.name.clone(),
+            error,
+        })
+    }
+
+    // raw value of column at idx in the format of the column description,
+    // none if the value is NULL. panics if idx is out of range
+    pub fn get_raw(&self, idx: usize) -> Option<&[u8]> {
+        self.data.get(idx)
+    }
+
+    // whether raw value of column at idx is in binary (or text) format
+    pub fn is_binary(&self, idx: usize) -> bool {
+        self.columns[idx].format == 1
+    }
+
+    pub(crate) fn column_type(&self, idx: usize) -> &Type {
+        &self.types[idx]
+    }
+
+    // whole row deserialized into T, see from_row
+    pub fn deserialize<'a, T: Deserialize<'a>>(&'a self) -> Result<T, FromRowError> {
+        from_row(self)
+    }
+}
+
+// column of a row given either by position or by name
+pub trait RowIndex {
+    fn index(&self, columns: &[FieldDescription]) -> Result<usize, RowError>;
+}
+
+impl RowIndex for usize {
+    fn index(&self, columns: &[FieldDescription]) -> Result<usize, RowError> {
+        if *self < columns.len() {
+            Ok(*self)
+        } else {
+            Err(RowError::OutOfRange {
+                idx: *self,
+                len: columns.len(),
+            })
+        }
+    }
+}
+
+impl RowIndex for &str {
+    fn index(&self, columns: &[FieldDescription]) -> Result<usize, RowError> {
+        columns
+            .iter()
+            .position(|c| c.name == *self)
+            .ok_or_else(|| RowError::UnknownColumn(self.to_string()))
+    }
+}
+
+#[derive(Debug)]
+pub enum RowError {
+    OutOfRange { idx: usize, len: usize },
+    UnknownColumn(String),
+    // value of the column can not be converted into requested type
+    Column { column: String, error: TypeError },
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RowError::OutOfRange { idx, len } => {
+                write!(f, "Column index {} out of range, row has {} columns", idx, len)
+            }
+            RowError::UnknownColumn(name) => write!(f, "Unknown column {}", name),
+            RowError::Column { column, error } => write!(f, "Column {}: {}", column, error),
+        }
+    }
+}
+
+impl std::error::Error for RowError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols::backend::{self, BackendMessage};
+
+    fn column(name: &str, ty: Type, format: i16) -> FieldDescription {
+        FieldDescription {
+            name: name.to_string(),
+            table_oid: 0,
+            column_id: 0,
+            type_oid: ty.oid(),
+            type_size: -1,
+            type_modifier: -1,
+            format,
+        }
+    }
+
+    fn row() -> Row {
+        let columns = vec![
+            column("id", Type::INT4, 1),
+            column("name", Type::TEXT, 1),
+            column("total", Type::INT8, 0),
+            column("note", Type::TEXT, 1),
+        ];
+        let body = vec![
+            0, 4, 0, 0, 0, 4, 0, 0, 0, 7, 0, 0, 0, 2, b'a', b'b', 0, 0, 0, 2, b'4', b'2', 255, 255,
+            255, 255,
+        ];
+        match backend::parse(b'D', body).unwrap() {
+            BackendMessage::DataRow(data) => {
+                let types = columns.iter().map(|c| Type::from_oid(c.type_oid)).collect();
+                Row::new(Arc::new(columns), Arc::new(types), data)
+            }
+            m => panic!("Unexpected message: {:?}", m),
+        }
+    }
+
+    #[test]
+    fn test_get() {
+        let row = row();
+        assert_eq!(4, row.columns().len());
+        assert_eq!(7, row.get::<_, i32>(0));
+        assert_eq!("ab", row.get::<_, &str>("name"));
+        assert_eq!(42, row.get::<_, i64>("total"));
+        assert_eq!("42", row.get::<_, String>(2));
+    }
+
+    #[test]
+    fn test_try_get_errors() {
+        let row = row();
+        match row.try_get::<_, i32>(4) {
+            Err(RowError::OutOfRange { idx: 4, len: 4 }) => {}
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        match row.try_get::<_, i32>("missing") {
+            Err(RowError::UnknownColumn(name)) => assert_eq!("missing", name),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        match row.try_get::<_, i64>("id") {
+            Err(RowError::Column { column, error: TypeError::WrongType { rust: "i64", .. } }) => {
+                assert_eq!("id", column)
+            }
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        match row.try_get::<_, String>("note") {
+            Err(RowError::Column { error: TypeError::UnexpectedNull, .. }) => {}
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+}
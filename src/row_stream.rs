@@ -0,0 +1,122 @@
+use super::cancel::CancelGuard;
+use super::connection::{Connection, ConnectionError};
+use super::protocols::backend::{BackendMessage, FieldDescription};
+use super::protocols::frontend::{Execute, FlushMessage, EXECUTE_TAG, FLUSH_TAG};
+use super::protocols;
+use super::query;
+use super::row::Row;
+use super::statement::{Statement, StatementRef};
+use super::types::{ToSql, Type};
+use futures::stream::{self, Stream};
+use std::sync::Arc;
+
+struct State<'a> {
+    conn: &'a mut Connection,
+    // statement and parameters not bound yet
+    pending: Option<(StatementRef<'a>, &'a [&'a (dyn ToSql + Sync)])>,
+    // kept alive until the portal is done
+    stmt: Option<Statement>,
+    // sql text of cached statement, to invalidate it when stale
+    sql: Option<&'a str>,
+    columns: Arc<Vec<FieldDescription>>,
+    types: Arc<Vec<Type>>,
+    fetch_size: i32,
+    guard: Option<CancelGuard>,
+    done: bool,
+}
+
+// rows are fetched from unnamed portal fetch_size at a time, next batch
+// is only requested once the consumer has drained the current one
+pub(crate) fn query<'a>(
+    conn: &'a mut Connection,
+    statement: StatementRef<'a>,
+    params: &'a [&'a (dyn ToSql + Sync)],
+    fetch_size: usize,
+) -> impl Stream<Item = Result<Row, ConnectionError>> + 'a {
+    let state = State {
+        conn,
+        pending: Some((statement, params)),
+        stmt: None,
+        sql: None,
+        columns: Arc::new(vec![]),
+        types: Arc::new(vec![]),
+        fetch_size: fetch_size.min(i32::MAX as usize) as i32,
+        guard: None,
+        done: false,
+    };
+    stream::unfold(state, |mut state| async move {
+        if state.done {
+            return None;
+        }
+        match state.next().await {
+            Ok(Some(row)) => Some((Ok(row), state)),
+            Ok(None) => None,
+            Err(e) => {
+                state.done = true;
+                // the connection is ready again after an error, unless broken
+                state.conn.release_guard(state.guard.take());
+                Some((Err(e), state))
+            }
+        }
+    })
+}
+
+impl<'a> State<'a> {
+    async fn next(&mut self) -> Result<Option<Row>, ConnectionError> {
+        if let Some((statement, params)) = self.pending.take() {
+            self.conn.ready().await?;
+            self.guard = self.conn.cancel_guard();
+            let stmt = match statement {
+                StatementRef::Prepared(stmt) => stmt.clone(),
+                StatementRef::Sql(sql) => {
+                    self.sql = Some(sql);
+                    query::cached(self.conn, sql).await?
+                }
+            };
+
+            // no Sync until the portal completes, Sync would close it
+            let mut buf = query::encode_bind(&stmt, "", params)?;
+            buf.extend(self.fetch_message()?);
+            self.conn.write_unsynced(&buf).await?;
+            self.columns = stmt.shared_columns();
+            self.types = stmt.shared_column_types();
+            self.stmt = Some(stmt);
+        }
+
+        loop {
+            match self.conn.read_message().await? {
+                BackendMessage::BindComplete => {}
+                BackendMessage::DataRow(data) => return Ok(Some(Row::new(self.columns.clone(), self.types.clone(), data))),
+                BackendMessage::PortalSuspended => {
+                    let buf = self.fetch_message()?;
+                    self.conn.write_unsynced(&buf).await?;
+                }
+                BackendMessage::CommandComplete(_) | BackendMessage::EmptyQueryResponse => {
+                    self.conn.sync().await?;
+                }
+                BackendMessage::ErrorResponse(e) => {
+                    if let (Some(sql), true) = (self.sql, query::is_stale_plan(&e)) {
+                        self.conn.statement_cache().remove(sql);
+                    }
+                    self.conn.sync().await?;
+                    return Err(self.conn.sync_error(e).await);
+                }
+                BackendMessage::ReadyForQuery(_) => {
+                    self.done = true;
+                    if let Some(guard) = self.guard.take() {
+                        guard.disarm();
+                    }
+                    return Ok(None);
+                }
+                m => return Err(self.conn.unexpected(m)),
+            }
+        }
+    }
+
+    // Execute for next batch of rows + Flush
+    fn fetch_message(&self) -> Result<Vec<u8>, ConnectionError> {
+        let mut buf = protocols::to_tagged_message(EXECUTE_TAG, &Execute::new("", self.fetch_size))?;
+        buf.extend(protocols::to_tagged_message(FLUSH_TAG, &FlushMessage {})?);
+        Ok(buf)
+    }
+}
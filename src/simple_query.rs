@@ -0,0 +1,117 @@
+use super::cancel::CancelGuard;
+use super::connection::{Connection, ConnectionError};
+use super::protocols::backend::{BackendMessage, CommandTag, FieldDescription};
+use super::protocols::{self, frontend::Query, frontend::QUERY_TAG};
+use super::row::Row;
+use super::types::Type;
+use futures::stream::{self, Stream};
+use std::sync::Arc;
+
+// result of a single statement of simple query
+#[derive(Debug)]
+pub enum SimpleQueryResult {
+    // statement returning rows (SELECT, SHOW, ... RETURNING)
+    Rows(RowSet),
+    // statement without result rows
+    Command(CommandTag),
+    // query string (or statement) was empty
+    EmptyQuery,
+}
+
+// rows returned by single statement, values are in text format
+#[derive(Debug)]
+pub struct RowSet {
+    pub columns: Arc<Vec<FieldDescription>>,
+    pub rows: Vec<Row>,
+    pub tag: CommandTag,
+}
+
+struct State<'a> {
+    conn: &'a mut Connection,
+    // query not sent yet
+    sql: Option<&'a str>,
+    guard: Option<CancelGuard>,
+    done: bool,
+}
+
+pub(crate) fn query<'a>(
+    conn: &'a mut Connection,
+    sql: &'a str,
+) -> impl Stream<Item = Result<SimpleQueryResult, ConnectionError>> + 'a {
+    let state = State {
+        conn,
+        sql: Some(sql),
+        guard: None,
+        done: false,
+    };
+    stream::unfold(state, |mut state| async move {
+        if state.done {
+            return None;
+        }
+        state.next().await.map(|item| (item, state))
+    })
+}
+
+impl<'a> State<'a> {
+    async fn next(&mut self) -> Option<Result<SimpleQueryResult, ConnectionError>> {
+        match self.advance().await {
+            Ok(Some(r)) => Some(Ok(r)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            // backend skips the rest of the statements after an error and
+            // continues to ReadyForQuery, which is read on next poll (or
+            // next use of the connection if dropped), so nothing is left
+            // to cancel. any other error leaves nothing to drain
+            Err(ConnectionError::Db(e)) => {
+                self.conn.release_guard(self.guard.take());
+                Some(Err(ConnectionError::Db(e)))
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+
+    async fn advance(&mut self) -> Result<Option<SimpleQueryResult>, ConnectionError> {
+        if let Some(sql) = self.sql.take() {
+            self.conn.ready().await?;
+            let m = protocols::to_tagged_message(QUERY_TAG, &Query::new(sql))?;
+            self.guard = self.conn.cancel_guard();
+            self.conn.write_sync(&m).await?;
+        }
+
+        // description and types of the columns of current statement
+        let mut columns = None;
+        let mut rows = vec![];
+        loop {
+            match self.conn.read_message().await? {
+                BackendMessage::RowDescription(c) => {
+                    let types: Vec<Type> = c.iter().map(|c| self.conn.known_type(c.type_oid)).collect();
+                    columns = Some((Arc::new(c), Arc::new(types)))
+                }
+                BackendMessage::DataRow(r) => match &columns {
+                    Some((c, t)) => rows.push(Row::new(c.clone(), t.clone(), r)),
+                    None => return Err(self.conn.unexpected(BackendMessage::DataRow(r))),
+                },
+                BackendMessage::CommandComplete(tag) => {
+                    return Ok(Some(match columns.take() {
+                        Some((columns, _)) => SimpleQueryResult::Rows(RowSet { columns, rows, tag }),
+                        None => SimpleQueryResult::Command(tag),
+                    }))
+                }
+                BackendMessage::EmptyQueryResponse => return Ok(Some(SimpleQueryResult::EmptyQuery)),
+                BackendMessage::ErrorResponse(e) => return Err(ConnectionError::Db(Box::new(e))),
+                BackendMessage::ReadyForQuery(_) => {
+                    if let Some(guard) = self.guard.take() {
+                        guard.disarm();
+                    }
+                    return Ok(None);
+                }
+                m => return Err(self.conn.unexpected(m)),
+            }
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use super::protocols::backend::FieldDescription;
+use super::types::Type;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Weak};
+
+const DEF_CACHE_CAPACITY: usize = 100;
+
+// statement parsed by the backend, with the types of its parameters
+// and result columns. only valid on the connection which prepared it
+#[derive(Debug, Clone)]
+pub struct Statement(Arc<Inner>);
+
+#[derive(Debug)]
+struct Inner {
+    // empty for unnamed statement
+    name: String,
+    params: Vec<Type>,
+    // formats set to the ones requested when executing
+    columns: Arc<Vec<FieldDescription>>,
+    column_types: Arc<Vec<Type>>,
+    // connection closes the statement once every clone is dropped
+    closed: Weak<Mutex<Vec<String>>>,
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        if self.name.is_empty() {
+            return;
+        }
+        if let Some(closed) = self.closed.upgrade() {
+            closed.lock().unwrap().push(std::mem::take(&mut self.name));
+        }
+    }
+}
+
+impl Statement {
+    pub fn params(&self) -> &[Type] {
+        &self.0.params
+    }
+
+    pub fn columns(&self) -> &[FieldDescription] {
+        &self.0.columns
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    pub fn column_types(&self) -> &[Type] {
+        &self.0.column_types
+    }
+
+    pub(crate) fn shared_columns(&self) -> Arc<Vec<FieldDescription>> {
+        self.0.columns.clone()
+    }
+
+    pub(crate) fn shared_column_types(&self) -> Arc<Vec<Type>> {
+        self.0.column_types.clone()
+    }
+
+    // replace types once user-defined ones are looked up, only possible
+    // before the statement is shared
+    pub(crate) fn set_types(
+        &mut self,
+        params: Vec<Type>,
+        columns: Vec<FieldDescription>,
+        column_types: Vec<Type>,
+    ) {
+        if let Some(inner) = Arc::get_mut(&mut self.0) {
+            inner.params = params;
+            inner.columns = Arc::new(columns);
+            inner.column_types = Arc::new(column_types);
+        }
+    }
+}
+
+// statement given as sql text, going through the statement cache
+// of the connection, or already prepared
+pub enum StatementRef<'a> {
+    Sql(&'a str),
+    Prepared(&'a Statement),
+}
+
+pub trait ToStatement {
+    fn to_statement(&self) -> StatementRef<'_>;
+}
+
+impl ToStatement for str {
+    fn to_statement(&self) -> StatementRef<'_> {
+        StatementRef::Sql(self)
+    }
+}
+
+impl ToStatement for String {
+    fn to_statement(&self) -> StatementRef<'_> {
+        StatementRef::Sql(self)
+    }
+}
+
+impl ToStatement for Statement {
+    fn to_statement(&self) -> StatementRef<'_> {
+        StatementRef::Prepared(self)
+    }
+}
+
+// per connection cache of named statements keyed by sql text,
+// least recently used statement is evicted when full
+pub(crate) struct StatementCache {
+    capacity: usize,
+    // statement and its last use tick
+    entries: HashMap<String, (Statement, u64)>,
+    tick: u64,
+    // used to generate unique statement names
+    counter: u64,
+    // names of statements dropped but not closed yet
+    closed: Arc<Mutex<Vec<String>>>,
+}
+
+impl StatementCache {
+    pub(crate) fn new() -> StatementCache {
+        StatementCache {
+            capacity: DEF_CACHE_CAPACITY,
+            entries: HashMap::new(),
+            tick: 0,
+            counter: 0,
+            closed: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.entries.len() > capacity {
+            self.evict();
+        }
+    }
+
+    pub(crate) fn get(&mut self, sql: &str) -> Option<Statement> {
+        self.tick += 1;
+        let tick = self.tick;
+        self.entries.get_mut(sql).map(|e| {
+            e.1 = tick;
+            e.0.clone()
+        })
+    }
+
+    pub(crate) fn insert(&mut self, sql: &str, stmt: Statement) {
+        if self.capacity == 0 {
+            return;
+        }
+        while self.entries.len() >= self.capacity {
+            self.evict();
+        }
+        self.tick += 1;
+        self.entries.insert(sql.to_string(), (stmt, self.tick));
+    }
+
+    pub(crate) fn remove(&mut self, sql: &str) {
+        self.entries.remove(sql);
+    }
+
+    fn evict(&mut self) {
+        let lru = self
+            .entries
+            .iter()
+            .min_by_key(|(_, e)| e.1)
+            .map(|(sql, _)| sql.clone());
+        if let Some(sql) = lru {
+            debug!("Evicting statement from cache: {}", sql);
+            self.entries.remove(&sql);
+        }
+    }
+
+    // name for the next statement, empty (unnamed) when caching is disabled
+    pub(crate) fn next_name(&mut self) -> String {
+        if self.capacity == 0 {
+            return String::new();
+        }
+        self.unique_name()
+    }
+
+    // name for the next statement, even when caching is disabled
+    pub(crate) fn unique_name(&mut self) -> String {
+        self.counter += 1;
+        format!("s{}", self.counter)
+    }
+
+    pub(crate) fn statement(
+        &self,
+        name: String,
+        params: Vec<Type>,
+        columns: Vec<FieldDescription>,
+        column_types: Vec<Type>,
+    ) -> Statement {
+        Statement(Arc::new(Inner {
+            name,
+            params,
+            columns: Arc::new(columns),
+            column_types: Arc::new(column_types),
+            closed: Arc::downgrade(&self.closed),
+        }))
+    }
+
+    // statements to be closed on the backend
+    pub(crate) fn take_closed(&self) -> Vec<String> {
+        std::mem::take(&mut *self.closed.lock().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prepare(cache: &mut StatementCache, sql: &str) -> Statement {
+        let name = cache.next_name();
+        let stmt = cache.statement(name, vec![], vec![], vec![]);
+        cache.insert(sql, stmt.clone());
+        stmt
+    }
+
+    #[test]
+    fn test_cache_evicts_least_recently_used() {
+        let mut cache = StatementCache::new();
+        cache.set_capacity(2);
+        drop(prepare(&mut cache, "a"));
+        drop(prepare(&mut cache, "b"));
+        assert!(cache.get("a").is_some());
+        drop(prepare(&mut cache, "c"));
+
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("c").is_some());
+        assert_eq!(vec![String::from("s2")], cache.take_closed());
+        assert!(cache.take_closed().is_empty());
+    }
+
+    #[test]
+    fn test_statement_closed_after_last_clone_dropped() {
+        let mut cache = StatementCache::new();
+        cache.set_capacity(1);
+        let a = prepare(&mut cache, "a");
+        drop(prepare(&mut cache, "b"));
+        assert!(cache.take_closed().is_empty());
+        drop(a);
+        assert_eq!(vec![String::from("s1")], cache.take_closed());
+    }
+
+    #[test]
+    fn test_cache_disabled() {
+        let mut cache = StatementCache::new();
+        cache.set_capacity(0);
+        let a = prepare(&mut cache, "a");
+        assert_eq!("", a.name());
+        assert!(cache.get("a").is_none());
+        drop(a);
+        assert!(cache.take_closed().is_empty());
+    }
+}
@@ -0,0 +1,597 @@
+use super::binary_copy::{column, invalid};
+use super::connection::{Connection, ConnectionError};
+use super::copy::{self, CopyIn};
+use super::from_row::from_row;
+use super::protocols::backend::{self, BackendMessage, FieldDescription};
+use super::row::Row;
+use super::to_params::{to_params, Param};
+use super::types::{IsNull, ToSql, Type};
+use futures::stream::{self, Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::sync::Arc;
+
+// options of text and csv COPY formats, to match those of the statement.
+// the constructors give the defaults of the backend
+#[derive(Debug, Clone)]
+pub struct TextCopyOptions {
+    pub csv: bool,
+    pub delimiter: u8,
+    // quote and escape apply to csv only
+    pub quote: u8,
+    pub escape: u8,
+    // NULL as written unquoted
+    pub null: String,
+    // first line names the columns
+    pub header: bool,
+}
+
+impl TextCopyOptions {
+    pub fn text() -> TextCopyOptions {
+        TextCopyOptions {
+            csv: false,
+            delimiter: b'\t',
+            quote: b'"',
+            escape: b'"',
+            null: String::from("\\N"),
+            header: false,
+        }
+    }
+
+    pub fn csv() -> TextCopyOptions {
+        TextCopyOptions {
+            csv: true,
+            delimiter: b',',
+            quote: b'"',
+            escape: b'"',
+            null: String::new(),
+            header: false,
+        }
+    }
+}
+
+// type whose text form the value is written in
+fn text_type(p: &Param) -> Type {
+    match p {
+        Param::Bool(_) => Type::BOOL,
+        Param::Int(_) => Type::INT8,
+        Param::Float(_) => Type::FLOAT8,
+        Param::Bytes(_) => Type::BYTEA,
+        Param::Array(_) => Type::TEXT_ARRAY,
+        Param::Json(_) => Type::JSON,
+        Param::Null | Param::Text(_) => Type::TEXT,
+    }
+}
+
+// rows of COPY ... FROM STDIN in text or csv format, each serialized
+// from struct (or map) with a field per column in order, see to_params
+pub struct TextCopyEncoder {
+    options: TextCopyOptions,
+    buf: Vec<u8>,
+    // header is written before the first row
+    started: bool,
+}
+
+impl TextCopyEncoder {
+    pub fn new(options: TextCopyOptions) -> TextCopyEncoder {
+        TextCopyEncoder {
+            options,
+            buf: vec![],
+            started: false,
+        }
+    }
+
+    pub fn write<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ConnectionError> {
+        let fields = to_params(value).map_err(ConnectionError::ToParams)?;
+        if !self.started && self.options.header {
+            for (i, (name, _)) in fields.iter().enumerate() {
+                self.delimit(i);
+                self.field(name.as_bytes());
+            }
+            self.buf.push(b'\n');
+        }
+        self.started = true;
+
+        let start = self.buf.len();
+        let mut value = vec![];
+        for (i, (_, p)) in fields.iter().enumerate() {
+            self.delimit(i);
+            value.clear();
+            match p.to_sql_text(&text_type(p), &mut value) {
+                Ok(IsNull::Yes) => self.buf.extend(self.options.null.as_bytes()),
+                Ok(IsNull::No) => self.field(&value),
+                Err(e) => {
+                    self.buf.truncate(start);
+                    return Err(ConnectionError::Type(e));
+                }
+            }
+        }
+        self.buf.push(b'\n');
+        Ok(())
+    }
+
+    // data encoded since last taken
+    pub fn take(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.buf)
+    }
+
+    fn delimit(&mut self, idx: usize) {
+        if idx > 0 {
+            self.buf.push(self.options.delimiter);
+        }
+    }
+
+    fn field(&mut self, v: &[u8]) {
+        let o = &self.options;
+        if !o.csv {
+            for &c in v {
+                match c {
+                    b'\\' => self.buf.extend(b"\\\\"),
+                    b'\n' => self.buf.extend(b"\\n"),
+                    b'\r' => self.buf.extend(b"\\r"),
+                    b'\t' => self.buf.extend(b"\\t"),
+                    c if c == o.delimiter => self.buf.extend(&[b'\\', c]),
+                    c => self.buf.push(c),
+                }
+            }
+            return;
+        }
+
+        // quoted when it could be read as anything else, NULL included
+        let special = [o.delimiter, o.quote, b'\n', b'\r'];
+        if v != o.null.as_bytes() && !v.iter().any(|c| special.contains(c)) {
+            self.buf.extend(v);
+            return;
+        }
+        self.buf.push(o.quote);
+        for &c in v {
+            if c == o.quote || c == o.escape {
+                self.buf.push(o.escape);
+            }
+            self.buf.push(c);
+        }
+        self.buf.push(o.quote);
+    }
+}
+
+// CopyIn of text or csv format, rows encoded as they are written
+pub struct TextCopyWriter<'a> {
+    copy: CopyIn<'a>,
+    encoder: TextCopyEncoder,
+}
+
+impl<'a> TextCopyWriter<'a> {
+    pub fn new(copy: CopyIn<'a>, options: TextCopyOptions) -> TextCopyWriter<'a> {
+        TextCopyWriter {
+            copy,
+            encoder: TextCopyEncoder::new(options),
+        }
+    }
+
+    pub async fn write<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ConnectionError> {
+        self.encoder.write(value)?;
+        let data = self.encoder.take();
+        self.copy.send(&data).await
+    }
+
+    // number of rows copied, see CopyIn::finish
+    pub async fn finish(self) -> Result<u64, ConnectionError> {
+        self.copy.finish().await
+    }
+}
+
+// rows of COPY ... TO STDOUT in text or csv format (or of a csv file),
+// fed with the data as it arrives. values are text, read as &str or
+// String, or parsed into numbers when deserialized. columns are named
+// by with_names, by the header, or have no name
+pub struct TextCopyDecoder {
+    options: TextCopyOptions,
+    columns: Option<Arc<Vec<FieldDescription>>>,
+    types: Arc<Vec<Type>>,
+    buf: Vec<u8>,
+    // start of unread data in buf
+    pos: usize,
+    // header line is yet to be read
+    header: bool,
+    // no more data is coming
+    ended: bool,
+}
+
+type Record = Vec<Option<Vec<u8>>>;
+
+impl TextCopyDecoder {
+    pub fn new(options: TextCopyOptions) -> TextCopyDecoder {
+        TextCopyDecoder {
+            header: options.header,
+            options,
+            columns: None,
+            types: Arc::new(vec![]),
+            buf: vec![],
+            pos: 0,
+            ended: false,
+        }
+    }
+
+    pub fn with_names(mut self, names: &[&str]) -> TextCopyDecoder {
+        self.set_columns(names);
+        self
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        if self.pos > 0 && self.pos >= self.buf.len() / 2 {
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+        }
+        self.buf.extend_from_slice(data);
+    }
+
+    // all the data is pushed, the last row may lack its line end
+    pub fn end(&mut self) {
+        self.ended = true;
+    }
+
+    // next row, none until all of it is pushed
+    pub fn read_row(&mut self) -> Result<Option<Row>, ConnectionError> {
+        loop {
+            let (record, len) = match self.record()? {
+                Some(r) => r,
+                None => return Ok(None),
+            };
+            self.pos += len;
+            match &self.columns {
+                // names given by with_names take precedence
+                Some(_) if self.header => {
+                    self.header = false;
+                    continue;
+                }
+                None if self.header => {
+                    self.header = false;
+                    let names: Vec<String> =
+                        record.iter().map(|f| String::from_utf8_lossy(f.as_deref().unwrap_or_default()).into()).collect();
+                    let names: Vec<&str> = names.iter().map(String::as_str).collect();
+                    self.set_columns(&names);
+                    continue;
+                }
+                Some(c) if c.len() != record.len() => {
+                    return Err(invalid(format!("row of {} fields, {} columns expected", record.len(), c.len())))
+                }
+                Some(_) => {}
+                None => self.set_columns(&vec![""; record.len()]),
+            }
+            return self.row(record).map(Some);
+        }
+    }
+
+    // next row deserialized into T, see from_row
+    pub fn read<T: DeserializeOwned>(&mut self) -> Result<Option<T>, ConnectionError> {
+        match self.read_row()? {
+            Some(row) => from_row(&row).map(Some).map_err(ConnectionError::FromRow),
+            None => Ok(None),
+        }
+    }
+
+    fn set_columns(&mut self, names: &[&str]) {
+        self.columns = Some(Arc::new(names.iter().map(|n| column(n, &Type::TEXT, 0)).collect()));
+        self.types = Arc::new(vec![Type::TEXT; names.len()]);
+    }
+
+    // fields in DataRow layout
+    fn row(&self, record: Record) -> Result<Row, ConnectionError> {
+        let mut body = (record.len() as i16).to_be_bytes().to_vec();
+        for f in record {
+            match f {
+                Some(f) => {
+                    body.extend(&(f.len() as i32).to_be_bytes());
+                    body.extend(f);
+                }
+                None => body.extend(&(-1i32).to_be_bytes()),
+            }
+        }
+        let columns = self.columns.clone().unwrap_or_default();
+        match backend::parse(b'D', body).map_err(ConnectionError::Deserialize)? {
+            BackendMessage::DataRow(data) => Ok(Row::new(columns, self.types.clone(), data)),
+            _ => Err(ConnectionError::Unknown),
+        }
+    }
+
+    // fields of the next complete record and its length in the data,
+    // skipping end of data marker
+    fn record(&self) -> Result<Option<(Record, usize)>, ConnectionError> {
+        let data = &self.buf[self.pos..];
+        let r = if self.options.csv {
+            self.csv_record(data)?
+        } else {
+            self.text_record(data)
+        };
+        match r {
+            Some((_, len)) if is_end_marker(&data[..len]) => Ok(None),
+            r => Ok(r),
+        }
+    }
+
+    fn text_record(&self, data: &[u8]) -> Option<(Record, usize)> {
+        let (line, len) = match data.iter().position(|c| *c == b'\n') {
+            Some(end) => (&data[..end], end + 1),
+            None if self.ended && !data.is_empty() => (data, data.len()),
+            None => return None,
+        };
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let mut record = vec![];
+        let mut start = 0;
+        let mut i = 0;
+        while i <= line.len() {
+            match line.get(i) {
+                Some(b'\\') => i += 2,
+                Some(c) if *c != self.options.delimiter => i += 1,
+                // delimiter or line end
+                _ => {
+                    let raw = &line[start..i.min(line.len())];
+                    record.push(match raw == self.options.null.as_bytes() {
+                        true => None,
+                        false => Some(unescape(raw)),
+                    });
+                    i += 1;
+                    start = i;
+                }
+            }
+        }
+        Some((record, len))
+    }
+
+    fn csv_record(&self, data: &[u8]) -> Result<Option<(Record, usize)>, ConnectionError> {
+        let o = &self.options;
+        let mut record = vec![];
+        let mut field = vec![];
+        let mut quoted = false;
+        let mut in_quotes = false;
+        let mut i = 0;
+        loop {
+            let c = match data.get(i) {
+                Some(c) => *c,
+                None if !self.ended || data.is_empty() => return Ok(None),
+                None if in_quotes => return Err(invalid(String::from("unterminated csv quoted field"))),
+                None => b'\n',
+            };
+            let next = data.get(i + 1).copied();
+            if in_quotes {
+                if c == o.escape && (next == Some(o.quote) || (next == Some(o.escape) && o.escape != o.quote)) {
+                    field.push(next.unwrap_or_default());
+                    i += 2;
+                    continue;
+                }
+                // escape equal to quote may be closing quote or doubled
+                // quote, can't tell until the next byte arrives
+                if c == o.escape && next.is_none() && !self.ended {
+                    return Ok(None);
+                }
+                if c == o.quote {
+                    in_quotes = false;
+                } else {
+                    field.push(c);
+                }
+                i += 1;
+                continue;
+            }
+            match c {
+                c if c == o.quote => {
+                    in_quotes = true;
+                    quoted = true;
+                }
+                c if c == o.delimiter || c == b'\n' => {
+                    let value = std::mem::take(&mut field);
+                    record.push(match !quoted && value == o.null.as_bytes() {
+                        true => None,
+                        false => Some(value),
+                    });
+                    quoted = false;
+                    if c == b'\n' {
+                        return Ok(Some((record, (i + 1).min(data.len()))));
+                    }
+                }
+                b'\r' if next == Some(b'\n') => {}
+                c => field.push(c),
+            }
+            i += 1;
+        }
+    }
+}
+
+// \. line, ending the data in older protocol versions
+fn is_end_marker(line: &[u8]) -> bool {
+    matches!(line, b"\\.\n" | b"\\.\r\n" | b"\\.")
+}
+
+// backslash sequences of text format
+fn unescape(raw: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(raw.len());
+    let mut i = 0;
+    while i < raw.len() {
+        let c = raw[i];
+        i += 1;
+        if c != b'\\' || i == raw.len() {
+            out.push(c);
+            continue;
+        }
+        let e = raw[i];
+        i += 1;
+        match e {
+            b'b' => out.push(8),
+            b'f' => out.push(12),
+            b'n' => out.push(b'\n'),
+            b'r' => out.push(b'\r'),
+            b't' => out.push(b'\t'),
+            b'v' => out.push(11),
+            b'0'..=b'7' => {
+                // up to 3 digits
+                let mut v = u32::from(e - b'0');
+                let end = (i + 2).min(raw.len());
+                while i < end && (b'0'..=b'7').contains(&raw[i]) {
+                    v = v * 8 + u32::from(raw[i] - b'0');
+                    i += 1;
+                }
+                out.push(v as u8);
+            }
+            b'x' if raw.get(i).is_some_and(u8::is_ascii_hexdigit) => {
+                let digits = raw[i..].iter().take(2).take_while(|c| c.is_ascii_hexdigit()).count();
+                let hex = std::str::from_utf8(&raw[i..i + digits]).unwrap_or_default();
+                out.push(u8::from_str_radix(hex, 16).unwrap_or_default());
+                i += digits;
+            }
+            e => out.push(e),
+        }
+    }
+    out
+}
+
+struct CopyOut<S> {
+    data: S,
+    decoder: TextCopyDecoder,
+    failed: bool,
+}
+
+// rows of COPY ... TO STDOUT in text or csv format decoded as they arrive
+pub(crate) fn copy_out<'a>(
+    conn: &'a mut Connection,
+    sql: &'a str,
+    decoder: TextCopyDecoder,
+) -> impl Stream<Item = Result<Row, ConnectionError>> + 'a {
+    let state = CopyOut {
+        data: Box::pin(copy::copy_out(conn, sql)),
+        decoder,
+        failed: false,
+    };
+    stream::unfold(state, |mut state| async move {
+        if state.failed {
+            return None;
+        }
+        loop {
+            let r = match state.decoder.read_row() {
+                Ok(Some(row)) => return Some((Ok(row), state)),
+                Ok(None) if state.decoder.ended => return None,
+                Ok(None) => match state.data.next().await {
+                    Some(Ok(data)) => {
+                        state.decoder.push(&data);
+                        continue;
+                    }
+                    Some(Err(e)) => Err(e),
+                    None => {
+                        state.decoder.end();
+                        continue;
+                    }
+                },
+                Err(e) => Err(e),
+            };
+            state.failed = true;
+            return Some((r, state));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Visit {
+        id: i64,
+        page: Option<String>,
+        score: f64,
+        tags: String,
+    }
+
+    fn visits() -> Vec<Visit> {
+        vec![
+            Visit {
+                id: 1,
+                page: Some(String::from("a,\"b\"\nc\\d\te")),
+                score: 0.5,
+                tags: String::from("{x,\"y z\"}"),
+            },
+            Visit {
+                id: 2,
+                page: None,
+                score: -1.0,
+                tags: String::new(),
+            },
+            Visit {
+                id: 3,
+                page: Some(String::new()),
+                score: 2.0,
+                tags: String::from("\\N"),
+            },
+        ]
+    }
+
+    fn decode(mut decoder: TextCopyDecoder, data: &[u8], chunk: usize) -> Vec<Visit> {
+        let mut out = vec![];
+        for c in data.chunks(chunk) {
+            decoder.push(c);
+            while let Some(v) = decoder.read().unwrap() {
+                out.push(v);
+            }
+        }
+        decoder.end();
+        while let Some(v) = decoder.read().unwrap() {
+            out.push(v);
+        }
+        out
+    }
+
+    #[test]
+    fn test_text() {
+        let options = TextCopyOptions::text();
+        let mut encoder = TextCopyEncoder::new(options.clone());
+        for v in visits() {
+            encoder.write(&v).unwrap();
+        }
+        let data = encoder.take();
+        assert!(data.starts_with(b"1\ta,\"b\"\\nc\\\\d\\te\t0.5\t{x,\"y z\"}\n2\t\\N\t-1\t\n"));
+
+        let names = ["id", "page", "score", "tags"];
+        for chunk in [1, 3, data.len()] {
+            let decoder = TextCopyDecoder::new(options.clone()).with_names(&names);
+            assert_eq!(visits(), decode(decoder, &data, chunk));
+        }
+
+        // escapes written by other tools, no final line end
+        let mut decoder = TextCopyDecoder::new(options).with_names(&["a", "b"]);
+        decoder.push(b"\\101\\x42\\,\\q\t\\N\r\n\\.\n");
+        decoder.end();
+        let row = decoder.read_row().unwrap().unwrap();
+        assert_eq!("AB,q", row.get::<_, &str>("a"));
+        assert_eq!(None, row.get::<_, Option<&str>>("b"));
+        assert!(decoder.read_row().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_csv() {
+        let mut options = TextCopyOptions::csv();
+        options.header = true;
+        let mut encoder = TextCopyEncoder::new(options.clone());
+        for v in visits() {
+            encoder.write(&v).unwrap();
+        }
+        let data = encoder.take();
+        let expected = "id,page,score,tags\n1,\"a,\"\"b\"\"\nc\\d\te\",0.5,\"{x,\"\"y z\"\"}\"\n2,,-1,\"\"\n3,\"\",2,\\N\n";
+        assert_eq!(expected.as_bytes(), &data[..]);
+
+        // columns named by the header
+        for chunk in [1, 2, data.len()] {
+            assert_eq!(visits(), decode(TextCopyDecoder::new(options.clone()), &data, chunk));
+        }
+
+        options.delimiter = b';';
+        options.escape = b'\\';
+        options.null = String::from("NULL");
+        let mut decoder = TextCopyDecoder::new(options).with_names(&["a", "b", "c"]);
+        decoder.push(b"x;y\r\n\"q\\\"\";NULL;\"NULL\"\nlast;\"open");
+        let row = decoder.read_row().unwrap().unwrap();
+        assert_eq!(Some("q\""), row.get::<_, Option<&str>>("a"));
+        assert_eq!(None, row.get::<_, Option<&str>>("b"));
+        assert_eq!(Some("NULL"), row.get::<_, Option<&str>>("c"));
+        assert!(decoder.read_row().unwrap().is_none());
+        decoder.end();
+        assert!(decoder.read_row().is_err());
+    }
+}
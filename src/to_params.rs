@@ -0,0 +1,631 @@
+use super::types::{IsNull, Json, ToSql, Type, TypeError};
+use serde::ser::{self, Impossible, Serialize};
+use serde_json::value::Serializer as JsonSerializer;
+use serde_json::Value;
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+
+type SResult<T> = Result<T, ToParamsError>;
+
+// serialize struct (or map) into named parameters, one per field.
+// nested structs, maps and enums with data become json
+pub fn to_params<T: Serialize + ?Sized>(value: &T) -> Result<Vec<(String, Param)>, ToParamsError> {
+    let mut fields = Fields { params: vec![], key: None };
+    value.serialize(&mut fields)?;
+    Ok(fields.params)
+}
+
+// value of single field, bound as any postgres type it fits:
+// numbers as numeric types, strings as text which the backend parses
+// into the type of the parameter, sequences as arrays
+#[derive(Debug, Clone, PartialEq)]
+pub enum Param {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+    Bytes(Vec<u8>),
+    Array(Vec<Param>),
+    Json(Value),
+}
+
+impl Param {
+    fn to_json(&self) -> Value {
+        match self {
+            Param::Null => Value::Null,
+            Param::Bool(v) => Value::from(*v),
+            Param::Int(v) => Value::from(*v),
+            Param::Float(v) => Value::from(*v),
+            Param::Text(v) => Value::from(v.as_str()),
+            Param::Bytes(v) => Value::from(v.as_slice()),
+            Param::Array(v) => Value::Array(v.iter().map(Param::to_json).collect()),
+            Param::Json(v) => v.clone(),
+        }
+    }
+
+    fn is_json(ty: &Type) -> bool {
+        matches!(*ty, Type::JSON | Type::JSONB)
+    }
+}
+
+fn out_of_range(v: i64, ty: &Type) -> TypeError {
+    TypeError::InvalidValue(format!("{} out of range for {}", v, ty))
+}
+
+impl ToSql for Param {
+    fn accepts(&self, ty: &Type) -> bool {
+        match self {
+            Param::Null => true,
+            _ if Param::is_json(ty) => true,
+            Param::Bool(_) => *ty == Type::BOOL,
+            Param::Int(_) => matches!(*ty, Type::INT2 | Type::INT4 | Type::INT8 | Type::OID | Type::FLOAT4 | Type::FLOAT8),
+            Param::Float(_) => matches!(*ty, Type::FLOAT4 | Type::FLOAT8),
+            Param::Text(v) => v.accepts(ty),
+            Param::Bytes(v) => v.accepts(ty),
+            Param::Array(v) => v.accepts(ty),
+            Param::Json(_) => false,
+        }
+    }
+
+    fn to_sql(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        match self {
+            Param::Null => Ok(IsNull::Yes),
+            _ if Param::is_json(ty) => Json(self.to_json()).to_sql(ty, out),
+            Param::Bool(v) => v.to_sql(ty, out),
+            Param::Int(v) => match *ty {
+                Type::INT2 => i16::try_from(*v).map_err(|_| out_of_range(*v, ty))?.to_sql(ty, out),
+                Type::INT4 => i32::try_from(*v).map_err(|_| out_of_range(*v, ty))?.to_sql(ty, out),
+                Type::OID => u32::try_from(*v).map_err(|_| out_of_range(*v, ty))?.to_sql(ty, out),
+                Type::FLOAT4 => (*v as f32).to_sql(ty, out),
+                Type::FLOAT8 => (*v as f64).to_sql(ty, out),
+                _ => v.to_sql(ty, out),
+            },
+            Param::Float(v) if *ty == Type::FLOAT4 => (*v as f32).to_sql(ty, out),
+            Param::Float(v) => v.to_sql(ty, out),
+            Param::Text(v) => v.to_sql(ty, out),
+            Param::Bytes(v) => v.to_sql(ty, out),
+            Param::Array(v) => v.to_sql(ty, out),
+            Param::Json(_) => Err(TypeError::wrong_type::<Self>(ty)),
+        }
+    }
+
+    // numbers and strings as text of any type, e.g. numeric or date
+    fn accepts_text(&self, ty: &Type) -> bool {
+        match self {
+            Param::Null | Param::Int(_) | Param::Float(_) | Param::Text(_) => true,
+            _ if Param::is_json(ty) => true,
+            Param::Bool(v) => v.accepts_text(ty),
+            Param::Bytes(v) => v.accepts_text(ty),
+            Param::Array(v) => v.accepts_text(ty),
+            Param::Json(_) => false,
+        }
+    }
+
+    fn to_sql_text(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        match self {
+            Param::Null => Ok(IsNull::Yes),
+            _ if Param::is_json(ty) => Json(self.to_json()).to_sql_text(ty, out),
+            Param::Int(v) => {
+                out.extend(v.to_string().as_bytes());
+                Ok(IsNull::No)
+            }
+            Param::Float(v) => v.to_sql_text(&Type::FLOAT8, out),
+            Param::Bool(v) => v.to_sql_text(ty, out),
+            Param::Text(v) => v.to_sql_text(ty, out),
+            Param::Bytes(v) => v.to_sql_text(ty, out),
+            Param::Array(v) => v.to_sql_text(ty, out),
+            Param::Json(_) => Err(TypeError::wrong_type::<Self>(ty)),
+        }
+    }
+}
+
+// top level struct or map, each field serialized into Param
+struct Fields {
+    params: Vec<(String, Param)>,
+    // key of the map entry whose value comes next
+    key: Option<String>,
+}
+
+impl Fields {
+    fn push<T: Serialize + ?Sized>(&mut self, name: String, value: &T) -> SResult<()> {
+        let param = value.serialize(ParamSerializer)?;
+        match self.params.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, p)) => *p = param,
+            None => self.params.push((name, param)),
+        }
+        Ok(())
+    }
+}
+
+fn not_struct<T>() -> SResult<T> {
+    Err(ToParamsError::Custom(String::from("parameters must be a struct or map")))
+}
+
+macro_rules! not_struct {
+    ($($fn:ident($($arg:ty),*))*) => {
+        $(
+            fn $fn(self, $(_: $arg),*) -> SResult<()> {
+                not_struct()
+            }
+        )*
+    };
+}
+
+impl ser::Serializer for &mut Fields {
+    type Ok = ();
+    type Error = ToParamsError;
+    type SerializeSeq = Impossible<(), ToParamsError>;
+    type SerializeTuple = Impossible<(), ToParamsError>;
+    type SerializeTupleStruct = Impossible<(), ToParamsError>;
+    type SerializeTupleVariant = Impossible<(), ToParamsError>;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Impossible<(), ToParamsError>;
+
+    not_struct!(
+        serialize_bool(bool) serialize_i8(i8) serialize_i16(i16) serialize_i32(i32)
+        serialize_i64(i64) serialize_u8(u8) serialize_u16(u16) serialize_u32(u32)
+        serialize_u64(u64) serialize_f32(f32) serialize_f64(f64) serialize_char(char)
+        serialize_str(&str) serialize_bytes(&[u8]) serialize_none() serialize_unit()
+        serialize_unit_struct(&'static str)
+        serialize_unit_variant(&'static str, u32, &'static str)
+    );
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> SResult<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> SResult<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _idx: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> SResult<()> {
+        not_struct()
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> SResult<Self::SerializeSeq> {
+        not_struct()
+    }
+
+    fn serialize_tuple(self, _len: usize) -> SResult<Self::SerializeTuple> {
+        not_struct()
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> SResult<Self::SerializeTupleStruct> {
+        not_struct()
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _idx: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> SResult<Self::SerializeTupleVariant> {
+        not_struct()
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> SResult<Self> {
+        Ok(self)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> SResult<Self> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _idx: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> SResult<Self::SerializeStructVariant> {
+        not_struct()
+    }
+}
+
+impl ser::SerializeStruct for &mut Fields {
+    type Ok = ();
+    type Error = ToParamsError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> SResult<()> {
+        self.push(key.to_string(), value)
+    }
+
+    fn end(self) -> SResult<()> {
+        Ok(())
+    }
+}
+
+// used by flattened structs as well
+impl ser::SerializeMap for &mut Fields {
+    type Ok = ();
+    type Error = ToParamsError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> SResult<()> {
+        match key.serialize(ParamSerializer)? {
+            Param::Text(key) => self.key = Some(key),
+            _ => return Err(ToParamsError::Custom(String::from("parameter name must be a string"))),
+        }
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> SResult<()> {
+        let key = self.key.take().expect("value without key");
+        self.push(key, value)
+    }
+
+    fn end(self) -> SResult<()> {
+        Ok(())
+    }
+}
+
+// value of single field
+struct ParamSerializer;
+
+fn int<T: TryInto<i64> + fmt::Display + Copy>(v: T) -> SResult<Param> {
+    v.try_into()
+        .map(Param::Int)
+        .map_err(|_| ToParamsError::Custom(format!("{} out of range for bigint", v)))
+}
+
+impl ser::Serializer for ParamSerializer {
+    type Ok = Param;
+    type Error = ToParamsError;
+    type SerializeSeq = Seq;
+    type SerializeTuple = Seq;
+    type SerializeTupleStruct = Seq;
+    type SerializeTupleVariant = ToJson<<JsonSerializer as ser::Serializer>::SerializeTupleVariant>;
+    type SerializeMap = ToJson<<JsonSerializer as ser::Serializer>::SerializeMap>;
+    type SerializeStruct = ToJson<<JsonSerializer as ser::Serializer>::SerializeStruct>;
+    type SerializeStructVariant = ToJson<<JsonSerializer as ser::Serializer>::SerializeStructVariant>;
+
+    fn serialize_bool(self, v: bool) -> SResult<Param> {
+        Ok(Param::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> SResult<Param> {
+        int(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> SResult<Param> {
+        int(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> SResult<Param> {
+        int(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> SResult<Param> {
+        int(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> SResult<Param> {
+        int(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> SResult<Param> {
+        int(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> SResult<Param> {
+        int(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> SResult<Param> {
+        int(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> SResult<Param> {
+        Ok(Param::Float(v.into()))
+    }
+
+    fn serialize_f64(self, v: f64) -> SResult<Param> {
+        Ok(Param::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> SResult<Param> {
+        Ok(Param::Text(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> SResult<Param> {
+        Ok(Param::Text(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> SResult<Param> {
+        Ok(Param::Bytes(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> SResult<Param> {
+        Ok(Param::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> SResult<Param> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> SResult<Param> {
+        Ok(Param::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> SResult<Param> {
+        Ok(Param::Null)
+    }
+
+    // unit variant by its name, e.g. as postgres enum label
+    fn serialize_unit_variant(self, _name: &'static str, _idx: u32, variant: &'static str) -> SResult<Param> {
+        Ok(Param::Text(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> SResult<Param> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        idx: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> SResult<Param> {
+        JsonSerializer
+            .serialize_newtype_variant(name, idx, variant, value)
+            .map(Param::Json)
+            .map_err(ToParamsError::json)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> SResult<Seq> {
+        Ok(Seq(Vec::with_capacity(len.unwrap_or(0))))
+    }
+
+    fn serialize_tuple(self, len: usize) -> SResult<Seq> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> SResult<Seq> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        idx: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> SResult<Self::SerializeTupleVariant> {
+        JsonSerializer
+            .serialize_tuple_variant(name, idx, variant, len)
+            .map(ToJson)
+            .map_err(ToParamsError::json)
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> SResult<Self::SerializeMap> {
+        JsonSerializer.serialize_map(len).map(ToJson).map_err(ToParamsError::json)
+    }
+
+    fn serialize_struct(self, name: &'static str, len: usize) -> SResult<Self::SerializeStruct> {
+        JsonSerializer.serialize_struct(name, len).map(ToJson).map_err(ToParamsError::json)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        idx: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> SResult<Self::SerializeStructVariant> {
+        JsonSerializer
+            .serialize_struct_variant(name, idx, variant, len)
+            .map(ToJson)
+            .map_err(ToParamsError::json)
+    }
+}
+
+struct Seq(Vec<Param>);
+
+impl Seq {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> SResult<()> {
+        self.0.push(value.serialize(ParamSerializer)?);
+        Ok(())
+    }
+}
+
+impl ser::SerializeSeq for Seq {
+    type Ok = Param;
+    type Error = ToParamsError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> SResult<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> SResult<Param> {
+        Ok(Param::Array(self.0))
+    }
+}
+
+impl ser::SerializeTuple for Seq {
+    type Ok = Param;
+    type Error = ToParamsError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> SResult<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> SResult<Param> {
+        Ok(Param::Array(self.0))
+    }
+}
+
+impl ser::SerializeTupleStruct for Seq {
+    type Ok = Param;
+    type Error = ToParamsError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> SResult<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> SResult<Param> {
+        Ok(Param::Array(self.0))
+    }
+}
+
+// nested value serialized by serde_json
+struct ToJson<S>(S);
+
+impl<S: ser::SerializeTupleVariant<Ok = Value, Error = serde_json::Error>> ser::SerializeTupleVariant for ToJson<S> {
+    type Ok = Param;
+    type Error = ToParamsError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> SResult<()> {
+        self.0.serialize_field(value).map_err(ToParamsError::json)
+    }
+
+    fn end(self) -> SResult<Param> {
+        self.0.end().map(Param::Json).map_err(ToParamsError::json)
+    }
+}
+
+impl<S: ser::SerializeMap<Ok = Value, Error = serde_json::Error>> ser::SerializeMap for ToJson<S> {
+    type Ok = Param;
+    type Error = ToParamsError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> SResult<()> {
+        self.0.serialize_key(key).map_err(ToParamsError::json)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> SResult<()> {
+        self.0.serialize_value(value).map_err(ToParamsError::json)
+    }
+
+    fn end(self) -> SResult<Param> {
+        self.0.end().map(Param::Json).map_err(ToParamsError::json)
+    }
+}
+
+impl<S: ser::SerializeStruct<Ok = Value, Error = serde_json::Error>> ser::SerializeStruct for ToJson<S> {
+    type Ok = Param;
+    type Error = ToParamsError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> SResult<()> {
+        self.0.serialize_field(key, value).map_err(ToParamsError::json)
+    }
+
+    fn end(self) -> SResult<Param> {
+        self.0.end().map(Param::Json).map_err(ToParamsError::json)
+    }
+}
+
+impl<S: ser::SerializeStructVariant<Ok = Value, Error = serde_json::Error>> ser::SerializeStructVariant
+    for ToJson<S>
+{
+    type Ok = Param;
+    type Error = ToParamsError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> SResult<()> {
+        self.0.serialize_field(key, value).map_err(ToParamsError::json)
+    }
+
+    fn end(self) -> SResult<Param> {
+        self.0.end().map(Param::Json).map_err(ToParamsError::json)
+    }
+}
+
+#[derive(Debug)]
+pub enum ToParamsError {
+    // placeholder without field (or value) of the same name
+    MissingField(String),
+    Custom(String),
+}
+
+impl ToParamsError {
+    fn json(e: serde_json::Error) -> ToParamsError {
+        ToParamsError::Custom(e.to_string())
+    }
+}
+
+impl fmt::Display for ToParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ToParamsError::MissingField(name) => write!(f, "No value for parameter :{}", name),
+            ToParamsError::Custom(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ToParamsError {}
+
+impl ser::Error for ToParamsError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        ToParamsError::Custom(msg.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize)]
+    struct Order {
+        id: i32,
+        #[serde(rename = "customer")]
+        name: &'static str,
+        note: Option<String>,
+        tags: Vec<&'static str>,
+        #[serde(flatten)]
+        extra: BTreeMap<&'static str, f64>,
+        address: Address,
+    }
+
+    #[derive(Serialize)]
+    struct Address {
+        city: &'static str,
+    }
+
+    #[test]
+    fn test_to_params() {
+        let order = Order {
+            id: 7,
+            name: "ann",
+            note: None,
+            tags: vec!["a", "b"],
+            extra: vec![("total", 1.5)].into_iter().collect(),
+            address: Address { city: "Oslo" },
+        };
+        let params = to_params(&order).unwrap();
+        let expected = vec![
+            ("id".to_string(), Param::Int(7)),
+            ("customer".to_string(), Param::Text("ann".to_string())),
+            ("note".to_string(), Param::Null),
+            ("tags".to_string(), Param::Array(vec![Param::Text("a".to_string()), Param::Text("b".to_string())])),
+            ("total".to_string(), Param::Float(1.5)),
+            ("address".to_string(), Param::Json(serde_json::json!({"city": "Oslo"}))),
+        ];
+        assert_eq!(expected, params);
+        assert!(to_params(&7).is_err());
+    }
+
+    #[test]
+    fn test_param_to_sql() {
+        let mut out = vec![];
+        Param::Int(7).to_sql(&Type::INT2, &mut out).unwrap();
+        assert_eq!(vec![0, 7], out);
+        assert!(Param::Int(70000).to_sql(&Type::INT2, &mut vec![]).is_err());
+        assert!(!Param::Int(7).accepts(&Type::NUMERIC));
+        assert!(Param::Int(7).accepts_text(&Type::NUMERIC));
+
+        let mut out = vec![];
+        let tags = Param::Array(vec![Param::Text("a".to_string())]);
+        tags.to_sql(&Type::JSONB, &mut out).unwrap();
+        assert_eq!(b"\x01[\"a\"]".to_vec(), out);
+        assert!(tags.accepts(&Type::TEXT_ARRAY));
+    }
+}
@@ -0,0 +1,68 @@
+use super::connection::{Connection, ConnectionError};
+use super::simple_query::SimpleQueryResult;
+use super::types::{CustomType, Field, Kind, Type};
+use futures::future::BoxFuture;
+use futures::TryStreamExt;
+
+// oids below are assigned by initdb, every type above is user-defined
+// (CREATE TYPE, extensions)
+const FIRST_NORMAL_OID: u32 = 16384;
+
+// user-defined type the connection has no description of yet
+pub(crate) fn is_unknown(conn: &Connection, oid: u32) -> bool {
+    oid >= FIRST_NORMAL_OID && conn.known_type(oid).custom().is_none()
+}
+
+// describe type from the catalog and keep it in the type cache of the
+// connection, along with the types it refers to
+pub(crate) fn lookup(conn: &mut Connection, oid: u32) -> BoxFuture<'_, Result<Type, ConnectionError>> {
+    Box::pin(async move {
+        let sql = format!(
+            "SELECT t.typname, n.nspname, t.typtype, t.typcategory, t.typelem \
+             FROM pg_catalog.pg_type t \
+             JOIN pg_catalog.pg_namespace n ON n.oid = t.typnamespace WHERE t.oid = {oid}; \
+             SELECT enumlabel FROM pg_catalog.pg_enum WHERE enumtypid = {oid} ORDER BY enumsortorder; \
+             SELECT a.attname, a.atttypid FROM pg_catalog.pg_attribute a \
+             JOIN pg_catalog.pg_type t ON t.typrelid = a.attrelid \
+             WHERE t.oid = {oid} AND a.attnum > 0 AND NOT a.attisdropped ORDER BY a.attnum",
+            oid = oid
+        );
+        let mut results = conn.simple_query(&sql).try_collect::<Vec<_>>().await?.into_iter();
+        let mut rows = || match results.next() {
+            Some(SimpleQueryResult::Rows(r)) => Ok(r.rows),
+            _ => Err(ConnectionError::UnexpectedMessage),
+        };
+        let (ty, labels, attributes) = (rows()?, rows()?, rows()?);
+        // dropped meanwhile
+        let ty = match ty.first() {
+            Some(ty) => ty,
+            None => return Ok(Type::from_oid(oid)),
+        };
+
+        let kind = match (ty.get::<_, &str>(2), ty.get::<_, &str>(3)) {
+            ("e", _) => Kind::Enum(labels.iter().map(|r| r.get(0)).collect()),
+            ("c", _) => {
+                let mut fields = vec![];
+                for a in &attributes {
+                    fields.push(Field::new(a.get(0), resolve(conn, a.get(1)).await?));
+                }
+                Kind::Composite(fields)
+            }
+            (_, "A") => Kind::Array(resolve(conn, ty.get(4)).await?),
+            _ => Kind::Other,
+        };
+        let custom = Type::from_custom(CustomType::new(oid, ty.get(0), ty.get(1), kind));
+        debug!("Type looked up: {:?}", custom);
+        conn.type_cache().insert(oid, custom.clone());
+        Ok(custom)
+    })
+}
+
+// type referred to by another one, looked up if unknown
+async fn resolve(conn: &mut Connection, oid: u32) -> Result<Type, ConnectionError> {
+    if is_unknown(conn, oid) {
+        lookup(conn, oid).await
+    } else {
+        Ok(conn.known_type(oid))
+    }
+}
@@ -0,0 +1,410 @@
+use super::{decode, FromSql, IsNull, ToSql, Type, TypeError};
+use std::convert::{TryFrom, TryInto};
+
+// size and first index of one dimension of an array
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dimension {
+    pub len: i32,
+    pub lower_bound: i32,
+}
+
+// array of any number of dimensions, elements kept flat in row-major
+// order. elements which may be NULL need Option<T>
+#[derive(Debug, Clone, PartialEq)]
+pub struct Array<T> {
+    dims: Vec<Dimension>,
+    elements: Vec<T>,
+}
+
+impl<T> Array<T> {
+    // one dimensional array indexed from 1
+    pub fn from_vec(elements: Vec<T>) -> Array<T> {
+        let dims = if elements.is_empty() {
+            vec![]
+        } else {
+            vec![Dimension {
+                len: elements.len() as i32,
+                lower_bound: 1,
+            }]
+        };
+        Array { dims, elements }
+    }
+
+    // panics if number of elements does not match the dimensions
+    pub fn from_parts(elements: Vec<T>, dims: Vec<Dimension>) -> Array<T> {
+        let expected = if dims.is_empty() {
+            0
+        } else {
+            dims.iter().map(|d| d.len as usize).product()
+        };
+        assert_eq!(expected, elements.len(), "Array size does not match its dimensions");
+        Array { dims, elements }
+    }
+
+    pub fn dimensions(&self) -> &[Dimension] {
+        &self.dims
+    }
+
+    pub fn elements(&self) -> &[T] {
+        &self.elements
+    }
+
+    pub fn into_elements(self) -> Vec<T> {
+        self.elements
+    }
+
+    // element at the given index of every dimension, counted from the
+    // lower bound of the dimension as in postgres
+    pub fn get(&self, indices: &[i32]) -> Option<&T> {
+        if indices.len() != self.dims.len() || self.dims.is_empty() {
+            return None;
+        }
+        let mut pos = 0;
+        for (i, d) in indices.iter().zip(&self.dims) {
+            let offset = i.checked_sub(d.lower_bound)?;
+            if offset < 0 || offset >= d.len {
+                return None;
+            }
+            pos = pos * d.len as usize + offset as usize;
+        }
+        self.elements.get(pos)
+    }
+}
+
+fn element_type(ty: &Type) -> Result<Type, TypeError> {
+    ty.element().ok_or_else(|| TypeError::InvalidValue(format!("{} is not an array type", ty)))
+}
+
+// binary format: number of dimensions, NULL flag, element oid,
+// size and lower bound of each dimension, then every element
+// prefixed with its length, -1 for NULL
+fn encode<T: ToSql>(
+    ty: &Type,
+    dims: &[Dimension],
+    elements: &[T],
+    out: &mut Vec<u8>,
+) -> Result<IsNull, TypeError> {
+    let elem = element_type(ty)?;
+    out.extend(&(dims.len() as i32).to_be_bytes());
+    let flag = out.len();
+    out.extend(&0i32.to_be_bytes());
+    out.extend(&elem.oid().to_be_bytes());
+    for d in dims {
+        out.extend(&d.len.to_be_bytes());
+        out.extend(&d.lower_bound.to_be_bytes());
+    }
+
+    let mut has_null = false;
+    for e in elements {
+        let start = out.len();
+        out.extend(&0i32.to_be_bytes());
+        let len = match e.to_sql(&elem, out)? {
+            IsNull::Yes => {
+                has_null = true;
+                -1
+            }
+            IsNull::No => i32::try_from(out.len() - start - 4)
+                .map_err(|_| TypeError::InvalidValue(String::from("array element too large")))?,
+        };
+        out[start..start + 4].copy_from_slice(&len.to_be_bytes());
+    }
+    if has_null {
+        out[flag..flag + 4].copy_from_slice(&1i32.to_be_bytes());
+    }
+    Ok(IsNull::No)
+}
+
+// text format: nested braces, every element quoted, dimensions
+// prefixed with their bounds unless every lower bound is 1
+fn encode_text<T: ToSql>(
+    ty: &Type,
+    dims: &[Dimension],
+    elements: &[T],
+    out: &mut Vec<u8>,
+) -> Result<IsNull, TypeError> {
+    let elem = element_type(ty)?;
+    if dims.iter().any(|d| d.lower_bound != 1) {
+        for d in dims {
+            let upper = d.lower_bound + d.len - 1;
+            out.extend(format!("[{}:{}]", d.lower_bound, upper).as_bytes());
+        }
+        out.push(b'=');
+    }
+    if dims.is_empty() {
+        out.extend(b"{}");
+        return Ok(IsNull::No);
+    }
+    encode_text_dim(&elem, dims, elements, out)?;
+    Ok(IsNull::No)
+}
+
+fn encode_text_dim<T: ToSql>(
+    elem: &Type,
+    dims: &[Dimension],
+    elements: &[T],
+    out: &mut Vec<u8>,
+) -> Result<(), TypeError> {
+    out.push(b'{');
+    let chunk = elements.len() / dims[0].len.max(1) as usize;
+    for (i, part) in elements.chunks(chunk.max(1)).enumerate() {
+        if i > 0 {
+            out.push(b',');
+        }
+        if dims.len() > 1 {
+            encode_text_dim(elem, &dims[1..], part, out)?;
+            continue;
+        }
+        let mut value = vec![];
+        match part[0].to_sql_text(elem, &mut value)? {
+            IsNull::Yes => out.extend(b"NULL"),
+            IsNull::No => {
+                out.push(b'"');
+                for b in value {
+                    if b == b'"' || b == b'\\' {
+                        out.push(b'\\');
+                    }
+                    out.push(b);
+                }
+                out.push(b'"');
+            }
+        }
+    }
+    out.push(b'}');
+    Ok(())
+}
+
+struct Reader<'a> {
+    raw: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], TypeError> {
+        if self.raw.len() < n {
+            return Err(TypeError::InvalidValue(String::from("truncated array")));
+        }
+        let (head, rest) = self.raw.split_at(n);
+        self.raw = rest;
+        Ok(head)
+    }
+
+    fn i32(&mut self) -> Result<i32, TypeError> {
+        Ok(i32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+}
+
+fn decode_array<'a, T: FromSql<'a>>(ty: &Type, raw: &'a [u8]) -> Result<Array<T>, TypeError> {
+    let mut r = Reader { raw };
+    let ndim = r.i32()?;
+    if !(0..=6).contains(&ndim) {
+        return Err(TypeError::InvalidValue(format!("array of {} dimensions", ndim)));
+    }
+    let _has_null = r.i32()?;
+    let oid = r.i32()? as u32;
+    // element oid of the payload carries no description of user-defined type
+    let elem = match ty.element() {
+        Some(e) if e.oid() == oid => e,
+        _ => return Err(TypeError::InvalidValue(String::from("unexpected array element type"))),
+    };
+
+    let mut dims = Vec::with_capacity(ndim as usize);
+    let mut count: usize = if ndim == 0 { 0 } else { 1 };
+    for _ in 0..ndim {
+        let d = Dimension {
+            len: r.i32()?,
+            lower_bound: r.i32()?,
+        };
+        count = usize::try_from(d.len)
+            .ok()
+            .and_then(|len| count.checked_mul(len))
+            .ok_or_else(|| TypeError::InvalidValue(String::from("invalid array dimension")))?;
+        dims.push(d);
+    }
+
+    // every element takes at least its length
+    if count > r.raw.len() / 4 {
+        return Err(TypeError::InvalidValue(String::from("truncated array")));
+    }
+    let mut elements = Vec::with_capacity(count);
+    for _ in 0..count {
+        let value = match r.i32()? {
+            -1 => None,
+            len => Some(r.bytes(len as usize)?),
+        };
+        elements.push(decode(&elem, true, value)?);
+    }
+    Ok(Array { dims, elements })
+}
+
+// elements are checked one by one, any value accepts empty array
+fn accepts_all<T: ToSql>(ty: &Type, elements: &[T], text: bool) -> bool {
+    match ty.element() {
+        Some(e) if text => elements.iter().all(|v| v.accepts_text(&e)),
+        Some(e) => elements.iter().all(|v| v.accepts(&e)),
+        None => false,
+    }
+}
+
+impl<T: ToSql> ToSql for Array<T> {
+    fn accepts(&self, ty: &Type) -> bool {
+        accepts_all(ty, &self.elements, false)
+    }
+
+    fn to_sql(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        encode(ty, &self.dims, &self.elements, out)
+    }
+
+    fn accepts_text(&self, ty: &Type) -> bool {
+        accepts_all(ty, &self.elements, true)
+    }
+
+    fn to_sql_text(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        encode_text(ty, &self.dims, &self.elements, out)
+    }
+
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+impl<'a, T: FromSql<'a>> FromSql<'a> for Array<T> {
+    fn accepts(ty: &Type) -> bool {
+        ty.element().is_some_and(|e| T::accepts(&e))
+    }
+
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        decode_array(ty, raw)
+    }
+}
+
+impl<T: ToSql> ToSql for &[T] {
+    fn accepts(&self, ty: &Type) -> bool {
+        accepts_all(ty, self, false)
+    }
+
+    fn to_sql(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        encode(ty, &one_dim(self), self, out)
+    }
+
+    fn accepts_text(&self, ty: &Type) -> bool {
+        accepts_all(ty, self, true)
+    }
+
+    fn to_sql_text(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        encode_text(ty, &one_dim(self), self, out)
+    }
+
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+fn one_dim<T>(elements: &[T]) -> Vec<Dimension> {
+    if elements.is_empty() {
+        return vec![];
+    }
+    vec![Dimension {
+        len: elements.len() as i32,
+        lower_bound: 1,
+    }]
+}
+
+impl<T: ToSql> ToSql for Vec<T> {
+    fn accepts(&self, ty: &Type) -> bool {
+        self.as_slice().accepts(ty)
+    }
+
+    fn to_sql(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        self.as_slice().to_sql(ty, out)
+    }
+
+    fn accepts_text(&self, ty: &Type) -> bool {
+        self.as_slice().accepts_text(ty)
+    }
+
+    fn to_sql_text(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        self.as_slice().to_sql_text(ty, out)
+    }
+
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+// only arrays of at most one dimension, lower bound is dropped
+impl<'a, T: FromSql<'a>> FromSql<'a> for Vec<T> {
+    fn accepts(ty: &Type) -> bool {
+        Array::<T>::accepts(ty)
+    }
+
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        let array = Array::from_sql(ty, raw)?;
+        if array.dims.len() > 1 {
+            return Err(TypeError::InvalidValue(format!(
+                "array of {} dimensions into Vec",
+                array.dims.len()
+            )));
+        }
+        Ok(array.elements)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vec() {
+        let v = vec![Some(1i32), None, Some(3)];
+        let mut out = vec![];
+        assert!(v.accepts(&Type::INT4_ARRAY));
+        v.to_sql(&Type::INT4_ARRAY, &mut out).unwrap();
+        let expected = vec![
+            0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 23, 0, 0, 0, 3, 0, 0, 0, 1, 0, 0, 0, 4, 0, 0, 0, 1, 255,
+            255, 255, 255, 0, 0, 0, 4, 0, 0, 0, 3,
+        ];
+        assert_eq!(expected, out);
+        assert_eq!(v, decode::<Vec<Option<i32>>>(&Type::INT4_ARRAY, true, Some(&out)).unwrap());
+        match decode::<Vec<i32>>(&Type::INT4_ARRAY, true, Some(&out)) {
+            Err(TypeError::UnexpectedNull) => {}
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        assert!(!v.accepts(&Type::INT8_ARRAY));
+
+        let mut out = vec![];
+        Vec::<i64>::new().to_sql(&Type::INT8_ARRAY, &mut out).unwrap();
+        assert_eq!(vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 20], out);
+        assert!(decode::<Vec<i64>>(&Type::INT8_ARRAY, true, Some(&out)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_multi_dimensional() {
+        let dims = vec![
+            Dimension { len: 2, lower_bound: 0 },
+            Dimension { len: 3, lower_bound: 1 },
+        ];
+        let array = Array::from_parts(vec!["a", "b", "c", "d", "e", "f"], dims);
+        assert_eq!(Some(&"f"), array.get(&[1, 3]));
+        assert_eq!(None, array.get(&[2, 1]));
+
+        let mut out = vec![];
+        array.to_sql(&Type::TEXT_ARRAY, &mut out).unwrap();
+        let back: Array<String> = decode(&Type::TEXT_ARRAY, true, Some(&out)).unwrap();
+        assert_eq!(array.dimensions(), back.dimensions());
+        assert_eq!(array.elements(), back.elements());
+        assert!(decode::<Vec<String>>(&Type::TEXT_ARRAY, true, Some(&out)).is_err());
+
+        let mut out = vec![];
+        array.to_sql_text(&Type::TEXT_ARRAY, &mut out).unwrap();
+        assert_eq!(&b"[0:1][1:3]={{\"a\",\"b\",\"c\"},{\"d\",\"e\",\"f\"}}"[..], &out[..]);
+    }
+
+    #[test]
+    fn test_text() {
+        let v = vec![Some("1.5"), None, Some("a\"b\\")];
+        assert!(!v.accepts(&Type::NUMERIC_ARRAY));
+        assert!(v.accepts_text(&Type::NUMERIC_ARRAY));
+        let mut out = vec![];
+        v.to_sql_text(&Type::NUMERIC_ARRAY, &mut out).unwrap();
+        assert_eq!(&b"{\"1.5\",NULL,\"a\\\"b\\\\\"}"[..], &out[..]);
+    }
+}
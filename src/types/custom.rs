@@ -0,0 +1,246 @@
+use super::Type;
+
+// user-defined type as described by pg_type, looked up by the
+// connection the first time a statement uses it
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct CustomType {
+    oid: u32,
+    name: String,
+    schema: String,
+    kind: Kind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Kind {
+    // labels in sort order
+    Enum(Vec<String>),
+    // attributes in order
+    Composite(Vec<Field>),
+    Array(Type),
+    // base, domain, range and pseudo types, exchanged in text format
+    Other,
+}
+
+// attribute of composite type
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Field {
+    name: String,
+    ty: Type,
+}
+
+impl CustomType {
+    pub(crate) fn new(oid: u32, name: String, schema: String, kind: Kind) -> CustomType {
+        CustomType {
+            oid,
+            name,
+            schema,
+            kind,
+        }
+    }
+
+    pub fn oid(&self) -> u32 {
+        self.oid
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn schema(&self) -> &str {
+        &self.schema
+    }
+
+    pub fn kind(&self) -> &Kind {
+        &self.kind
+    }
+}
+
+impl Field {
+    pub(crate) fn new(name: String, ty: Type) -> Field {
+        Field { name, ty }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn type_(&self) -> &Type {
+        &self.ty
+    }
+}
+
+// used by code generated with derive(ToSql, FromSql)
+#[doc(hidden)]
+pub mod private {
+    use super::super::{FromSql, IsNull, ToSql, Type, TypeError};
+    use super::{Field, Kind};
+    use std::convert::{TryFrom, TryInto};
+
+    pub fn decode<'a, T: FromSql<'a>>(ty: &Type, binary: bool, raw: Option<&'a [u8]>) -> Result<T, TypeError> {
+        super::super::decode(ty, binary, raw)
+    }
+
+    // enum type of the name with exactly the given labels
+    pub fn enum_accepts(ty: &Type, name: &str, labels: &[&str]) -> bool {
+        match ty.custom() {
+            Some(c) if c.name() == name => match c.kind() {
+                Kind::Enum(l) => l.len() == labels.len() && labels.iter().all(|v| l.iter().any(|l| l == v)),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    // attributes of composite type of the name
+    pub fn composite_fields<'t>(ty: &'t Type, name: &str) -> Option<&'t [Field]> {
+        match ty.custom() {
+            Some(c) if c.name() == name => match c.kind() {
+                Kind::Composite(fields) => Some(fields),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // composite value starts with the number of attributes, then each
+    // one is prefixed with its type oid and length, -1 for NULL
+    pub fn write_count(count: usize, out: &mut Vec<u8>) {
+        out.extend(&(count as i32).to_be_bytes());
+    }
+
+    pub fn write_field(ty: &Type, value: &dyn ToSql, out: &mut Vec<u8>) -> Result<(), TypeError> {
+        out.extend(&ty.oid().to_be_bytes());
+        let start = out.len();
+        out.extend(&0i32.to_be_bytes());
+        let len = match value.to_sql(ty, out)? {
+            IsNull::Yes => -1,
+            IsNull::No => i32::try_from(out.len() - start - 4)
+                .map_err(|_| TypeError::InvalidValue(String::from("composite attribute too large")))?,
+        };
+        out[start..start + 4].copy_from_slice(&len.to_be_bytes());
+        Ok(())
+    }
+
+    pub struct CompositeReader<'a> {
+        raw: &'a [u8],
+    }
+
+    impl<'a> CompositeReader<'a> {
+        // checks the number of attributes matches the type
+        pub fn new(raw: &'a [u8], fields: &[Field]) -> Result<CompositeReader<'a>, TypeError> {
+            let mut r = CompositeReader { raw };
+            let count = i32::from_be_bytes(r.bytes(4)?.try_into().unwrap());
+            if count as usize != fields.len() {
+                return Err(TypeError::InvalidValue(format!(
+                    "composite of {} attributes, expected {}",
+                    count,
+                    fields.len()
+                )));
+            }
+            Ok(r)
+        }
+
+        fn bytes(&mut self, n: usize) -> Result<&'a [u8], TypeError> {
+            if self.raw.len() < n {
+                return Err(TypeError::InvalidValue(String::from("truncated composite")));
+            }
+            let (head, rest) = self.raw.split_at(n);
+            self.raw = rest;
+            Ok(head)
+        }
+
+        // raw value of next attribute, none if NULL
+        pub fn next(&mut self, field: &Field) -> Result<Option<&'a [u8]>, TypeError> {
+            let oid = u32::from_be_bytes(self.bytes(4)?.try_into().unwrap());
+            if oid != field.type_().oid() {
+                return Err(TypeError::InvalidValue(format!("unexpected type of attribute {}", field.name())));
+            }
+            match i32::from_be_bytes(self.bytes(4)?.try_into().unwrap()) {
+                -1 => Ok(None),
+                len => Ok(Some(self.bytes(len as usize)?)),
+            }
+        }
+    }
+
+    pub fn missing_field(name: &str) -> TypeError {
+        TypeError::InvalidValue(format!("missing attribute {}", name))
+    }
+}
+
+#[cfg(all(test, feature = "derive"))]
+mod tests {
+    use super::super::{decode, FromSql, ToSql};
+    use super::*;
+
+    #[derive(Debug, PartialEq, ToSql, FromSql)]
+    enum OrderStatus {
+        Pending,
+        #[postgres(name = "shipped")]
+        Sent,
+    }
+
+    #[derive(Debug, PartialEq, ToSql, FromSql)]
+    #[postgres(name = "line_item")]
+    struct Item {
+        sku: String,
+        #[postgres(name = "qty")]
+        quantity: Option<i32>,
+        status: OrderStatus,
+    }
+
+    fn status_type() -> Type {
+        let labels = vec![String::from("pending"), String::from("shipped")];
+        Type::from_custom(CustomType::new(20000, "order_status".into(), "public".into(), Kind::Enum(labels)))
+    }
+
+    fn item_type() -> Type {
+        let fields = vec![
+            Field::new("status".into(), status_type()),
+            Field::new("sku".into(), Type::TEXT),
+            Field::new("qty".into(), Type::INT4),
+        ];
+        Type::from_custom(CustomType::new(20001, "line_item".into(), "public".into(), Kind::Composite(fields)))
+    }
+
+    #[test]
+    fn test_enum() {
+        let ty = status_type();
+        let mut out = vec![];
+        assert!(OrderStatus::Sent.accepts(&ty));
+        OrderStatus::Sent.to_sql(&ty, &mut out).unwrap();
+        assert_eq!(b"shipped", &out[..]);
+        assert_eq!(OrderStatus::Pending, decode(&ty, false, Some(b"pending")).unwrap());
+        assert!(decode::<OrderStatus>(&ty, true, Some(b"lost")).is_err());
+
+        // different labels or not an enum at all
+        let other = Type::from_custom(CustomType::new(
+            20002,
+            "order_status".into(),
+            "public".into(),
+            Kind::Enum(vec![String::from("pending")]),
+        ));
+        assert!(!<OrderStatus as FromSql>::accepts(&other));
+        assert!(!OrderStatus::Pending.accepts(&Type::TEXT));
+    }
+
+    #[test]
+    fn test_composite() {
+        let ty = item_type();
+        assert!(ty.supports_binary());
+        let item = Item {
+            sku: String::from("ab"),
+            quantity: None,
+            status: OrderStatus::Pending,
+        };
+        assert!(item.accepts(&ty));
+        let mut out = vec![];
+        item.to_sql(&ty, &mut out).unwrap();
+        let mut expected = vec![0, 0, 0, 3, 0, 0, 0x4E, 0x20, 0, 0, 0, 7];
+        expected.extend(b"pending");
+        expected.extend(&[0, 0, 0, 25, 0, 0, 0, 2, b'a', b'b', 0, 0, 0, 23, 255, 255, 255, 255]);
+        assert_eq!(expected, out);
+        assert_eq!(item, decode(&ty, true, Some(&out)).unwrap());
+        assert!(decode::<Item>(&ty, true, Some(&out[..out.len() - 1])).is_err());
+        assert!(decode::<Item>(&ty, false, Some(b"(pending,ab,)")).is_err());
+    }
+}
@@ -0,0 +1,503 @@
+use super::{FromSql, IsNull, ToSql, Type, TypeError};
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// postgres counts from 2000-01-01 00:00:00 UTC
+pub(crate) const PG_EPOCH_SECS: u64 = 946_684_800;
+pub(crate) const USECS_PER_DAY: i64 = 86_400_000_000;
+// days from 1970-01-01 to 2000-01-01
+const UNIX_TO_PG_DAYS: i64 = 10_957;
+
+fn pg_epoch() -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(PG_EPOCH_SECS)
+}
+
+// value which may also be one of the infinities postgres supports
+// for timestamp and timestamptz
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timestamp<T> {
+    PosInfinity,
+    NegInfinity,
+    Value(T),
+}
+
+// same as Timestamp, for date
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Date<T> {
+    PosInfinity,
+    NegInfinity,
+    Value(T),
+}
+
+// date as days since 2000-01-01, negative before, when neither chrono
+// nor time is used. displayed as YYYY-MM-DD
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EpochDays(pub i32);
+
+impl EpochDays {
+    // proleptic gregorian date, year 0 being 1 BC
+    pub fn from_ymd(year: i32, month: u32, day: u32) -> Option<EpochDays> {
+        if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+            return None;
+        }
+        // days from civil, with years starting in march
+        let y = i64::from(year) - i64::from(month <= 2);
+        let era = y.div_euclid(400);
+        let yoe = y - era * 400;
+        let mp = (i64::from(month) + 9) % 12;
+        let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146_097 + doe - 719_468 - UNIX_TO_PG_DAYS;
+        i32::try_from(days).ok().map(EpochDays)
+    }
+
+    pub fn ymd(self) -> (i32, u32, u32) {
+        let z = i64::from(self.0) + UNIX_TO_PG_DAYS + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + i64::from(month <= 2);
+        (year as i32, month, day)
+    }
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl fmt::Display for EpochDays {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (year, month, day) = self.ymd();
+        if year > 0 {
+            write!(f, "{:04}-{:02}-{:02}", year, month, day)
+        } else {
+            write!(f, "{:04}-{:02}-{:02} BC", 1 - year, month, day)
+        }
+    }
+}
+
+// interval keeps months and days apart from the time part,
+// their length depends on the date the interval is applied to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Interval {
+    pub months: i32,
+    pub days: i32,
+    pub microseconds: i64,
+}
+
+pub(crate) fn out_of_range(ty: &Type) -> TypeError {
+    TypeError::InvalidValue(format!("value out of range for {}", ty))
+}
+
+// microseconds since postgres epoch of finite timestamp
+pub(crate) fn timestamp_from_sql(ty: &Type, raw: &[u8]) -> Result<i64, TypeError> {
+    match <i64 as FromSql>::from_sql(ty, raw)? {
+        i64::MAX | i64::MIN => Err(TypeError::InvalidValue(String::from("infinite timestamp"))),
+        v => Ok(v),
+    }
+}
+
+// days since postgres epoch of finite date
+pub(crate) fn date_from_sql(ty: &Type, raw: &[u8]) -> Result<i32, TypeError> {
+    match <i32 as FromSql>::from_sql(ty, raw)? {
+        i32::MAX | i32::MIN => Err(TypeError::InvalidValue(String::from("infinite date"))),
+        v => Ok(v),
+    }
+}
+
+// microseconds since midnight, 24:00:00 included
+pub(crate) fn time_from_sql(ty: &Type, raw: &[u8]) -> Result<i64, TypeError> {
+    match <i64 as FromSql>::from_sql(ty, raw)? {
+        v if (0..=USECS_PER_DAY).contains(&v) => Ok(v),
+        _ => Err(out_of_range(ty)),
+    }
+}
+
+// ISO 8601 text of binary timestamp, timestamptz, date, time or
+// interval, timestamptz in UTC as RFC 3339 asks
+pub(crate) fn iso_text(ty: &Type, raw: &[u8]) -> Result<String, TypeError> {
+    Ok(match *ty {
+        Type::TIMESTAMP | Type::TIMESTAMPTZ => match <i64 as FromSql>::from_sql(ty, raw)? {
+            i64::MAX => String::from("infinity"),
+            i64::MIN => String::from("-infinity"),
+            v if *ty == Type::TIMESTAMPTZ => format!("{}+00:00", iso_timestamp(v)),
+            v => iso_timestamp(v),
+        },
+        Type::DATE => match <i32 as FromSql>::from_sql(ty, raw)? {
+            i32::MAX => String::from("infinity"),
+            i32::MIN => String::from("-infinity"),
+            v => iso_date(EpochDays(v)),
+        },
+        Type::TIME => iso_time(time_from_sql(ty, raw)?),
+        Type::INTERVAL => iso_interval(&Interval::from_sql(ty, raw)?),
+        _ => return Err(TypeError::InvalidValue(format!("{} is not a date or time type", ty))),
+    })
+}
+
+// year 0 being 1 BC, sign and more digits outside 0 to 9999
+fn iso_date(date: EpochDays) -> String {
+    match date.ymd() {
+        (year @ 0..=9999, month, day) => format!("{:04}-{:02}-{:02}", year, month, day),
+        (year, month, day) => format!("{:+05}-{:02}-{:02}", year, month, day),
+    }
+}
+
+fn iso_timestamp(usecs: i64) -> String {
+    let days = usecs.div_euclid(USECS_PER_DAY) as i32;
+    format!("{}T{}", iso_date(EpochDays(days)), iso_time(usecs.rem_euclid(USECS_PER_DAY)))
+}
+
+// fraction only when not zero, without trailing zeros
+fn iso_time(usecs: i64) -> String {
+    let secs = usecs / 1_000_000;
+    let mut s = format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60);
+    push_fraction(&mut s, usecs % 1_000_000);
+    s
+}
+
+fn push_fraction(s: &mut String, usecs: i64) {
+    if usecs != 0 {
+        s.push_str(format!(".{:06}", usecs).trim_end_matches('0'));
+    }
+}
+
+// same as intervalstyle iso_8601: every part keeps its own sign
+fn iso_interval(i: &Interval) -> String {
+    let mut s = String::from("P");
+    for (v, unit) in &[(i.months / 12, 'Y'), (i.months % 12, 'M'), (i.days, 'D')] {
+        if *v != 0 {
+            s.push_str(&format!("{}{}", v, unit));
+        }
+    }
+    let usecs = i.microseconds;
+    if usecs != 0 {
+        s.push('T');
+        for (v, unit) in &[(usecs / 3_600_000_000, 'H'), (usecs / 60_000_000 % 60, 'M')] {
+            if *v != 0 {
+                s.push_str(&format!("{}{}", v, unit));
+            }
+        }
+        let secs = usecs % 60_000_000;
+        if secs != 0 {
+            if secs < 0 {
+                s.push('-');
+            }
+            s.push_str(&(secs.abs() / 1_000_000).to_string());
+            push_fraction(&mut s, secs.abs() % 1_000_000);
+            s.push('S');
+        }
+    } else if s.len() == 1 {
+        s.push_str("T0S");
+    }
+    s
+}
+
+impl<T: ToSql> ToSql for Timestamp<T> {
+    fn accepts(&self, ty: &Type) -> bool {
+        match self {
+            Timestamp::Value(v) => v.accepts(ty),
+            _ => matches!(*ty, Type::TIMESTAMP | Type::TIMESTAMPTZ),
+        }
+    }
+
+    fn to_sql(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        match self {
+            Timestamp::PosInfinity => i64::MAX.to_sql(&Type::INT8, out),
+            Timestamp::NegInfinity => i64::MIN.to_sql(&Type::INT8, out),
+            Timestamp::Value(v) => v.to_sql(ty, out),
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+impl<'a, T: FromSql<'a>> FromSql<'a> for Timestamp<T> {
+    fn accepts(ty: &Type) -> bool {
+        T::accepts(ty)
+    }
+
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        match <i64 as FromSql>::from_sql(ty, raw)? {
+            i64::MAX => Ok(Timestamp::PosInfinity),
+            i64::MIN => Ok(Timestamp::NegInfinity),
+            _ => T::from_sql(ty, raw).map(Timestamp::Value),
+        }
+    }
+}
+
+impl<T: ToSql> ToSql for Date<T> {
+    fn accepts(&self, ty: &Type) -> bool {
+        match self {
+            Date::Value(v) => v.accepts(ty),
+            _ => *ty == Type::DATE,
+        }
+    }
+
+    fn to_sql(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        match self {
+            Date::PosInfinity => i32::MAX.to_sql(&Type::INT4, out),
+            Date::NegInfinity => i32::MIN.to_sql(&Type::INT4, out),
+            Date::Value(v) => v.to_sql(ty, out),
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+impl<'a, T: FromSql<'a>> FromSql<'a> for Date<T> {
+    fn accepts(ty: &Type) -> bool {
+        T::accepts(ty)
+    }
+
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        match <i32 as FromSql>::from_sql(ty, raw)? {
+            i32::MAX => Ok(Date::PosInfinity),
+            i32::MIN => Ok(Date::NegInfinity),
+            _ => T::from_sql(ty, raw).map(Date::Value),
+        }
+    }
+}
+
+// sub-microsecond part is truncated toward the past
+impl ToSql for SystemTime {
+    fn accepts(&self, ty: &Type) -> bool {
+        matches!(*ty, Type::TIMESTAMP | Type::TIMESTAMPTZ)
+    }
+
+    fn to_sql(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        let micros = match self.duration_since(pg_epoch()) {
+            Ok(d) => d.as_micros().try_into().ok(),
+            Err(e) => {
+                let d = e.duration();
+                let micros = d.as_micros() + (d.subsec_nanos() % 1000 != 0) as u128;
+                i64::try_from(micros).ok().map(|m| -m)
+            }
+        };
+        match micros {
+            // reserved for infinities
+            Some(m) if m != i64::MAX && m != i64::MIN => m.to_sql(&Type::INT8, out),
+            _ => Err(out_of_range(ty)),
+        }
+    }
+}
+
+impl<'a> FromSql<'a> for SystemTime {
+    fn accepts(ty: &Type) -> bool {
+        matches!(*ty, Type::TIMESTAMP | Type::TIMESTAMPTZ)
+    }
+
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        let micros = timestamp_from_sql(ty, raw)?;
+        let d = Duration::from_micros(micros.unsigned_abs());
+        let t = if micros >= 0 {
+            pg_epoch().checked_add(d)
+        } else {
+            pg_epoch().checked_sub(d)
+        };
+        t.ok_or_else(|| out_of_range(ty))
+    }
+}
+
+impl ToSql for EpochDays {
+    fn accepts(&self, ty: &Type) -> bool {
+        *ty == Type::DATE
+    }
+
+    fn to_sql(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        match self.0 {
+            // reserved for infinities, see Date
+            i32::MAX | i32::MIN => Err(out_of_range(ty)),
+            d => d.to_sql(&Type::INT4, out),
+        }
+    }
+}
+
+impl<'a> FromSql<'a> for EpochDays {
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::DATE
+    }
+
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        date_from_sql(ty, raw).map(EpochDays)
+    }
+}
+
+// time of day as time since midnight, up to 24:00:00
+impl ToSql for Duration {
+    fn accepts(&self, ty: &Type) -> bool {
+        *ty == Type::TIME
+    }
+
+    fn to_sql(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        match i64::try_from(self.as_micros()) {
+            Ok(micros) if micros <= USECS_PER_DAY => micros.to_sql(&Type::INT8, out),
+            _ => Err(out_of_range(ty)),
+        }
+    }
+}
+
+impl<'a> FromSql<'a> for Duration {
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::TIME
+    }
+
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        time_from_sql(ty, raw).map(|micros| Duration::from_micros(micros as u64))
+    }
+}
+
+impl ToSql for Interval {
+    fn accepts(&self, ty: &Type) -> bool {
+        *ty == Type::INTERVAL
+    }
+
+    fn to_sql(&self, _ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        out.extend(&self.microseconds.to_be_bytes());
+        out.extend(&self.days.to_be_bytes());
+        out.extend(&self.months.to_be_bytes());
+        Ok(IsNull::No)
+    }
+}
+
+impl<'a> FromSql<'a> for Interval {
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::INTERVAL
+    }
+
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        if raw.len() != 16 {
+            return Err(TypeError::InvalidValue(format!("expected 16 bytes, got {}", raw.len())));
+        }
+        Ok(Interval {
+            microseconds: i64::from_be_bytes(raw[..8].try_into().unwrap()),
+            days: i32::from_be_bytes(raw[8..12].try_into().unwrap()),
+            months: i32::from_be_bytes(raw[12..].try_into().unwrap()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::decode;
+    use super::*;
+
+    fn encode<T: ToSql>(ty: Type, v: T) -> Vec<u8> {
+        let mut out = vec![];
+        assert!(v.accepts(&ty));
+        v.to_sql(&ty, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_system_time() {
+        let t = UNIX_EPOCH + Duration::from_secs(PG_EPOCH_SECS) + Duration::from_nanos(1_500);
+        let raw = encode(Type::TIMESTAMPTZ, t);
+        assert_eq!(1i64.to_be_bytes().to_vec(), raw);
+        let back: SystemTime = decode(&Type::TIMESTAMPTZ, true, Some(&raw)).unwrap();
+        assert_eq!(t - Duration::from_nanos(500), back);
+
+        // before the epoch, truncated toward the past
+        let t = UNIX_EPOCH + Duration::from_nanos(1_500);
+        let raw = encode(Type::TIMESTAMP, t);
+        assert_eq!((-946_684_799_999_999i64).to_be_bytes().to_vec(), raw);
+        let back: SystemTime = decode(&Type::TIMESTAMP, true, Some(&raw)).unwrap();
+        assert_eq!(UNIX_EPOCH + Duration::from_micros(1), back);
+    }
+
+    #[test]
+    fn test_infinity() {
+        let raw = encode(Type::TIMESTAMPTZ, Timestamp::<SystemTime>::PosInfinity);
+        assert_eq!(i64::MAX.to_be_bytes().to_vec(), raw);
+        assert_eq!(
+            Timestamp::<SystemTime>::PosInfinity,
+            decode(&Type::TIMESTAMPTZ, true, Some(&raw)).unwrap()
+        );
+        match decode::<SystemTime>(&Type::TIMESTAMPTZ, true, Some(&raw)) {
+            Err(TypeError::InvalidValue(_)) => {}
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        let raw = i32::MIN.to_be_bytes();
+        assert_eq!(Date::<i32>::NegInfinity, Date::from_sql(&Type::DATE, &raw).unwrap());
+    }
+
+    #[test]
+    fn test_epoch_days() {
+        assert_eq!(Some(EpochDays(0)), EpochDays::from_ymd(2000, 1, 1));
+        assert_eq!(Some(EpochDays(-10_957)), EpochDays::from_ymd(1970, 1, 1));
+        assert_eq!(Some(EpochDays(8_825)), EpochDays::from_ymd(2024, 2, 29));
+        assert_eq!(None, EpochDays::from_ymd(2023, 2, 29));
+        for days in &[-800_000, -730_120, -1, 0, 59, 60, 8_825, 3_000_000] {
+            let (y, m, d) = EpochDays(*days).ymd();
+            assert_eq!(Some(EpochDays(*days)), EpochDays::from_ymd(y, m, d));
+        }
+        assert_eq!("2024-02-29", EpochDays(8_825).to_string());
+        assert_eq!("0044-03-15 BC", EpochDays::from_ymd(-43, 3, 15).unwrap().to_string());
+
+        let raw = encode(Type::DATE, EpochDays(8_825));
+        assert_eq!(8_825i32.to_be_bytes().to_vec(), raw);
+        assert_eq!(EpochDays(8_825), decode(&Type::DATE, true, Some(&raw)).unwrap());
+        let raw = i32::MAX.to_be_bytes();
+        assert_eq!(Date::<EpochDays>::PosInfinity, Date::from_sql(&Type::DATE, &raw).unwrap());
+        assert!(EpochDays::from_sql(&Type::DATE, &raw).is_err());
+    }
+
+    #[test]
+    fn test_time_of_day() {
+        let t = Duration::from_micros(45_296_000_001);
+        let raw = encode(Type::TIME, t);
+        assert_eq!(45_296_000_001i64.to_be_bytes().to_vec(), raw);
+        assert_eq!(t, decode::<Duration>(&Type::TIME, true, Some(&raw)).unwrap());
+        let mut out = vec![];
+        assert!(Duration::from_secs(86_401).to_sql(&Type::TIME, &mut out).is_err());
+        assert!(decode::<Duration>(&Type::TIME, true, Some(&(-1i64).to_be_bytes())).is_err());
+    }
+
+    #[test]
+    fn test_iso_text() {
+        let text = |ty: Type, raw: &[u8]| iso_text(&ty, raw).unwrap();
+        let usecs = 8_825 * USECS_PER_DAY + 45_296_000_001;
+        assert_eq!("2024-02-29T12:34:56.000001", text(Type::TIMESTAMP, &usecs.to_be_bytes()));
+        assert_eq!("2000-01-01T00:00:00+00:00", text(Type::TIMESTAMPTZ, &0i64.to_be_bytes()));
+        assert_eq!("1999-12-31T23:59:59.5", text(Type::TIMESTAMP, &(-500_000i64).to_be_bytes()));
+        assert_eq!("-infinity", text(Type::TIMESTAMPTZ, &i64::MIN.to_be_bytes()));
+        let bc = EpochDays::from_ymd(-43, 3, 15).unwrap();
+        assert_eq!("-0043-03-15", text(Type::DATE, &bc.0.to_be_bytes()));
+        assert_eq!("infinity", text(Type::DATE, &i32::MAX.to_be_bytes()));
+        assert_eq!("24:00:00", text(Type::TIME, &USECS_PER_DAY.to_be_bytes()));
+
+        let interval = |months, days, microseconds| {
+            let raw = encode(Type::INTERVAL, Interval { months, days, microseconds });
+            iso_text(&Type::INTERVAL, &raw).unwrap()
+        };
+        assert_eq!("P1Y2M3DT4H5M6.000007S", interval(14, 3, 14_706_000_007));
+        assert_eq!("P-1MT-0.5S", interval(-1, 0, -500_000));
+        assert_eq!("PT1H", interval(0, 0, 3_600_000_000));
+        assert_eq!("PT0S", interval(0, 0, 0));
+    }
+
+    #[test]
+    fn test_interval() {
+        let i = Interval {
+            months: 14,
+            days: -3,
+            microseconds: 1_000_001,
+        };
+        let raw = encode(Type::INTERVAL, i);
+        assert_eq!(vec![0, 0, 0, 0, 0, 15, 66, 65, 255, 255, 255, 253, 0, 0, 0, 14], raw);
+        assert_eq!(i, decode(&Type::INTERVAL, true, Some(&raw)).unwrap());
+    }
+}
@@ -0,0 +1,122 @@
+use super::{FromSql, IsNull, ToSql, Type, TypeError};
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+
+// jsonb binary format is the json text prefixed with format version
+const JSONB_VERSION: u8 = 1;
+
+// value exchanged as json or jsonb through serde
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Json<T>(pub T);
+
+impl<T: Serialize + Debug> ToSql for Json<T> {
+    fn accepts(&self, ty: &Type) -> bool {
+        matches!(*ty, Type::JSON | Type::JSONB)
+    }
+
+    fn to_sql(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        if *ty == Type::JSONB {
+            out.push(JSONB_VERSION);
+        }
+        serde_json::to_writer(out, &self.0).map_err(TypeError::invalid)?;
+        Ok(IsNull::No)
+    }
+
+    fn accepts_text(&self, ty: &Type) -> bool {
+        matches!(*ty, Type::JSON | Type::JSONB)
+    }
+
+    fn to_sql_text(&self, _ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        serde_json::to_writer(out, &self.0).map_err(TypeError::invalid)?;
+        Ok(IsNull::No)
+    }
+
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+impl<'a, T: Deserialize<'a>> FromSql<'a> for Json<T> {
+    fn accepts(ty: &Type) -> bool {
+        matches!(*ty, Type::JSON | Type::JSONB)
+    }
+
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        let json = match (ty, raw.split_first()) {
+            (&Type::JSONB, Some((&JSONB_VERSION, json))) => json,
+            (&Type::JSONB, _) => {
+                return Err(TypeError::InvalidValue(String::from("unsupported jsonb version")))
+            }
+            _ => raw,
+        };
+        Self::from_sql_text(ty, json)
+    }
+
+    fn from_sql_text(_ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        serde_json::from_slice(raw).map(Json).map_err(TypeError::invalid)
+    }
+}
+
+#[cfg(feature = "serde-json-value")]
+impl ToSql for serde_json::Value {
+    fn accepts(&self, ty: &Type) -> bool {
+        Json(self).accepts(ty)
+    }
+
+    fn to_sql(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        Json(self).to_sql(ty, out)
+    }
+
+    fn accepts_text(&self, ty: &Type) -> bool {
+        Json(self).accepts_text(ty)
+    }
+
+    fn to_sql_text(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        Json(self).to_sql_text(ty, out)
+    }
+}
+
+#[cfg(feature = "serde-json-value")]
+impl<'a> FromSql<'a> for serde_json::Value {
+    fn accepts(ty: &Type) -> bool {
+        <Json<Self> as FromSql>::accepts(ty)
+    }
+
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        Json::from_sql(ty, raw).map(|j| j.0)
+    }
+
+    fn from_sql_text(ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        Json::from_sql_text(ty, raw).map(|j| j.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::decode;
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Order<'a> {
+        id: i64,
+        note: &'a str,
+    }
+
+    #[test]
+    fn test_json() {
+        let order = Json(Order { id: 1, note: "a" });
+        let mut out = vec![];
+        order.to_sql(&Type::JSON, &mut out).unwrap();
+        assert_eq!(&b"{\"id\":1,\"note\":\"a\"}"[..], &out[..]);
+        assert_eq!(order, decode(&Type::JSON, true, Some(&out)).unwrap());
+
+        let mut out = vec![];
+        order.to_sql(&Type::JSONB, &mut out).unwrap();
+        assert_eq!(JSONB_VERSION, out[0]);
+        assert_eq!(order, decode(&Type::JSONB, true, Some(&out)).unwrap());
+
+        out[0] = 2;
+        assert!(decode::<Json<Order>>(&Type::JSONB, true, Some(&out)).is_err());
+        assert!(decode::<Json<Order>>(&Type::JSON, false, Some(b"{\"id\":1}")).is_err());
+    }
+}
@@ -0,0 +1,394 @@
+use std::fmt;
+use std::sync::Arc;
+
+mod array;
+mod custom;
+mod datetime;
+mod json;
+mod net;
+mod numeric;
+mod primitive;
+mod range;
+#[cfg(feature = "chrono")]
+mod with_chrono;
+#[cfg(feature = "rust_decimal")]
+mod with_rust_decimal;
+#[cfg(feature = "time")]
+mod with_time;
+#[cfg(feature = "uuid")]
+mod with_uuid;
+
+pub use array::{Array, Dimension};
+pub use custom::{CustomType, Field, Kind};
+#[doc(hidden)]
+pub use custom::private as __private;
+#[cfg(feature = "derive")]
+pub use async_pq_derive::{FromSql, ToSql};
+pub use datetime::{Date, EpochDays, Interval, Timestamp};
+pub(crate) use datetime::iso_text;
+pub use json::Json;
+pub use net::{Inet, MacAddr, MacAddr8};
+pub(crate) use net::inet_text;
+pub use numeric::Numeric;
+pub use range::{Multirange, Range};
+pub(crate) use range::range_text;
+
+// postgres type identified by its oid, user-defined types also carry
+// their description looked up in the catalog
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Type(Repr);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Repr {
+    Builtin(u32),
+    Custom(Arc<CustomType>),
+}
+
+impl Type {
+    pub const BOOL: Type = Type(Repr::Builtin(16));
+    pub const BYTEA: Type = Type(Repr::Builtin(17));
+    pub const CHAR: Type = Type(Repr::Builtin(18));
+    pub const NAME: Type = Type(Repr::Builtin(19));
+    pub const INT8: Type = Type(Repr::Builtin(20));
+    pub const INT2: Type = Type(Repr::Builtin(21));
+    pub const INT4: Type = Type(Repr::Builtin(23));
+    pub const TEXT: Type = Type(Repr::Builtin(25));
+    pub const OID: Type = Type(Repr::Builtin(26));
+    pub const JSON: Type = Type(Repr::Builtin(114));
+    pub const CIDR: Type = Type(Repr::Builtin(650));
+    pub const FLOAT4: Type = Type(Repr::Builtin(700));
+    pub const FLOAT8: Type = Type(Repr::Builtin(701));
+    pub const UNKNOWN: Type = Type(Repr::Builtin(705));
+    pub const MACADDR8: Type = Type(Repr::Builtin(774));
+    pub const MACADDR: Type = Type(Repr::Builtin(829));
+    pub const INET: Type = Type(Repr::Builtin(869));
+    pub const BPCHAR: Type = Type(Repr::Builtin(1042));
+    pub const VARCHAR: Type = Type(Repr::Builtin(1043));
+    pub const DATE: Type = Type(Repr::Builtin(1082));
+    pub const TIME: Type = Type(Repr::Builtin(1083));
+    pub const TIMESTAMP: Type = Type(Repr::Builtin(1114));
+    pub const TIMESTAMPTZ: Type = Type(Repr::Builtin(1184));
+    pub const INTERVAL: Type = Type(Repr::Builtin(1186));
+    pub const NUMERIC: Type = Type(Repr::Builtin(1700));
+    pub const VOID: Type = Type(Repr::Builtin(2278));
+    pub const UUID: Type = Type(Repr::Builtin(2950));
+    pub const JSONB: Type = Type(Repr::Builtin(3802));
+    pub const INT4RANGE: Type = Type(Repr::Builtin(3904));
+    pub const NUMRANGE: Type = Type(Repr::Builtin(3906));
+    pub const TSRANGE: Type = Type(Repr::Builtin(3908));
+    pub const TSTZRANGE: Type = Type(Repr::Builtin(3910));
+    pub const DATERANGE: Type = Type(Repr::Builtin(3912));
+    pub const INT8RANGE: Type = Type(Repr::Builtin(3926));
+    pub const INT4MULTIRANGE: Type = Type(Repr::Builtin(4451));
+    pub const NUMMULTIRANGE: Type = Type(Repr::Builtin(4532));
+    pub const TSMULTIRANGE: Type = Type(Repr::Builtin(4533));
+    pub const TSTZMULTIRANGE: Type = Type(Repr::Builtin(4534));
+    pub const DATEMULTIRANGE: Type = Type(Repr::Builtin(4535));
+    pub const INT8MULTIRANGE: Type = Type(Repr::Builtin(4536));
+
+    pub const JSON_ARRAY: Type = Type(Repr::Builtin(199));
+    pub const CIDR_ARRAY: Type = Type(Repr::Builtin(651));
+    pub const MACADDR8_ARRAY: Type = Type(Repr::Builtin(775));
+    pub const BOOL_ARRAY: Type = Type(Repr::Builtin(1000));
+    pub const BYTEA_ARRAY: Type = Type(Repr::Builtin(1001));
+    pub const CHAR_ARRAY: Type = Type(Repr::Builtin(1002));
+    pub const NAME_ARRAY: Type = Type(Repr::Builtin(1003));
+    pub const INT2_ARRAY: Type = Type(Repr::Builtin(1005));
+    pub const INT4_ARRAY: Type = Type(Repr::Builtin(1007));
+    pub const TEXT_ARRAY: Type = Type(Repr::Builtin(1009));
+    pub const BPCHAR_ARRAY: Type = Type(Repr::Builtin(1014));
+    pub const VARCHAR_ARRAY: Type = Type(Repr::Builtin(1015));
+    pub const INT8_ARRAY: Type = Type(Repr::Builtin(1016));
+    pub const FLOAT4_ARRAY: Type = Type(Repr::Builtin(1021));
+    pub const FLOAT8_ARRAY: Type = Type(Repr::Builtin(1022));
+    pub const OID_ARRAY: Type = Type(Repr::Builtin(1028));
+    pub const MACADDR_ARRAY: Type = Type(Repr::Builtin(1040));
+    pub const INET_ARRAY: Type = Type(Repr::Builtin(1041));
+    pub const TIMESTAMP_ARRAY: Type = Type(Repr::Builtin(1115));
+    pub const DATE_ARRAY: Type = Type(Repr::Builtin(1182));
+    pub const TIME_ARRAY: Type = Type(Repr::Builtin(1183));
+    pub const TIMESTAMPTZ_ARRAY: Type = Type(Repr::Builtin(1185));
+    pub const INTERVAL_ARRAY: Type = Type(Repr::Builtin(1187));
+    pub const NUMERIC_ARRAY: Type = Type(Repr::Builtin(1231));
+    pub const UUID_ARRAY: Type = Type(Repr::Builtin(2951));
+    pub const JSONB_ARRAY: Type = Type(Repr::Builtin(3807));
+    pub const INT4RANGE_ARRAY: Type = Type(Repr::Builtin(3905));
+    pub const NUMRANGE_ARRAY: Type = Type(Repr::Builtin(3907));
+    pub const TSRANGE_ARRAY: Type = Type(Repr::Builtin(3909));
+    pub const TSTZRANGE_ARRAY: Type = Type(Repr::Builtin(3911));
+    pub const DATERANGE_ARRAY: Type = Type(Repr::Builtin(3913));
+    pub const INT8RANGE_ARRAY: Type = Type(Repr::Builtin(3927));
+    pub const INT4MULTIRANGE_ARRAY: Type = Type(Repr::Builtin(6150));
+    pub const NUMMULTIRANGE_ARRAY: Type = Type(Repr::Builtin(6151));
+    pub const TSMULTIRANGE_ARRAY: Type = Type(Repr::Builtin(6152));
+    pub const TSTZMULTIRANGE_ARRAY: Type = Type(Repr::Builtin(6153));
+    pub const DATEMULTIRANGE_ARRAY: Type = Type(Repr::Builtin(6155));
+    pub const INT8MULTIRANGE_ARRAY: Type = Type(Repr::Builtin(6157));
+
+    pub fn from_oid(oid: u32) -> Type {
+        Type(Repr::Builtin(oid))
+    }
+
+    pub(crate) fn from_custom(custom: CustomType) -> Type {
+        Type(Repr::Custom(Arc::new(custom)))
+    }
+
+    pub fn oid(&self) -> u32 {
+        match &self.0 {
+            Repr::Builtin(oid) => *oid,
+            Repr::Custom(c) => c.oid(),
+        }
+    }
+
+    // description of user-defined type, none for built-in ones
+    pub fn custom(&self) -> Option<&CustomType> {
+        match &self.0 {
+            Repr::Builtin(_) => None,
+            Repr::Custom(c) => Some(c),
+        }
+    }
+
+    // element type if this is one of the known array types
+    pub fn element(&self) -> Option<Type> {
+        if let Some(Kind::Array(e)) = self.custom().map(|c| c.kind()) {
+            return Some(e.clone());
+        }
+        ARRAYS.iter().find(|(a, _)| a == self).map(|(_, e)| e.clone())
+    }
+
+    // type of the bounds if this is one of the built-in range types
+    pub fn range_subtype(&self) -> Option<Type> {
+        RANGES.iter().find(|(r, _)| r == self).map(|(_, s)| s.clone())
+    }
+
+    // range type of the members if this is one of the built-in
+    // multirange types
+    pub fn multirange_range(&self) -> Option<Type> {
+        MULTIRANGES.iter().find(|(m, _)| m == self).map(|(_, r)| r.clone())
+    }
+
+    // whether values of this type are exchanged in binary format
+    pub fn supports_binary(&self) -> bool {
+        if let Some(e) = self.element() {
+            return e.supports_binary();
+        }
+        if let Some(s) = self.range_subtype().or_else(|| self.multirange_range()) {
+            return s.supports_binary();
+        }
+        if let Some(c) = self.custom() {
+            return match c.kind() {
+                // label is sent as is
+                Kind::Enum(_) => true,
+                Kind::Composite(fields) => fields.iter().all(|f| f.type_().supports_binary()),
+                Kind::Array(_) | Kind::Other => false,
+            };
+        }
+        matches!(
+            *self,
+            Type::BOOL
+                | Type::BYTEA
+                | Type::CHAR
+                | Type::NAME
+                | Type::INT8
+                | Type::INT2
+                | Type::INT4
+                | Type::TEXT
+                | Type::OID
+                | Type::JSON
+                | Type::FLOAT4
+                | Type::FLOAT8
+                | Type::CIDR
+                | Type::MACADDR8
+                | Type::MACADDR
+                | Type::INET
+                | Type::BPCHAR
+                | Type::VARCHAR
+                | Type::DATE
+                | Type::TIME
+                | Type::TIMESTAMP
+                | Type::TIMESTAMPTZ
+                | Type::INTERVAL
+                | Type::NUMERIC
+                | Type::VOID
+                | Type::JSONB
+                | Type::UUID
+        )
+    }
+}
+
+// array type and its element type
+const ARRAYS: &[(Type, Type)] = &[
+    (Type::JSON_ARRAY, Type::JSON),
+    (Type::CIDR_ARRAY, Type::CIDR),
+    (Type::MACADDR8_ARRAY, Type::MACADDR8),
+    (Type::BOOL_ARRAY, Type::BOOL),
+    (Type::BYTEA_ARRAY, Type::BYTEA),
+    (Type::CHAR_ARRAY, Type::CHAR),
+    (Type::NAME_ARRAY, Type::NAME),
+    (Type::INT2_ARRAY, Type::INT2),
+    (Type::INT4_ARRAY, Type::INT4),
+    (Type::TEXT_ARRAY, Type::TEXT),
+    (Type::BPCHAR_ARRAY, Type::BPCHAR),
+    (Type::VARCHAR_ARRAY, Type::VARCHAR),
+    (Type::INT8_ARRAY, Type::INT8),
+    (Type::FLOAT4_ARRAY, Type::FLOAT4),
+    (Type::FLOAT8_ARRAY, Type::FLOAT8),
+    (Type::OID_ARRAY, Type::OID),
+    (Type::MACADDR_ARRAY, Type::MACADDR),
+    (Type::INET_ARRAY, Type::INET),
+    (Type::TIMESTAMP_ARRAY, Type::TIMESTAMP),
+    (Type::DATE_ARRAY, Type::DATE),
+    (Type::TIME_ARRAY, Type::TIME),
+    (Type::TIMESTAMPTZ_ARRAY, Type::TIMESTAMPTZ),
+    (Type::INTERVAL_ARRAY, Type::INTERVAL),
+    (Type::NUMERIC_ARRAY, Type::NUMERIC),
+    (Type::UUID_ARRAY, Type::UUID),
+    (Type::JSONB_ARRAY, Type::JSONB),
+    (Type::INT4RANGE_ARRAY, Type::INT4RANGE),
+    (Type::NUMRANGE_ARRAY, Type::NUMRANGE),
+    (Type::TSRANGE_ARRAY, Type::TSRANGE),
+    (Type::TSTZRANGE_ARRAY, Type::TSTZRANGE),
+    (Type::DATERANGE_ARRAY, Type::DATERANGE),
+    (Type::INT8RANGE_ARRAY, Type::INT8RANGE),
+    (Type::INT4MULTIRANGE_ARRAY, Type::INT4MULTIRANGE),
+    (Type::NUMMULTIRANGE_ARRAY, Type::NUMMULTIRANGE),
+    (Type::TSMULTIRANGE_ARRAY, Type::TSMULTIRANGE),
+    (Type::TSTZMULTIRANGE_ARRAY, Type::TSTZMULTIRANGE),
+    (Type::DATEMULTIRANGE_ARRAY, Type::DATEMULTIRANGE),
+    (Type::INT8MULTIRANGE_ARRAY, Type::INT8MULTIRANGE),
+];
+
+// range type and the type of its bounds
+const RANGES: &[(Type, Type)] = &[
+    (Type::INT4RANGE, Type::INT4),
+    (Type::NUMRANGE, Type::NUMERIC),
+    (Type::TSRANGE, Type::TIMESTAMP),
+    (Type::TSTZRANGE, Type::TIMESTAMPTZ),
+    (Type::DATERANGE, Type::DATE),
+    (Type::INT8RANGE, Type::INT8),
+];
+
+// multirange type and its range type
+const MULTIRANGES: &[(Type, Type)] = &[
+    (Type::INT4MULTIRANGE, Type::INT4RANGE),
+    (Type::NUMMULTIRANGE, Type::NUMRANGE),
+    (Type::TSMULTIRANGE, Type::TSRANGE),
+    (Type::TSTZMULTIRANGE, Type::TSTZRANGE),
+    (Type::DATEMULTIRANGE, Type::DATERANGE),
+    (Type::INT8MULTIRANGE, Type::INT8RANGE),
+];
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Repr::Builtin(oid) => write!(f, "oid {}", oid),
+            Repr::Custom(c) => write!(f, "{}.{}", c.schema(), c.name()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum IsNull {
+    Yes,
+    No,
+}
+
+// rust value which can be sent as query parameter
+pub trait ToSql: fmt::Debug {
+    // whether the value can be encoded as postgres type ty in binary format
+    fn accepts(&self, ty: &Type) -> bool;
+
+    // append binary representation of the value into out
+    fn to_sql(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError>;
+
+    // whether the value can be sent as postgres type ty in text format,
+    // used only when it can not be sent in binary
+    fn accepts_text(&self, _ty: &Type) -> bool {
+        false
+    }
+
+    // append text representation of the value into out
+    fn to_sql_text(&self, ty: &Type, _out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        Err(TypeError::wrong_type::<Self>(ty))
+    }
+
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+// rust value which can be read from query result, possibly
+// borrowing from the row
+pub trait FromSql<'a>: Sized {
+    // whether values of postgres type ty can be decoded from binary format
+    fn accepts(ty: &Type) -> bool;
+
+    // decode value received in binary format
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError>;
+
+    // whether values of postgres type ty can be decoded from text format
+    fn accepts_text(ty: &Type) -> bool {
+        Self::accepts(ty)
+    }
+
+    // decode value received in text format
+    fn from_sql_text(ty: &Type, _raw: &'a [u8]) -> Result<Self, TypeError> {
+        Err(TypeError::wrong_type::<Self>(ty))
+    }
+
+    // value is NULL
+    fn from_sql_null(_ty: &Type) -> Result<Self, TypeError> {
+        Err(TypeError::UnexpectedNull)
+    }
+}
+
+// decode value in the given format, checking the type is accepted
+pub(crate) fn decode<'a, T: FromSql<'a>>(
+    ty: &Type,
+    binary: bool,
+    raw: Option<&'a [u8]>,
+) -> Result<T, TypeError> {
+    match raw {
+        None => T::from_sql_null(ty),
+        Some(raw) if binary && T::accepts(ty) => T::from_sql(ty, raw),
+        Some(raw) if !binary && T::accepts_text(ty) => T::from_sql_text(ty, raw),
+        Some(_) => Err(TypeError::wrong_type::<T>(ty)),
+    }
+}
+
+#[derive(Debug)]
+pub enum TypeError {
+    // rust type can not be converted from / into the postgres type
+    WrongType { rust: &'static str, postgres: Type },
+    // NULL read into type which can not represent it
+    UnexpectedNull,
+    // malformed value received from the backend
+    InvalidValue(String),
+}
+
+impl TypeError {
+    pub(crate) fn wrong_type<T: ?Sized>(ty: &Type) -> TypeError {
+        TypeError::WrongType {
+            rust: std::any::type_name::<T>(),
+            postgres: ty.clone(),
+        }
+    }
+
+    pub(crate) fn invalid<E: fmt::Display>(e: E) -> TypeError {
+        TypeError::InvalidValue(e.to_string())
+    }
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeError::WrongType { rust, postgres } => {
+                write!(f, "Rust type {} is incompatible with postgres type {}", rust, postgres)
+            }
+            TypeError::UnexpectedNull => write!(f, "Unexpected NULL value"),
+            TypeError::InvalidValue(e) => write!(f, "Invalid value: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TypeError {}
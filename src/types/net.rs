@@ -0,0 +1,241 @@
+use super::{FromSql, IsNull, ToSql, Type, TypeError};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+// address families as sent by postgres, not the ones of the OS
+const PGSQL_AF_INET: u8 = 2;
+const PGSQL_AF_INET6: u8 = 3;
+
+// address with its netmask length, value of inet or cidr. cidr
+// requires the bits outside the netmask to be zero
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Inet {
+    addr: IpAddr,
+    netmask: u8,
+}
+
+impl Inet {
+    // none if netmask is longer than the address
+    pub fn new(addr: IpAddr, netmask: u8) -> Option<Inet> {
+        if netmask > max_netmask(&addr) {
+            return None;
+        }
+        Some(Inet { addr, netmask })
+    }
+
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    pub fn netmask(&self) -> u8 {
+        self.netmask
+    }
+}
+
+impl From<IpAddr> for Inet {
+    fn from(addr: IpAddr) -> Inet {
+        Inet {
+            netmask: max_netmask(&addr),
+            addr,
+        }
+    }
+}
+
+impl fmt::Display for Inet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.netmask)
+    }
+}
+
+fn max_netmask(addr: &IpAddr) -> u8 {
+    match addr {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+// family, netmask length, cidr flag, address length, address
+fn encode_inet(ty: &Type, inet: &Inet, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+    let is_cidr = (*ty == Type::CIDR) as u8;
+    match inet.addr {
+        IpAddr::V4(a) => {
+            out.extend(&[PGSQL_AF_INET, inet.netmask, is_cidr, 4]);
+            out.extend(&a.octets());
+        }
+        IpAddr::V6(a) => {
+            out.extend(&[PGSQL_AF_INET6, inet.netmask, is_cidr, 16]);
+            out.extend(&a.octets());
+        }
+    }
+    Ok(IsNull::No)
+}
+
+fn decode_inet(raw: &[u8]) -> Result<Inet, TypeError> {
+    let addr = match raw {
+        [PGSQL_AF_INET, _, _, 4, a @ ..] if a.len() == 4 => {
+            IpAddr::V4(Ipv4Addr::new(a[0], a[1], a[2], a[3]))
+        }
+        [PGSQL_AF_INET6, _, _, 16, a @ ..] if a.len() == 16 => {
+            let mut octets = [0; 16];
+            octets.copy_from_slice(a);
+            IpAddr::V6(Ipv6Addr::from(octets))
+        }
+        _ => return Err(TypeError::InvalidValue(String::from("malformed inet"))),
+    };
+    Inet::new(addr, raw[1]).ok_or_else(|| TypeError::InvalidValue(String::from("malformed inet")))
+}
+
+// postgres text of binary inet or cidr, the netmask left out for a
+// single host inet
+pub(crate) fn inet_text(ty: &Type, raw: &[u8]) -> Result<String, TypeError> {
+    let inet = decode_inet(raw)?;
+    if *ty == Type::INET && inet.netmask == max_netmask(&inet.addr) {
+        return Ok(inet.addr.to_string());
+    }
+    Ok(inet.to_string())
+}
+
+impl ToSql for Inet {
+    fn accepts(&self, ty: &Type) -> bool {
+        matches!(*ty, Type::INET | Type::CIDR)
+    }
+
+    fn to_sql(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        encode_inet(ty, self, out)
+    }
+}
+
+impl<'a> FromSql<'a> for Inet {
+    fn accepts(ty: &Type) -> bool {
+        matches!(*ty, Type::INET | Type::CIDR)
+    }
+
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        decode_inet(raw)
+    }
+}
+
+// sent as single host, netmask covering the whole address
+impl ToSql for IpAddr {
+    fn accepts(&self, ty: &Type) -> bool {
+        matches!(*ty, Type::INET | Type::CIDR)
+    }
+
+    fn to_sql(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        encode_inet(ty, &Inet::from(*self), out)
+    }
+}
+
+// netmask is dropped, read Inet to keep it
+impl<'a> FromSql<'a> for IpAddr {
+    fn accepts(ty: &Type) -> bool {
+        matches!(*ty, Type::INET | Type::CIDR)
+    }
+
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        decode_inet(raw).map(|i| i.addr)
+    }
+}
+
+// 6 byte (EUI-48) hardware address, value of macaddr
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MacAddr(pub [u8; 6]);
+
+// 8 byte (EUI-64) hardware address, value of macaddr8
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MacAddr8(pub [u8; 8]);
+
+fn fmt_mac(bytes: &[u8], f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for (i, b) in bytes.iter().enumerate() {
+        if i > 0 {
+            write!(f, ":")?;
+        }
+        write!(f, "{:02x}", b)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_mac(&self.0, f)
+    }
+}
+
+impl fmt::Display for MacAddr8 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_mac(&self.0, f)
+    }
+}
+
+macro_rules! mac {
+    ($t:ident, $pg:path, $len:expr) => {
+        impl ToSql for $t {
+            fn accepts(&self, ty: &Type) -> bool {
+                *ty == $pg
+            }
+
+            fn to_sql(&self, _ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+                out.extend(&self.0);
+                Ok(IsNull::No)
+            }
+        }
+
+        impl<'a> FromSql<'a> for $t {
+            fn accepts(ty: &Type) -> bool {
+                *ty == $pg
+            }
+
+            fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+                let mut bytes = [0; $len];
+                if raw.len() != $len {
+                    return Err(TypeError::InvalidValue(format!(
+                        "expected {} bytes, got {}",
+                        $len,
+                        raw.len()
+                    )));
+                }
+                bytes.copy_from_slice(raw);
+                Ok($t(bytes))
+            }
+        }
+    };
+}
+
+mac!(MacAddr, Type::MACADDR, 6);
+mac!(MacAddr8, Type::MACADDR8, 8);
+
+#[cfg(test)]
+mod tests {
+    use super::super::decode;
+    use super::*;
+
+    #[test]
+    fn test_inet() {
+        let inet = Inet::new("10.1.0.0".parse().unwrap(), 16).unwrap();
+        let mut out = vec![];
+        inet.to_sql(&Type::CIDR, &mut out).unwrap();
+        assert_eq!(vec![2, 16, 1, 4, 10, 1, 0, 0], out);
+        assert_eq!(inet, decode(&Type::CIDR, true, Some(&out)).unwrap());
+        assert_eq!("10.1.0.0/16", inet.to_string());
+
+        let addr: IpAddr = "::1".parse().unwrap();
+        let mut out = vec![];
+        addr.to_sql(&Type::INET, &mut out).unwrap();
+        assert_eq!(&[3, 128, 0, 16], &out[..4]);
+        assert_eq!(
+            addr,
+            decode::<IpAddr>(&Type::INET, true, Some(&out)).unwrap()
+        );
+
+        assert!(Inet::new(addr, 129).is_none());
+        assert!(decode::<Inet>(&Type::INET, true, Some(&[2, 33, 0, 4, 1, 2, 3, 4])).is_err());
+    }
+
+    #[test]
+    fn test_mac() {
+        let raw = [8, 0, 0x2b, 1, 2, 3];
+        let mac: MacAddr = decode(&Type::MACADDR, true, Some(&raw)).unwrap();
+        assert_eq!("08:00:2b:01:02:03", mac.to_string());
+        assert!(decode::<MacAddr8>(&Type::MACADDR8, true, Some(&raw)).is_err());
+    }
+}
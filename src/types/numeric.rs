@@ -0,0 +1,294 @@
+use super::{FromSql, IsNull, ToSql, Type, TypeError};
+use std::convert::TryInto;
+use std::fmt;
+use std::str::FromStr;
+
+const SIGN_POSITIVE: u16 = 0x0000;
+const SIGN_NEGATIVE: u16 = 0x4000;
+const SIGN_NAN: u16 = 0xC000;
+const SIGN_POS_INFINITY: u16 = 0xD000;
+const SIGN_NEG_INFINITY: u16 = 0xF000;
+
+// arbitrary precision number kept the way postgres sends it, base 10000
+// digits with the weight of the first one, so it round-trips exactly
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Numeric {
+    sign: u16,
+    // power of 10000 of the first digit
+    weight: i16,
+    // number of decimal digits after the point
+    scale: u16,
+    digits: Vec<i16>,
+}
+
+impl Numeric {
+    pub fn nan() -> Numeric {
+        Numeric::special(SIGN_NAN)
+    }
+
+    pub fn infinity() -> Numeric {
+        Numeric::special(SIGN_POS_INFINITY)
+    }
+
+    pub fn neg_infinity() -> Numeric {
+        Numeric::special(SIGN_NEG_INFINITY)
+    }
+
+    fn special(sign: u16) -> Numeric {
+        Numeric {
+            sign,
+            weight: 0,
+            scale: 0,
+            digits: vec![],
+        }
+    }
+
+    pub fn is_nan(&self) -> bool {
+        self.sign == SIGN_NAN
+    }
+
+    pub fn is_infinite(&self) -> bool {
+        matches!(self.sign, SIGN_POS_INFINITY | SIGN_NEG_INFINITY)
+    }
+
+    pub fn is_negative(&self) -> bool {
+        matches!(self.sign, SIGN_NEGATIVE | SIGN_NEG_INFINITY)
+    }
+
+    // number of decimal digits after the point
+    pub fn scale(&self) -> u16 {
+        self.scale
+    }
+
+    // base 10000 digit at position idx, 0 is the first stored digit
+    fn digit(&self, idx: i32) -> i16 {
+        if idx < 0 {
+            return 0;
+        }
+        self.digits.get(idx as usize).copied().unwrap_or(0)
+    }
+}
+
+fn invalid(s: &str) -> TypeError {
+    TypeError::InvalidValue(format!("numeric {:?}", s))
+}
+
+impl FromStr for Numeric {
+    type Err = TypeError;
+
+    fn from_str(s: &str) -> Result<Numeric, TypeError> {
+        match s {
+            "NaN" => return Ok(Numeric::nan()),
+            "Infinity" | "+Infinity" => return Ok(Numeric::infinity()),
+            "-Infinity" => return Ok(Numeric::neg_infinity()),
+            _ => {}
+        }
+
+        let (negative, unsigned) = match s.as_bytes().first() {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        let (int, frac) = match unsigned.find('.') {
+            Some(i) => (&unsigned[..i], &unsigned[i + 1..]),
+            None => (unsigned, ""),
+        };
+        let all_digits = |p: &str| p.bytes().all(|b| b.is_ascii_digit());
+        if (int.is_empty() && frac.is_empty()) || !all_digits(int) || !all_digits(frac) {
+            return Err(invalid(s));
+        }
+        let scale = frac.len().try_into().map_err(|_| invalid(s))?;
+
+        // align both parts to groups of 4 decimal digits around the point
+        let int_pad = (4 - int.len() % 4) % 4;
+        let frac_pad = (4 - frac.len() % 4) % 4;
+        let mut decimal = "0".repeat(int_pad);
+        decimal.push_str(int);
+        decimal.push_str(frac);
+        decimal.push_str(&"0".repeat(frac_pad));
+
+        let mut digits: Vec<i16> = decimal
+            .as_bytes()
+            .chunks(4)
+            .map(|c| c.iter().fold(0, |d, b| d * 10 + (b - b'0') as i16))
+            .collect();
+        let mut weight: i32 = ((int.len() + int_pad) / 4) as i32 - 1;
+
+        // leading and trailing zero digits are not stored
+        let leading = digits.iter().take_while(|d| **d == 0).count();
+        digits.drain(..leading);
+        weight -= leading as i32;
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        if digits.is_empty() {
+            weight = 0;
+        }
+
+        Ok(Numeric {
+            sign: if negative && !digits.is_empty() { SIGN_NEGATIVE } else { SIGN_POSITIVE },
+            weight: weight.try_into().map_err(|_| invalid(s))?,
+            scale,
+            digits,
+        })
+    }
+}
+
+impl fmt::Display for Numeric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.sign {
+            SIGN_NAN => return write!(f, "NaN"),
+            SIGN_POS_INFINITY => return write!(f, "Infinity"),
+            SIGN_NEG_INFINITY => return write!(f, "-Infinity"),
+            SIGN_NEGATIVE => write!(f, "-")?,
+            _ => {}
+        }
+
+        let weight = self.weight as i32;
+        if weight < 0 {
+            write!(f, "0")?;
+        } else {
+            write!(f, "{}", self.digit(0))?;
+            for i in 1..=weight {
+                write!(f, "{:04}", self.digit(i))?;
+            }
+        }
+
+        if self.scale > 0 {
+            let mut frac = String::new();
+            let mut i = weight + 1;
+            while frac.len() < self.scale as usize {
+                frac.push_str(&format!("{:04}", self.digit(i)));
+                i += 1;
+            }
+            frac.truncate(self.scale as usize);
+            write!(f, ".{}", frac)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<i64> for Numeric {
+    fn from(v: i64) -> Numeric {
+        v.to_string().parse().unwrap()
+    }
+}
+
+impl From<i32> for Numeric {
+    fn from(v: i32) -> Numeric {
+        Numeric::from(v as i64)
+    }
+}
+
+impl ToSql for Numeric {
+    fn accepts(&self, ty: &Type) -> bool {
+        *ty == Type::NUMERIC
+    }
+
+    fn to_sql(&self, _ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        out.extend(&(self.digits.len() as i16).to_be_bytes());
+        out.extend(&self.weight.to_be_bytes());
+        out.extend(&self.sign.to_be_bytes());
+        out.extend(&self.scale.to_be_bytes());
+        for d in &self.digits {
+            out.extend(&d.to_be_bytes());
+        }
+        Ok(IsNull::No)
+    }
+
+    fn accepts_text(&self, ty: &Type) -> bool {
+        *ty == Type::NUMERIC
+    }
+
+    fn to_sql_text(&self, _ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        out.extend(self.to_string().as_bytes());
+        Ok(IsNull::No)
+    }
+}
+
+impl<'a> FromSql<'a> for Numeric {
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::NUMERIC
+    }
+
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        let header = |i: usize| u16::from_be_bytes([raw[i], raw[i + 1]]);
+        if raw.len() < 8 {
+            return Err(TypeError::InvalidValue(format!("numeric of {} bytes", raw.len())));
+        }
+        let ndigits = header(0) as usize;
+        if raw.len() != 8 + ndigits * 2 {
+            return Err(TypeError::InvalidValue(format!(
+                "numeric of {} digits in {} bytes",
+                ndigits,
+                raw.len()
+            )));
+        }
+        let sign = header(4);
+        if !matches!(
+            sign,
+            SIGN_POSITIVE | SIGN_NEGATIVE | SIGN_NAN | SIGN_POS_INFINITY | SIGN_NEG_INFINITY
+        ) {
+            return Err(TypeError::InvalidValue(format!("numeric sign {:#x}", sign)));
+        }
+        let digits = raw[8..]
+            .chunks(2)
+            .map(|d| i16::from_be_bytes([d[0], d[1]]))
+            .collect();
+        Ok(Numeric {
+            sign,
+            weight: header(2) as i16,
+            scale: header(6),
+            digits,
+        })
+    }
+
+    fn from_sql_text(_ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        std::str::from_utf8(raw).map_err(TypeError::invalid)?.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::decode;
+    use super::*;
+
+    fn binary(n: &Numeric) -> Vec<u8> {
+        let mut out = vec![];
+        n.to_sql(&Type::NUMERIC, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_parse_display() {
+        for s in &[
+            "0", "0.00", "1", "-1", "12345.678900", "0.0001", "-0.000012", "10000", "100000000.5",
+            "NaN", "Infinity", "-Infinity",
+        ] {
+            let n: Numeric = s.parse().unwrap();
+            assert_eq!(*s, n.to_string());
+            assert_eq!(n, decode::<Numeric>(&Type::NUMERIC, true, Some(&binary(&n))).unwrap());
+        }
+        assert_eq!("1.50", "+1.50".parse::<Numeric>().unwrap().to_string());
+        assert_eq!("0", "-0".parse::<Numeric>().unwrap().to_string());
+        assert_eq!("0.5", ".5".parse::<Numeric>().unwrap().to_string());
+        assert!("1.2.3".parse::<Numeric>().is_err());
+        assert!("-".parse::<Numeric>().is_err());
+        assert!("1e5".parse::<Numeric>().is_err());
+    }
+
+    #[test]
+    fn test_binary() {
+        // 12345.678900: digits 1, 2345, 6789, weight 1, dscale 6
+        let n: Numeric = "-12345.678900".parse().unwrap();
+        let expected = vec![0, 3, 0, 1, 0x40, 0, 0, 6, 0, 1, 0x09, 0x29, 0x1A, 0x85];
+        assert_eq!(expected, binary(&n));
+
+        // 0.0001: digit 1 with weight -1
+        let n: Numeric = "0.0001".parse().unwrap();
+        assert_eq!(vec![0, 1, 255, 255, 0, 0, 0, 4, 0, 1], binary(&n));
+
+        assert_eq!(vec![0, 0, 0, 0, 0xD0, 0, 0, 0], binary(&Numeric::infinity()));
+        assert!(decode::<Numeric>(&Type::NUMERIC, true, Some(&[0, 1, 0, 0])).is_err());
+    }
+}
@@ -0,0 +1,545 @@
+use super::{FromSql, IsNull, ToSql, Type, TypeError};
+use std::convert::TryInto;
+use std::str;
+
+const TEXT_TYPES: [Type; 5] = [Type::TEXT, Type::VARCHAR, Type::BPCHAR, Type::NAME, Type::UNKNOWN];
+
+fn text(raw: &[u8]) -> Result<&str, TypeError> {
+    str::from_utf8(raw).map_err(TypeError::invalid)
+}
+
+fn fixed<const N: usize>(raw: &[u8]) -> Result<[u8; N], TypeError> {
+    raw.try_into()
+        .map_err(|_| TypeError::InvalidValue(format!("expected {} bytes, got {}", N, raw.len())))
+}
+
+impl ToSql for bool {
+    fn accepts(&self, ty: &Type) -> bool {
+        *ty == Type::BOOL
+    }
+
+    fn to_sql(&self, _ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        out.push(*self as u8);
+        Ok(IsNull::No)
+    }
+
+    fn accepts_text(&self, ty: &Type) -> bool {
+        *ty == Type::BOOL
+    }
+
+    fn to_sql_text(&self, _ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        out.push(if *self { b't' } else { b'f' });
+        Ok(IsNull::No)
+    }
+}
+
+impl<'a> FromSql<'a> for bool {
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::BOOL
+    }
+
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        Ok(fixed::<1>(raw)?[0] != 0)
+    }
+
+    fn from_sql_text(_ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        match raw {
+            b"t" => Ok(true),
+            b"f" => Ok(false),
+            _ => Err(TypeError::InvalidValue(format!("bool {:?}", String::from_utf8_lossy(raw)))),
+        }
+    }
+}
+
+// "char" is a single byte, text form escapes non ascii bytes as \ooo
+impl ToSql for i8 {
+    fn accepts(&self, ty: &Type) -> bool {
+        *ty == Type::CHAR
+    }
+
+    fn to_sql(&self, _ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        out.push(*self as u8);
+        Ok(IsNull::No)
+    }
+
+    fn accepts_text(&self, ty: &Type) -> bool {
+        *ty == Type::CHAR
+    }
+
+    fn to_sql_text(&self, _ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        match *self as u8 {
+            0 => {}
+            b if b.is_ascii() => out.push(b),
+            b => out.extend(format!("\\{:03o}", b).as_bytes()),
+        }
+        Ok(IsNull::No)
+    }
+}
+
+impl<'a> FromSql<'a> for i8 {
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::CHAR
+    }
+
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        Ok(fixed::<1>(raw)?[0] as i8)
+    }
+
+    fn from_sql_text(_ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        match raw {
+            [] => Ok(0),
+            [b] => Ok(*b as i8),
+            [b'\\', ..] if raw.len() == 4 => u8::from_str_radix(text(&raw[1..])?, 8)
+                .map(|b| b as i8)
+                .map_err(TypeError::invalid),
+            _ => Err(TypeError::InvalidValue(format!("char {:?}", String::from_utf8_lossy(raw)))),
+        }
+    }
+}
+
+macro_rules! integer {
+    ($t:ty, $pg:path) => {
+        impl ToSql for $t {
+            fn accepts(&self, ty: &Type) -> bool {
+                *ty == $pg
+            }
+
+            fn to_sql(&self, _ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+                out.extend(&self.to_be_bytes());
+                Ok(IsNull::No)
+            }
+
+            fn accepts_text(&self, ty: &Type) -> bool {
+                *ty == $pg
+            }
+
+            fn to_sql_text(&self, _ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+                out.extend(self.to_string().as_bytes());
+                Ok(IsNull::No)
+            }
+        }
+
+        impl<'a> FromSql<'a> for $t {
+            fn accepts(ty: &Type) -> bool {
+                *ty == $pg
+            }
+
+            fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+                Ok(<$t>::from_be_bytes(fixed(raw)?))
+            }
+
+            fn from_sql_text(_ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+                text(raw)?.parse().map_err(TypeError::invalid)
+            }
+        }
+    };
+}
+
+integer!(i16, Type::INT2);
+integer!(i32, Type::INT4);
+integer!(i64, Type::INT8);
+integer!(u32, Type::OID);
+
+// text form spells out NaN and infinities
+macro_rules! float {
+    ($t:ty, $pg:path) => {
+        impl ToSql for $t {
+            fn accepts(&self, ty: &Type) -> bool {
+                *ty == $pg
+            }
+
+            fn to_sql(&self, _ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+                out.extend(&self.to_be_bytes());
+                Ok(IsNull::No)
+            }
+
+            fn accepts_text(&self, ty: &Type) -> bool {
+                *ty == $pg
+            }
+
+            fn to_sql_text(&self, _ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+                let s = if self.is_nan() {
+                    String::from("NaN")
+                } else if self.is_infinite() {
+                    String::from(if *self > 0.0 { "Infinity" } else { "-Infinity" })
+                } else {
+                    self.to_string()
+                };
+                out.extend(s.as_bytes());
+                Ok(IsNull::No)
+            }
+        }
+
+        impl<'a> FromSql<'a> for $t {
+            fn accepts(ty: &Type) -> bool {
+                *ty == $pg
+            }
+
+            fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+                Ok(<$t>::from_be_bytes(fixed(raw)?))
+            }
+
+            fn from_sql_text(_ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+                match text(raw)? {
+                    "NaN" => Ok(<$t>::NAN),
+                    "Infinity" => Ok(<$t>::INFINITY),
+                    "-Infinity" => Ok(<$t>::NEG_INFINITY),
+                    s => s.parse().map_err(TypeError::invalid),
+                }
+            }
+        }
+    };
+}
+
+float!(f32, Type::FLOAT4);
+float!(f64, Type::FLOAT8);
+
+// strings are sent as text of any type, the backend parses them
+impl ToSql for &str {
+    fn accepts(&self, ty: &Type) -> bool {
+        TEXT_TYPES.contains(ty)
+    }
+
+    fn to_sql(&self, _ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        out.extend(self.as_bytes());
+        Ok(IsNull::No)
+    }
+
+    fn accepts_text(&self, _ty: &Type) -> bool {
+        true
+    }
+
+    fn to_sql_text(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        self.to_sql(ty, out)
+    }
+}
+
+impl ToSql for String {
+    fn accepts(&self, ty: &Type) -> bool {
+        self.as_str().accepts(ty)
+    }
+
+    fn to_sql(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        self.as_str().to_sql(ty, out)
+    }
+
+    fn accepts_text(&self, ty: &Type) -> bool {
+        self.as_str().accepts_text(ty)
+    }
+
+    fn to_sql_text(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        self.as_str().to_sql_text(ty, out)
+    }
+}
+
+// text representation of any type can be read as string
+impl<'a> FromSql<'a> for &'a str {
+    fn accepts(ty: &Type) -> bool {
+        TEXT_TYPES.contains(ty)
+    }
+
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        text(raw)
+    }
+
+    fn accepts_text(_ty: &Type) -> bool {
+        true
+    }
+
+    fn from_sql_text(_ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        text(raw)
+    }
+}
+
+impl<'a> FromSql<'a> for String {
+    fn accepts(ty: &Type) -> bool {
+        <&str as FromSql>::accepts(ty)
+    }
+
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        <&str as FromSql>::from_sql(ty, raw).map(String::from)
+    }
+
+    fn accepts_text(ty: &Type) -> bool {
+        <&str as FromSql>::accepts_text(ty)
+    }
+
+    fn from_sql_text(ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        <&str as FromSql>::from_sql_text(ty, raw).map(String::from)
+    }
+}
+
+// bytea text form is hex, \x followed by two digits per byte
+impl ToSql for &[u8] {
+    fn accepts(&self, ty: &Type) -> bool {
+        *ty == Type::BYTEA
+    }
+
+    fn to_sql(&self, _ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        out.extend(*self);
+        Ok(IsNull::No)
+    }
+
+    fn accepts_text(&self, ty: &Type) -> bool {
+        *ty == Type::BYTEA
+    }
+
+    fn to_sql_text(&self, _ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        out.extend(b"\\x");
+        for b in self.iter() {
+            out.extend(format!("{:02x}", b).as_bytes());
+        }
+        Ok(IsNull::No)
+    }
+}
+
+impl ToSql for Vec<u8> {
+    fn accepts(&self, ty: &Type) -> bool {
+        self.as_slice().accepts(ty)
+    }
+
+    fn to_sql(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        self.as_slice().to_sql(ty, out)
+    }
+
+    fn accepts_text(&self, ty: &Type) -> bool {
+        self.as_slice().accepts_text(ty)
+    }
+
+    fn to_sql_text(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        self.as_slice().to_sql_text(ty, out)
+    }
+}
+
+// text form needs decoding, only owned Vec<u8> can be read from it
+impl<'a> FromSql<'a> for &'a [u8] {
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::BYTEA
+    }
+
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        Ok(raw)
+    }
+
+    fn accepts_text(_ty: &Type) -> bool {
+        false
+    }
+}
+
+impl<'a> FromSql<'a> for Vec<u8> {
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::BYTEA
+    }
+
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        Ok(raw.to_vec())
+    }
+
+    fn from_sql_text(_ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        match raw {
+            [b'\\', b'x', hex @ ..] => decode_hex(hex),
+            _ => decode_escape(raw),
+        }
+    }
+}
+
+// is_multiple_of would need rust 1.87
+#[allow(unknown_lints, clippy::manual_is_multiple_of)]
+fn decode_hex(hex: &[u8]) -> Result<Vec<u8>, TypeError> {
+    if hex.len() % 2 != 0 {
+        return Err(TypeError::InvalidValue(String::from("odd number of hex digits")));
+    }
+    hex.chunks(2)
+        .map(|d| u8::from_str_radix(text(d)?, 16).map_err(TypeError::invalid))
+        .collect()
+}
+
+// older escape format, \\ for backslash and \ooo for other bytes
+fn decode_escape(raw: &[u8]) -> Result<Vec<u8>, TypeError> {
+    let mut out = Vec::with_capacity(raw.len());
+    let mut i = 0;
+    while i < raw.len() {
+        match &raw[i..] {
+            [b'\\', b'\\', ..] => {
+                out.push(b'\\');
+                i += 2;
+            }
+            [b'\\', rest @ ..] if rest.len() >= 3 => {
+                out.push(u8::from_str_radix(text(&rest[..3])?, 8).map_err(TypeError::invalid)?);
+                i += 4;
+            }
+            [b'\\', ..] => return Err(TypeError::InvalidValue(String::from("invalid bytea escape"))),
+            [b, ..] => {
+                out.push(*b);
+                i += 1;
+            }
+            [] => break,
+        }
+    }
+    Ok(out)
+}
+
+// result of functions returning void, empty in both formats
+impl ToSql for () {
+    fn accepts(&self, ty: &Type) -> bool {
+        *ty == Type::VOID
+    }
+
+    fn to_sql(&self, _ty: &Type, _out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        Ok(IsNull::No)
+    }
+
+    fn accepts_text(&self, ty: &Type) -> bool {
+        *ty == Type::VOID
+    }
+
+    fn to_sql_text(&self, _ty: &Type, _out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        Ok(IsNull::No)
+    }
+}
+
+impl<'a> FromSql<'a> for () {
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::VOID
+    }
+
+    fn from_sql(_ty: &Type, _raw: &'a [u8]) -> Result<Self, TypeError> {
+        Ok(())
+    }
+
+    fn from_sql_text(_ty: &Type, _raw: &'a [u8]) -> Result<Self, TypeError> {
+        Ok(())
+    }
+}
+
+// None is NULL, which is a valid value of any type
+impl<T: ToSql> ToSql for Option<T> {
+    fn accepts(&self, ty: &Type) -> bool {
+        match self {
+            Some(v) => v.accepts(ty),
+            None => true,
+        }
+    }
+
+    fn to_sql(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        match self {
+            Some(v) => v.to_sql(ty, out),
+            None => Ok(IsNull::Yes),
+        }
+    }
+
+    fn accepts_text(&self, ty: &Type) -> bool {
+        match self {
+            Some(v) => v.accepts_text(ty),
+            None => true,
+        }
+    }
+
+    fn to_sql_text(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        match self {
+            Some(v) => v.to_sql_text(ty, out),
+            None => Ok(IsNull::Yes),
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+impl<'a, T: FromSql<'a>> FromSql<'a> for Option<T> {
+    fn accepts(ty: &Type) -> bool {
+        T::accepts(ty)
+    }
+
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        T::from_sql(ty, raw).map(Some)
+    }
+
+    fn accepts_text(ty: &Type) -> bool {
+        T::accepts_text(ty)
+    }
+
+    fn from_sql_text(ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        T::from_sql_text(ty, raw).map(Some)
+    }
+
+    fn from_sql_null(_ty: &Type) -> Result<Self, TypeError> {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::decode;
+    use super::*;
+    use std::fmt::Debug;
+
+    // value survives encoding and decoding in both formats
+    fn round_trip<T>(ty: Type, value: T, binary: &[u8], text: &[u8])
+    where
+        T: ToSql + for<'a> FromSql<'a> + PartialEq + Debug,
+    {
+        let mut out = vec![];
+        assert!(ToSql::accepts(&value, &ty));
+        assert_eq!(IsNull::No, value.to_sql(&ty, &mut out).unwrap());
+        assert_eq!(binary, &out[..]);
+        assert_eq!(value, decode::<T>(&ty, true, Some(&out)).unwrap());
+
+        out.clear();
+        assert!(ToSql::accepts_text(&value, &ty));
+        assert_eq!(IsNull::No, value.to_sql_text(&ty, &mut out).unwrap());
+        assert_eq!(text, &out[..]);
+        assert_eq!(value, decode::<T>(&ty, false, Some(&out)).unwrap());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        round_trip(Type::BOOL, true, &[1], b"t");
+        round_trip(Type::CHAR, b'a' as i8, b"a", b"a");
+        round_trip(Type::CHAR, -56i8, &[200], b"\\310");
+        round_trip(Type::INT2, -2i16, &[255, 254], b"-2");
+        round_trip(Type::INT4, 7i32, &[0, 0, 0, 7], b"7");
+        round_trip(Type::INT8, 1i64 << 40, &[0, 0, 1, 0, 0, 0, 0, 0], b"1099511627776");
+        round_trip(Type::OID, 26u32, &[0, 0, 0, 26], b"26");
+        round_trip(Type::FLOAT4, 1.5f32, &[63, 192, 0, 0], b"1.5");
+        round_trip(Type::FLOAT8, f64::NEG_INFINITY, &[255, 240, 0, 0, 0, 0, 0, 0], b"-Infinity");
+        round_trip(Type::VARCHAR, String::from("ab"), b"ab", b"ab");
+        round_trip(Type::BYTEA, vec![0u8, 255], &[0, 255], b"\\x00ff");
+        round_trip(Type::VOID, (), &[], b"");
+        round_trip(Type::INT4, Some(7i32), &[0, 0, 0, 7], b"7");
+    }
+
+    #[test]
+    fn test_decode_text() {
+        assert!(decode::<f64>(&Type::FLOAT8, false, Some(b"NaN")).unwrap().is_nan());
+        assert_eq!("1.50", decode::<&str>(&Type::from_oid(1700), false, Some(b"1.50")).unwrap());
+        assert_eq!(
+            vec![b'a', b'\\', 0],
+            decode::<Vec<u8>>(&Type::BYTEA, false, Some(b"a\\\\\\000")).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_null() {
+        assert_eq!(IsNull::Yes, None::<i32>.to_sql(&Type::TEXT, &mut vec![]).unwrap());
+        assert_eq!(None, decode::<Option<i32>>(&Type::INT4, true, None).unwrap());
+        match decode::<i32>(&Type::INT4, true, None) {
+            Err(TypeError::UnexpectedNull) => {}
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_wrong_type() {
+        match decode::<i64>(&Type::INT4, true, Some(&[0, 0, 0, 7])) {
+            Err(e) => assert_eq!("Rust type i64 is incompatible with postgres type oid 23", e.to_string()),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        match decode::<i32>(&Type::INT4, true, Some(&[0, 7])) {
+            Err(TypeError::InvalidValue(_)) => {}
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        assert!(!ToSql::accepts_text(&1i32, &Type::INT8));
+    }
+}
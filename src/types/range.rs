@@ -0,0 +1,395 @@
+use super::{decode, FromSql, IsNull, ToSql, Type, TypeError};
+use std::convert::{TryFrom, TryInto};
+use std::ops::Bound;
+
+const RANGE_EMPTY: u8 = 0x01;
+const RANGE_LB_INC: u8 = 0x02;
+const RANGE_UB_INC: u8 = 0x04;
+const RANGE_LB_INF: u8 = 0x08;
+const RANGE_UB_INF: u8 = 0x10;
+
+// value of a range type. postgres normalizes ranges of discrete
+// types, so [1,3] comes back as [1,4)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Range<T> {
+    Empty,
+    Nonempty(Bound<T>, Bound<T>),
+}
+
+impl<T> Range<T> {
+    pub fn new(lower: Bound<T>, upper: Bound<T>) -> Range<T> {
+        Range::Nonempty(lower, upper)
+    }
+
+    pub fn empty() -> Range<T> {
+        Range::Empty
+    }
+
+    pub fn is_empty(&self) -> bool {
+        matches!(self, Range::Empty)
+    }
+
+    // none for empty range
+    pub fn lower(&self) -> Option<Bound<&T>> {
+        match self {
+            Range::Empty => None,
+            Range::Nonempty(l, _) => Some(l.as_ref()),
+        }
+    }
+
+    pub fn upper(&self) -> Option<Bound<&T>> {
+        match self {
+            Range::Empty => None,
+            Range::Nonempty(_, u) => Some(u.as_ref()),
+        }
+    }
+
+    fn bounds(&self) -> impl Iterator<Item = &T> {
+        let (l, u) = match self {
+            Range::Empty => (None, None),
+            Range::Nonempty(l, u) => (bound_value(l), bound_value(u)),
+        };
+        l.into_iter().chain(u)
+    }
+}
+
+fn bound_value<T>(b: &Bound<T>) -> Option<&T> {
+    match b {
+        Bound::Included(v) | Bound::Excluded(v) => Some(v),
+        Bound::Unbounded => None,
+    }
+}
+
+// value of a multirange type, postgres 14 and later
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Multirange<T>(pub Vec<Range<T>>);
+
+fn subtype(ty: &Type) -> Result<Type, TypeError> {
+    ty.range_subtype().ok_or_else(|| TypeError::InvalidValue(format!("{} is not a range type", ty)))
+}
+
+fn range_type(ty: &Type) -> Result<Type, TypeError> {
+    ty.multirange_range()
+        .ok_or_else(|| TypeError::InvalidValue(format!("{} is not a multirange type", ty)))
+}
+
+fn accepts_range<T: ToSql>(ty: &Type, range: &Range<T>, text: bool) -> bool {
+    match ty.range_subtype() {
+        Some(s) if text => range.bounds().all(|v| v.accepts_text(&s)),
+        Some(s) => range.bounds().all(|v| v.accepts(&s)),
+        None => false,
+    }
+}
+
+// length prefixed value, NULL is not allowed as bound
+fn encode_value<T: ToSql>(ty: &Type, v: &T, out: &mut Vec<u8>) -> Result<(), TypeError> {
+    let start = out.len();
+    out.extend(&0i32.to_be_bytes());
+    if v.to_sql(ty, out)? == IsNull::Yes {
+        return Err(TypeError::InvalidValue(String::from("NULL range bound")));
+    }
+    let len = i32::try_from(out.len() - start - 4)
+        .map_err(|_| TypeError::InvalidValue(String::from("range bound too large")))?;
+    out[start..start + 4].copy_from_slice(&len.to_be_bytes());
+    Ok(())
+}
+
+// binary format: flags, then each finite bound prefixed with its length
+fn encode<T: ToSql>(ty: &Type, range: &Range<T>, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+    let sub = subtype(ty)?;
+    let (lower, upper) = match range {
+        Range::Empty => {
+            out.push(RANGE_EMPTY);
+            return Ok(IsNull::No);
+        }
+        Range::Nonempty(l, u) => (l, u),
+    };
+    let mut flags = 0;
+    match lower {
+        Bound::Included(_) => flags |= RANGE_LB_INC,
+        Bound::Excluded(_) => {}
+        Bound::Unbounded => flags |= RANGE_LB_INF,
+    }
+    match upper {
+        Bound::Included(_) => flags |= RANGE_UB_INC,
+        Bound::Excluded(_) => {}
+        Bound::Unbounded => flags |= RANGE_UB_INF,
+    }
+    out.push(flags);
+    for v in bound_value(lower).into_iter().chain(bound_value(upper)) {
+        encode_value(&sub, v, out)?;
+    }
+    Ok(IsNull::No)
+}
+
+// text format: empty or brackets around the bounds, every finite
+// bound quoted
+fn encode_text<T: ToSql>(ty: &Type, range: &Range<T>, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+    let sub = subtype(ty)?;
+    let (lower, upper) = match range {
+        Range::Empty => {
+            out.extend(b"empty");
+            return Ok(IsNull::No);
+        }
+        Range::Nonempty(l, u) => (l, u),
+    };
+    out.push(if matches!(lower, Bound::Included(_)) { b'[' } else { b'(' });
+    if let Some(v) = bound_value(lower) {
+        encode_text_value(&sub, v, out)?;
+    }
+    out.push(b',');
+    if let Some(v) = bound_value(upper) {
+        encode_text_value(&sub, v, out)?;
+    }
+    out.push(if matches!(upper, Bound::Included(_)) { b']' } else { b')' });
+    Ok(IsNull::No)
+}
+
+fn encode_text_value<T: ToSql>(ty: &Type, v: &T, out: &mut Vec<u8>) -> Result<(), TypeError> {
+    let mut value = vec![];
+    if v.to_sql_text(ty, &mut value)? == IsNull::Yes {
+        return Err(TypeError::InvalidValue(String::from("NULL range bound")));
+    }
+    out.push(b'"');
+    for b in value {
+        if b == b'"' || b == b'\\' {
+            out.push(b'\\');
+        }
+        out.push(b);
+    }
+    out.push(b'"');
+    Ok(())
+}
+
+struct Reader<'a> {
+    raw: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], TypeError> {
+        if self.raw.len() < n {
+            return Err(TypeError::InvalidValue(String::from("truncated range")));
+        }
+        let (head, rest) = self.raw.split_at(n);
+        self.raw = rest;
+        Ok(head)
+    }
+
+    fn i32(&mut self) -> Result<i32, TypeError> {
+        Ok(i32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    // length prefixed value
+    fn value(&mut self) -> Result<&'a [u8], TypeError> {
+        let len = usize::try_from(self.i32()?)
+            .map_err(|_| TypeError::InvalidValue(String::from("invalid range length")))?;
+        self.bytes(len)
+    }
+}
+
+fn decode_range<'a, T: FromSql<'a>>(ty: &Type, raw: &'a [u8]) -> Result<Range<T>, TypeError> {
+    let sub = subtype(ty)?;
+    let mut r = Reader { raw };
+    let flags = r.bytes(1)?[0];
+    if flags & RANGE_EMPTY != 0 {
+        return Ok(Range::Empty);
+    }
+    let mut bound = |inf, inc| -> Result<Bound<T>, TypeError> {
+        if flags & inf != 0 {
+            return Ok(Bound::Unbounded);
+        }
+        let v = decode(&sub, true, Some(r.value()?))?;
+        Ok(if flags & inc != 0 { Bound::Included(v) } else { Bound::Excluded(v) })
+    };
+    let lower = bound(RANGE_LB_INF, RANGE_LB_INC)?;
+    let upper = bound(RANGE_UB_INF, RANGE_UB_INC)?;
+    Ok(Range::Nonempty(lower, upper))
+}
+
+// postgres text of binary range or multirange, the bounds given by
+// bound_text and quoted when needed
+pub(crate) fn range_text(
+    ty: &Type,
+    raw: &[u8],
+    bound_text: fn(&Type, &[u8]) -> Result<String, TypeError>,
+) -> Result<String, TypeError> {
+    let mut r = Reader { raw };
+    if let Some(range) = ty.multirange_range() {
+        let mut out = String::from("{");
+        for i in 0..r.i32()? {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str(&range_text(&range, r.value()?, bound_text)?);
+        }
+        out.push('}');
+        return Ok(out);
+    }
+    let sub = subtype(ty)?;
+    let flags = r.bytes(1)?[0];
+    if flags & RANGE_EMPTY != 0 {
+        return Ok(String::from("empty"));
+    }
+    let mut out = String::from(if flags & RANGE_LB_INC != 0 { "[" } else { "(" });
+    if flags & RANGE_LB_INF == 0 {
+        push_bound_text(&mut out, &bound_text(&sub, r.value()?)?);
+    }
+    out.push(',');
+    if flags & RANGE_UB_INF == 0 {
+        push_bound_text(&mut out, &bound_text(&sub, r.value()?)?);
+    }
+    out.push(if flags & RANGE_UB_INC != 0 { ']' } else { ')' });
+    Ok(out)
+}
+
+fn push_bound_text(out: &mut String, v: &str) {
+    if !v.is_empty() && !v.contains(|c| "\"\\,()[] ".contains(c)) {
+        out.push_str(v);
+        return;
+    }
+    out.push('"');
+    for c in v.chars() {
+        if c == '"' || c == '\\' {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out.push('"');
+}
+
+impl<T: ToSql> ToSql for Range<T> {
+    fn accepts(&self, ty: &Type) -> bool {
+        accepts_range(ty, self, false)
+    }
+
+    fn to_sql(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        encode(ty, self, out)
+    }
+
+    fn accepts_text(&self, ty: &Type) -> bool {
+        accepts_range(ty, self, true)
+    }
+
+    fn to_sql_text(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        encode_text(ty, self, out)
+    }
+
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+impl<'a, T: FromSql<'a>> FromSql<'a> for Range<T> {
+    fn accepts(ty: &Type) -> bool {
+        ty.range_subtype().is_some_and(|s| T::accepts(&s))
+    }
+
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        decode_range(ty, raw)
+    }
+}
+
+impl<T: ToSql> ToSql for Multirange<T> {
+    fn accepts(&self, ty: &Type) -> bool {
+        ty.multirange_range()
+            .is_some_and(|r| self.0.iter().all(|v| accepts_range(&r, v, false)))
+    }
+
+    // binary format: number of ranges, then each range prefixed with
+    // its length
+    fn to_sql(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        let range = range_type(ty)?;
+        out.extend(&(self.0.len() as i32).to_be_bytes());
+        for v in &self.0 {
+            encode_value(&range, v, out)?;
+        }
+        Ok(IsNull::No)
+    }
+
+    fn accepts_text(&self, ty: &Type) -> bool {
+        ty.multirange_range()
+            .is_some_and(|r| self.0.iter().all(|v| accepts_range(&r, v, true)))
+    }
+
+    fn to_sql_text(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        let range = range_type(ty)?;
+        out.push(b'{');
+        for (i, v) in self.0.iter().enumerate() {
+            if i > 0 {
+                out.push(b',');
+            }
+            encode_text(&range, v, out)?;
+        }
+        out.push(b'}');
+        Ok(IsNull::No)
+    }
+
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+impl<'a, T: FromSql<'a>> FromSql<'a> for Multirange<T> {
+    fn accepts(ty: &Type) -> bool {
+        ty.multirange_range().is_some_and(|r| Range::<T>::accepts(&r))
+    }
+
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        let range = range_type(ty)?;
+        let mut r = Reader { raw };
+        let count = r.i32()?;
+        // every range takes at least its length and flags
+        if count < 0 || count as usize > r.raw.len() / 5 {
+            return Err(TypeError::InvalidValue(String::from("truncated multirange")));
+        }
+        let mut ranges = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            ranges.push(decode_range(&range, r.value()?)?);
+        }
+        Ok(Multirange(ranges))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_range() {
+        let range = Range::new(Bound::Included(1i64), Bound::Excluded(10));
+        assert!(range.accepts(&Type::INT8RANGE));
+        assert!(!range.accepts(&Type::INT4RANGE));
+        let mut out = vec![];
+        range.to_sql(&Type::INT8RANGE, &mut out).unwrap();
+        let expected = vec![2, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 10];
+        assert_eq!(expected, out);
+        assert_eq!(range, decode(&Type::INT8RANGE, true, Some(&out)).unwrap());
+
+        let range = Range::new(Bound::Unbounded, Bound::Included(5i32));
+        let mut out = vec![];
+        range.to_sql(&Type::INT4RANGE, &mut out).unwrap();
+        assert_eq!(vec![0x0C, 0, 0, 0, 4, 0, 0, 0, 5], out);
+        assert_eq!(range, decode(&Type::INT4RANGE, true, Some(&out)).unwrap());
+
+        let empty = decode::<Range<i32>>(&Type::INT4RANGE, true, Some(&[1])).unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(None, empty.lower());
+        assert!(decode::<Range<i32>>(&Type::INT4RANGE, true, Some(&[2, 0, 0, 0, 4, 0])).is_err());
+    }
+
+    #[test]
+    fn test_multirange() {
+        let m = Multirange(vec![
+            Range::new(Bound::Included(1i32), Bound::Excluded(3)),
+            Range::new(Bound::Excluded(5), Bound::Unbounded),
+        ]);
+        let mut out = vec![];
+        assert!(m.accepts(&Type::INT4MULTIRANGE));
+        m.to_sql(&Type::INT4MULTIRANGE, &mut out).unwrap();
+        assert_eq!(m, decode(&Type::INT4MULTIRANGE, true, Some(&out)).unwrap());
+
+        let mut out = vec![];
+        m.to_sql_text(&Type::INT4MULTIRANGE, &mut out).unwrap();
+        assert_eq!(&b"{[\"1\",\"3\"),(\"5\",)}"[..], &out[..]);
+    }
+}
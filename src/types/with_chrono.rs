@@ -0,0 +1,175 @@
+use super::datetime::{date_from_sql, out_of_range, time_from_sql, timestamp_from_sql};
+use super::{FromSql, IsNull, ToSql, Type, TypeError};
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+
+fn base() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2000, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap()
+}
+
+impl ToSql for NaiveDateTime {
+    fn accepts(&self, ty: &Type) -> bool {
+        *ty == Type::TIMESTAMP
+    }
+
+    fn to_sql(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        match self.signed_duration_since(base()).num_microseconds() {
+            Some(micros) => micros.to_sql(&Type::INT8, out),
+            None => Err(out_of_range(ty)),
+        }
+    }
+}
+
+impl<'a> FromSql<'a> for NaiveDateTime {
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::TIMESTAMP
+    }
+
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        let micros = timestamp_from_sql(ty, raw)?;
+        base()
+            .checked_add_signed(Duration::microseconds(micros))
+            .ok_or_else(|| out_of_range(ty))
+    }
+}
+
+// stored as UTC, the offset is not kept
+impl<Tz: TimeZone> ToSql for DateTime<Tz> {
+    fn accepts(&self, ty: &Type) -> bool {
+        *ty == Type::TIMESTAMPTZ
+    }
+
+    fn to_sql(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        match self.naive_utc().signed_duration_since(base()).num_microseconds() {
+            Some(micros) => micros.to_sql(&Type::INT8, out),
+            None => Err(out_of_range(ty)),
+        }
+    }
+}
+
+impl<'a> FromSql<'a> for DateTime<Utc> {
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::TIMESTAMPTZ
+    }
+
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        let micros = timestamp_from_sql(ty, raw)?;
+        base()
+            .checked_add_signed(Duration::microseconds(micros))
+            .map(|t| Utc.from_utc_datetime(&t))
+            .ok_or_else(|| out_of_range(ty))
+    }
+}
+
+impl<'a> FromSql<'a> for DateTime<Local> {
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::TIMESTAMPTZ
+    }
+
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        DateTime::<Utc>::from_sql(ty, raw).map(|t| t.with_timezone(&Local))
+    }
+}
+
+// offset is always UTC, the backend does not send it
+impl<'a> FromSql<'a> for DateTime<FixedOffset> {
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::TIMESTAMPTZ
+    }
+
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        DateTime::<Utc>::from_sql(ty, raw).map(|t| t.fixed_offset())
+    }
+}
+
+impl ToSql for NaiveDate {
+    fn accepts(&self, ty: &Type) -> bool {
+        *ty == Type::DATE
+    }
+
+    fn to_sql(&self, _ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        let days = self.signed_duration_since(base().date()).num_days();
+        (days as i32).to_sql(&Type::INT4, out)
+    }
+}
+
+impl<'a> FromSql<'a> for NaiveDate {
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::DATE
+    }
+
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        let days = date_from_sql(ty, raw)?;
+        base()
+            .date()
+            .checked_add_signed(Duration::days(days.into()))
+            .ok_or_else(|| out_of_range(ty))
+    }
+}
+
+impl ToSql for NaiveTime {
+    fn accepts(&self, ty: &Type) -> bool {
+        *ty == Type::TIME
+    }
+
+    fn to_sql(&self, _ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        let micros = self.signed_duration_since(NaiveTime::MIN).num_microseconds();
+        micros.unwrap_or_default().to_sql(&Type::INT8, out)
+    }
+}
+
+// 24:00:00 does not exist in chrono
+impl<'a> FromSql<'a> for NaiveTime {
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::TIME
+    }
+
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        let micros = time_from_sql(ty, raw)?;
+        let (t, wrapped) = NaiveTime::MIN.overflowing_add_signed(Duration::microseconds(micros));
+        if wrapped != 0 {
+            return Err(out_of_range(ty));
+        }
+        Ok(t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{decode, Timestamp};
+    use super::*;
+
+    fn round_trip<T>(ty: Type, value: T, micros: i64)
+    where
+        T: ToSql + for<'a> FromSql<'a> + PartialEq + std::fmt::Debug,
+    {
+        let mut out = vec![];
+        assert!(value.accepts(&ty));
+        value.to_sql(&ty, &mut out).unwrap();
+        let expected = if ty == Type::DATE {
+            (micros as i32).to_be_bytes().to_vec()
+        } else {
+            micros.to_be_bytes().to_vec()
+        };
+        assert_eq!(expected, out);
+        assert_eq!(value, decode(&ty, true, Some(&out)).unwrap());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let date = NaiveDate::from_ymd_opt(1999, 12, 31).unwrap();
+        let time = NaiveTime::from_hms_micro_opt(23, 59, 59, 999_999).unwrap();
+        round_trip(Type::TIMESTAMP, date.and_time(time), -1);
+        round_trip(Type::TIMESTAMPTZ, Utc.from_utc_datetime(&date.and_time(time)), -1);
+        round_trip(Type::DATE, date, -1);
+        round_trip(Type::TIME, time, 86_399_999_999);
+        round_trip(Type::TIMESTAMP, Timestamp::<NaiveDateTime>::NegInfinity, i64::MIN);
+    }
+
+    #[test]
+    fn test_time_24() {
+        match decode::<NaiveTime>(&Type::TIME, true, Some(&86_400_000_000i64.to_be_bytes())) {
+            Err(TypeError::InvalidValue(_)) => {}
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+}
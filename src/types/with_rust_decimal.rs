@@ -0,0 +1,54 @@
+use super::{FromSql, IsNull, Numeric, ToSql, Type, TypeError};
+use rust_decimal::Decimal;
+
+// converted through the decimal string, so no digit is lost silently
+impl From<Decimal> for Numeric {
+    fn from(d: Decimal) -> Numeric {
+        d.to_string().parse().unwrap()
+    }
+}
+
+impl ToSql for Decimal {
+    fn accepts(&self, ty: &Type) -> bool {
+        *ty == Type::NUMERIC
+    }
+
+    fn to_sql(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        Numeric::from(*self).to_sql(ty, out)
+    }
+}
+
+// NaN, infinities and values beyond 28 digits of precision are rejected
+impl<'a> FromSql<'a> for Decimal {
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::NUMERIC
+    }
+
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        let n = Numeric::from_sql(ty, raw)?;
+        Decimal::from_str_exact(&n.to_string())
+            .map_err(|e| TypeError::InvalidValue(format!("numeric {} as decimal: {}", n, e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::decode;
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_round_trip() {
+        for s in &["0", "-1.50", "123456789.000001", "0.0000000000000000000000000001"] {
+            let d = Decimal::from_str(s).unwrap();
+            let mut out = vec![];
+            d.to_sql(&Type::NUMERIC, &mut out).unwrap();
+            let back: Decimal = decode(&Type::NUMERIC, true, Some(&out)).unwrap();
+            assert_eq!(*s, back.to_string());
+        }
+
+        let mut out = vec![];
+        Numeric::nan().to_sql(&Type::NUMERIC, &mut out).unwrap();
+        assert!(decode::<Decimal>(&Type::NUMERIC, true, Some(&out)).is_err());
+    }
+}
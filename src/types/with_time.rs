@@ -0,0 +1,156 @@
+use super::datetime::{date_from_sql, out_of_range, time_from_sql, timestamp_from_sql};
+use super::{FromSql, IsNull, ToSql, Type, TypeError};
+use std::convert::TryFrom;
+use time::{Date, Duration, Month, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset};
+
+fn base() -> PrimitiveDateTime {
+    Date::from_calendar_date(2000, Month::January, 1).unwrap().midnight()
+}
+
+fn timestamp_to_sql(t: PrimitiveDateTime, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+    match i64::try_from((t - base()).whole_microseconds()) {
+        Ok(micros) => micros.to_sql(&Type::INT8, out),
+        Err(_) => Err(out_of_range(ty)),
+    }
+}
+
+impl ToSql for PrimitiveDateTime {
+    fn accepts(&self, ty: &Type) -> bool {
+        *ty == Type::TIMESTAMP
+    }
+
+    fn to_sql(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        timestamp_to_sql(*self, ty, out)
+    }
+}
+
+impl<'a> FromSql<'a> for PrimitiveDateTime {
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::TIMESTAMP
+    }
+
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        let micros = timestamp_from_sql(ty, raw)?;
+        base()
+            .checked_add(Duration::microseconds(micros))
+            .ok_or_else(|| out_of_range(ty))
+    }
+}
+
+// stored as UTC, the offset is not kept
+impl ToSql for OffsetDateTime {
+    fn accepts(&self, ty: &Type) -> bool {
+        *ty == Type::TIMESTAMPTZ
+    }
+
+    fn to_sql(&self, ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        let utc = self.to_offset(UtcOffset::UTC);
+        timestamp_to_sql(PrimitiveDateTime::new(utc.date(), utc.time()), ty, out)
+    }
+}
+
+impl<'a> FromSql<'a> for OffsetDateTime {
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::TIMESTAMPTZ
+    }
+
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        let micros = timestamp_from_sql(ty, raw)?;
+        base()
+            .checked_add(Duration::microseconds(micros))
+            .map(PrimitiveDateTime::assume_utc)
+            .ok_or_else(|| out_of_range(ty))
+    }
+}
+
+impl ToSql for Date {
+    fn accepts(&self, ty: &Type) -> bool {
+        *ty == Type::DATE
+    }
+
+    fn to_sql(&self, _ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        let days = (*self - base().date()).whole_days();
+        (days as i32).to_sql(&Type::INT4, out)
+    }
+}
+
+impl<'a> FromSql<'a> for Date {
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::DATE
+    }
+
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        let days = date_from_sql(ty, raw)?;
+        base()
+            .date()
+            .checked_add(Duration::days(days.into()))
+            .ok_or_else(|| out_of_range(ty))
+    }
+}
+
+impl ToSql for Time {
+    fn accepts(&self, ty: &Type) -> bool {
+        *ty == Type::TIME
+    }
+
+    fn to_sql(&self, _ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        let micros = (*self - Time::MIDNIGHT).whole_microseconds() as i64;
+        micros.to_sql(&Type::INT8, out)
+    }
+}
+
+// 24:00:00 does not exist in time
+impl<'a> FromSql<'a> for Time {
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::TIME
+    }
+
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        let micros = time_from_sql(ty, raw)?;
+        let d = Duration::microseconds(micros);
+        if d >= Duration::DAY {
+            return Err(out_of_range(ty));
+        }
+        Ok(Time::MIDNIGHT + d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{decode, Date as PgDate};
+    use super::*;
+
+    fn round_trip<T>(ty: Type, value: T, expected: &[u8])
+    where
+        T: ToSql + for<'a> FromSql<'a> + PartialEq + std::fmt::Debug,
+    {
+        let mut out = vec![];
+        assert!(value.accepts(&ty));
+        value.to_sql(&ty, &mut out).unwrap();
+        assert_eq!(expected, &out[..]);
+        assert_eq!(value, decode(&ty, true, Some(&out)).unwrap());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let date = Date::from_calendar_date(1999, Month::December, 31).unwrap();
+        let time = Time::from_hms_micro(23, 59, 59, 999_999).unwrap();
+        let ts = PrimitiveDateTime::new(date, time);
+        round_trip(Type::TIMESTAMP, ts, &(-1i64).to_be_bytes());
+        round_trip(Type::TIMESTAMPTZ, ts.assume_utc(), &(-1i64).to_be_bytes());
+        round_trip(Type::DATE, date, &(-1i32).to_be_bytes());
+        round_trip(Type::TIME, time, &86_399_999_999i64.to_be_bytes());
+        round_trip(Type::DATE, PgDate::<Date>::PosInfinity, &i32::MAX.to_be_bytes());
+    }
+
+    #[test]
+    fn test_offset_stored_as_utc() {
+        let t = base().assume_offset(UtcOffset::from_hms(2, 0, 0).unwrap());
+        let mut out = vec![];
+        t.to_sql(&Type::TIMESTAMPTZ, &mut out).unwrap();
+        assert_eq!((-7_200_000_000i64).to_be_bytes().to_vec(), out);
+        let back: OffsetDateTime = decode(&Type::TIMESTAMPTZ, true, Some(&out)).unwrap();
+        assert_eq!(t, back);
+        assert_eq!(UtcOffset::UTC, back.offset());
+    }
+}
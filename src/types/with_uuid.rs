@@ -0,0 +1,61 @@
+use super::{FromSql, IsNull, ToSql, Type, TypeError};
+use uuid::Uuid;
+
+impl ToSql for Uuid {
+    fn accepts(&self, ty: &Type) -> bool {
+        *ty == Type::UUID
+    }
+
+    fn to_sql(&self, _ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        out.extend(self.as_bytes());
+        Ok(IsNull::No)
+    }
+
+    fn accepts_text(&self, ty: &Type) -> bool {
+        *ty == Type::UUID
+    }
+
+    fn to_sql_text(&self, _ty: &Type, out: &mut Vec<u8>) -> Result<IsNull, TypeError> {
+        out.extend(self.hyphenated().to_string().as_bytes());
+        Ok(IsNull::No)
+    }
+}
+
+impl<'a> FromSql<'a> for Uuid {
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::UUID
+    }
+
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        Uuid::from_slice(raw).map_err(TypeError::invalid)
+    }
+
+    fn from_sql_text(_ty: &Type, raw: &'a [u8]) -> Result<Self, TypeError> {
+        Uuid::try_parse_ascii(raw).map_err(TypeError::invalid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::decode;
+    use super::*;
+
+    #[test]
+    fn test_uuid() {
+        let id = Uuid::parse_str("a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11").unwrap();
+        let mut out = vec![];
+        id.to_sql(&Type::UUID, &mut out).unwrap();
+        assert_eq!(id.as_bytes(), &out[..]);
+        assert_eq!(id, decode::<Uuid>(&Type::UUID, true, Some(&out)).unwrap());
+        assert_eq!(
+            id,
+            decode::<Uuid>(
+                &Type::UUID,
+                false,
+                Some(b"a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11")
+            )
+            .unwrap()
+        );
+        assert!(decode::<Uuid>(&Type::UUID, true, Some(&out[1..])).is_err());
+    }
+}